@@ -1,14 +1,19 @@
 #![no_main]
 #![no_std]
+// Views are passed to the display task by value and spawn returns them back
+// when its queue is full
+#![allow(clippy::result_large_err)]
 
 use proton_eurorack as _; // global logger + panicking-behavior
 
 #[rtic::app(device = stm32h7xx_hal::pac, peripherals = true, dispatchers = [EXTI0, EXTI1, EXTI2])]
 mod app {
+    use core::fmt::Write as _;
     use core::mem::MaybeUninit;
 
     use fugit::ExtU64;
     use heapless::spsc::{Consumer, Producer, Queue};
+    use heapless::String;
     use systick_monotonic::Systick;
 
     use daisy::led::LedUser;
@@ -16,20 +21,23 @@ mod app {
     use hal::pac::{ADC1, ADC2};
     use stm32h7xx_hal as hal;
 
-    #[cfg(feature = "traky")]
-    use proton_instruments_traky::Instrument;
-
     use proton_control::input_snapshot::InputSnapshot;
     use proton_eurorack::system::audio::{Audio, SAMPLE_RATE};
     use proton_eurorack::system::display::Display;
     use proton_eurorack::system::randomizer::Randomizer;
+    use proton_eurorack::system::sdmmc::SDMMCBlockDevice;
     use proton_eurorack::system::System;
-    use proton_instruments_interface::{Instrument as _, MemoryManager};
+    use proton_instruments_interface::settings;
+    use proton_instruments_interface::{Instrument as _, MemoryManager, StorageTask as _};
+    use proton_instruments_registry::{Instrument, NAMES as INSTRUMENTS};
     use proton_ui::action::Action as InputAction;
     use proton_ui::display::draw as draw_view_on_display;
+    use proton_ui::notification;
+    use proton_ui::presets::SLOTS;
     use proton_ui::reaction::Reaction as InputReaction;
     use proton_ui::reducer;
-    use proton_ui::state::State;
+    use proton_ui::snapshot::{Autosave, Snapshot};
+    use proton_ui::state::{Attribute, Menu, State, Value, ValuePreset, ValueSelect};
     use proton_ui::view::View;
 
     type UserInput = proton_ui::input::Input<
@@ -57,27 +65,53 @@ mod app {
         proton_eurorack::system::cv_output::CvOutput2,
     >;
 
+    // Name of the file in the root of the SD card keeping the instrument
+    // selected in the boot menu.
+    const BOOT_FILE: &str = "BOOT.TXT";
+    // Number of milliseconds without any change before the state gets saved.
+    const AUTOSAVE_DELAY: u32 = 2000;
+    // Recalling a preset passes reactions of all attributes at once.
+    const REACTIONS_QUEUE: usize = 128;
+    // Number of cycles of the 480 MHz core clock in a millisecond.
+    const CYCLES_PER_MS: u32 = 480_000;
+
+    // Files in the root of the SD card are named after the instrument, so
+    // values of one instrument are never restored into another. The state
+    // is kept in e.g. `TRAKY.TXT` and preset slots in `TRAKY1.TXT` and on.
+    type FileName = String<12>;
+
     #[monotonic(binds = SysTick, default = true)]
     type Mono = Systick<1000>; // 1 kHz / 1 ms granularity
 
     #[shared]
-    struct Shared {}
+    struct Shared {
+        instrument: Instrument,
+        snapshot: Option<Snapshot>,
+        preset_to_save: Option<(usize, Snapshot)>,
+        preset_to_recall: Option<usize>,
+        recalled_preset: Option<Snapshot>,
+        cv: [f32; 5],
+        double_click: bool,
+    }
 
     #[local]
     struct Local {
         audio: Audio,
         randomizer: Randomizer,
-        instrument: Instrument,
+        sd: SDMMCBlockDevice,
+        state_file: FileName,
+        preset_files: [FileName; SLOTS],
         led: LedUser,
         user_input: UserInput,
         control_input: ControlInput,
-        _control_output: ControlOutput,
+        control_output: ControlOutput,
         display: Display,
         state: State,
+        autosave: Autosave,
         input_actions_producer: Producer<'static, InputAction, 6>,
         input_actions_consumer: Consumer<'static, InputAction, 6>,
-        input_reactions_producer: Producer<'static, InputReaction, 6>,
-        input_reactions_consumer: Consumer<'static, InputReaction, 6>,
+        input_reactions_producer: Producer<'static, InputReaction, REACTIONS_QUEUE>,
+        input_reactions_consumer: Consumer<'static, InputReaction, REACTIONS_QUEUE>,
         control_input_producer: Producer<'static, InputSnapshot, 6>,
         control_input_consumer: Consumer<'static, InputSnapshot, 6>,
     }
@@ -85,7 +119,7 @@ mod app {
     #[init(
         local = [
             input_actions_queue: Queue<InputAction, 6> = Queue::new(),
-            input_reactions_queue: Queue<InputReaction, 6> = Queue::new(),
+            input_reactions_queue: Queue<InputReaction, REACTIONS_QUEUE> = Queue::new(),
             control_input_queue: Queue<InputSnapshot, 6> = Queue::new(),
         ]
     )]
@@ -99,7 +133,7 @@ mod app {
 
         let system = System::init(cx.core, cx.device);
 
-        let display = system.display;
+        let mut display = system.display;
         let led = system.led;
         let mono = system.mono;
         let sdram = system.sdram;
@@ -107,7 +141,21 @@ mod app {
         let mut audio = system.audio;
         audio.spawn();
 
-        let user_input = UserInput::new(system.button, system.rotary);
+        let mut sd = system.sdmmc.sdmmc_block_device();
+
+        // Holding the encoder while powering up opens the boot menu. It is
+        // entered only once released, so the hold does not confirm it
+        let boot_menu_requested = system.button.active_no_filter();
+        while system.button.active_no_filter() {
+            cortex_m::asm::delay(CYCLES_PER_MS);
+        }
+        let mut user_input = UserInput::new(system.button, system.rotary);
+        let instrument_name = if boot_menu_requested {
+            select_instrument(&mut user_input, &mut display, &mut sd)
+        } else {
+            load_instrument_name(&mut sd)
+        };
+        defmt::info!("Selected instrument {}", instrument_name);
 
         let control_input = ControlInput::new(
             system.adc_1,
@@ -128,7 +176,7 @@ mod app {
             system.cv_output_2,
         );
 
-        let instrument = {
+        let mut instrument = {
             let mut memory_manager = {
                 let ram_slice = unsafe {
                     let ram_items = sdram.size() / core::mem::size_of::<MaybeUninit<u32>>();
@@ -137,13 +185,30 @@ mod app {
                 };
                 MemoryManager::from(ram_slice)
             };
-            Instrument::new(
-                SAMPLE_RATE,
-                &mut memory_manager,
-                &mut system.sdmmc.sdmmc_block_device(),
-            )
+            Instrument::with_name(instrument_name, SAMPLE_RATE, &mut memory_manager, &mut sd)
         };
-        let state = instrument.state();
+        let state_file = instrument_file(instrument_name, "");
+        let preset_files = core::array::from_fn(|slot| instrument_file(instrument_name, slot + 1));
+
+        let mut state = instrument
+            .state()
+            .with_attributes(&[Attribute::new("preset").with_value_preset(ValuePreset::new())])
+            .unwrap();
+        for (used, name) in state.presets.used.iter_mut().zip(&preset_files) {
+            *used = matches!(settings::load(&mut sd, name), Ok(Some(_)));
+        }
+
+        // Restored values are passed to the instrument as if the user set them
+        match settings::load(&mut sd, &state_file) {
+            Ok(Some(snapshot)) => {
+                for reaction in snapshot.restore(&mut state) {
+                    instrument.execute(reaction.into());
+                }
+            }
+            Ok(None) => (),
+            Err(_) => defmt::warn!("Failed to load state"),
+        }
+
         #[allow(clippy::needless_borrow)] // It's not needless, it fails without it
         let view: View = (&state).into();
 
@@ -152,19 +217,31 @@ mod app {
         read_user_controls::spawn().unwrap();
         read_control_input::spawn().unwrap();
         update_state::spawn().unwrap();
+        handle_storage::spawn().unwrap();
 
         (
-            Shared {},
+            Shared {
+                instrument,
+                snapshot: None,
+                preset_to_save: None,
+                preset_to_recall: None,
+                recalled_preset: None,
+                cv: [0.0; 5],
+                double_click: true,
+            },
             Local {
                 audio,
                 randomizer,
-                instrument,
+                sd,
+                state_file,
+                preset_files,
                 led,
                 user_input,
                 control_input,
-                _control_output: control_output,
+                control_output,
                 display,
                 state,
+                autosave: Autosave::new(AUTOSAVE_DELAY),
                 input_actions_producer,
                 input_actions_consumer,
                 input_reactions_producer,
@@ -176,35 +253,88 @@ mod app {
         )
     }
 
-    #[task(binds = DMA1_STR1, local = [input_reactions_consumer, control_input_consumer, randomizer, instrument, audio], priority = 4)]
-    fn handle_dsp(cx: handle_dsp::Context) {
-        use core::convert::TryInto;
-
+    #[task(binds = DMA1_STR1, shared = [instrument], local = [input_reactions_consumer, control_input_consumer, control_output, randomizer, audio], priority = 4)]
+    fn handle_dsp(mut cx: handle_dsp::Context) {
         let input_reactions_consumer = cx.local.input_reactions_consumer;
         let control_input_consumer = cx.local.control_input_consumer;
-        let instrument = cx.local.instrument;
+        let control_output = cx.local.control_output;
         let randomizer = cx.local.randomizer;
         let audio = cx.local.audio;
 
-        while let Some(control_snapshot) = control_input_consumer.dequeue() {
-            instrument.update_control(control_snapshot);
+        cx.shared.instrument.lock(|instrument| {
+            while let Some(control_snapshot) = control_input_consumer.dequeue() {
+                instrument.update_control(control_snapshot);
+            }
+
+            while let Some(reaction) = input_reactions_consumer.dequeue() {
+                instrument.execute(reaction.into());
+            }
+
+            audio.update_buffer(|buffer| {
+                instrument.process(&mut buffer[..], randomizer);
+            });
+
+            control_output.apply(instrument.control_output());
+        });
+    }
+
+    // Storage runs on the lowest priority, so a slow SD card access cannot
+    // starve the user interface.
+    #[task(shared = [instrument, snapshot, preset_to_save, preset_to_recall, recalled_preset], local = [sd, state_file, preset_files], priority = 1)]
+    fn handle_storage(mut cx: handle_storage::Context) {
+        let sd = cx.local.sd;
+        let state_file = cx.local.state_file;
+        let preset_files = cx.local.preset_files;
+
+        // The lock is held only while passing the task around. The SD card
+        // access itself does not block the audio interrupt.
+        let task = cx
+            .shared
+            .instrument
+            .lock(|instrument| instrument.storage_task());
+        if let Some(mut task) = task {
+            task.run(sd);
+            cx.shared
+                .instrument
+                .lock(|instrument| instrument.finish_storage_task(task));
+        }
+
+        let snapshot = cx.shared.snapshot.lock(|snapshot| snapshot.take());
+        if let Some(snapshot) = snapshot {
+            if settings::save(sd, state_file, &snapshot).is_err() {
+                defmt::warn!("Failed to save state");
+            }
         }
 
-        while let Some(action) = input_reactions_consumer.dequeue() {
-            let reaction = action.try_into();
-            instrument.execute(reaction.unwrap());
+        let preset = cx.shared.preset_to_save.lock(|preset| preset.take());
+        if let Some((slot, snapshot)) = preset {
+            if settings::save(sd, &preset_files[slot], &snapshot).is_err() {
+                defmt::warn!("Failed to save preset");
+            }
         }
 
-        audio.update_buffer(|buffer| {
-            instrument.process(&mut buffer[..], randomizer);
-        });
+        let slot = cx.shared.preset_to_recall.lock(|slot| slot.take());
+        if let Some(slot) = slot {
+            match settings::load(sd, &preset_files[slot]) {
+                Ok(Some(snapshot)) => cx
+                    .shared
+                    .recalled_preset
+                    .lock(|recalled| *recalled = Some(snapshot)),
+                _ => defmt::warn!("Failed to load preset"),
+            }
+        }
+
+        handle_storage::spawn_after(1.millis()).unwrap();
     }
 
-    #[task(local = [user_input, input_actions_producer], priority = 3)]
-    fn read_user_controls(cx: read_user_controls::Context) {
+    #[task(shared = [double_click], local = [user_input, input_actions_producer], priority = 3)]
+    fn read_user_controls(mut cx: read_user_controls::Context) {
         let user_input = cx.local.user_input;
         let input_actions_producer = cx.local.input_actions_producer;
 
+        let double_click = cx.shared.double_click.lock(|double_click| *double_click);
+        user_input.set_double_click(double_click);
+
         for action in user_input.process() {
             let _ = input_actions_producer.enqueue(action);
         }
@@ -212,37 +342,100 @@ mod app {
         read_user_controls::spawn_after(1.millis()).unwrap();
     }
 
-    #[task(local = [control_input, control_input_producer], priority = 2)]
-    fn read_control_input(cx: read_control_input::Context) {
+    #[task(shared = [cv], local = [control_input, control_input_producer], priority = 2)]
+    fn read_control_input(mut cx: read_control_input::Context) {
         let control_input = cx.local.control_input;
         let control_input_producer = cx.local.control_input_producer;
 
-        control_input_producer
-            .enqueue(control_input.update())
-            .ok()
-            .unwrap();
+        let snapshot = control_input.update();
+        cx.shared.cv.lock(|cv| *cv = snapshot.cv.map(|cv| cv.value));
+        control_input_producer.enqueue(snapshot).ok().unwrap();
 
         read_control_input::spawn_after(1.millis()).unwrap();
     }
 
-    #[task(local = [input_actions_consumer, input_reactions_producer, state])]
-    fn update_state(cx: update_state::Context) {
+    #[task(shared = [instrument, snapshot, preset_to_save, preset_to_recall, recalled_preset, cv, double_click], local = [input_actions_consumer, input_reactions_producer, state, autosave], priority = 2)]
+    fn update_state(mut cx: update_state::Context) {
         let input_actions_consumer = cx.local.input_actions_consumer;
         let input_reactions_producer = cx.local.input_reactions_producer;
+        let autosave = cx.local.autosave;
 
         let state = cx.local.state;
 
+        // A burst of input may outrun the instrument, the excess is dropped
+        // rather than taking the module down
+        let mut pass_to_instrument = |reaction| {
+            if input_reactions_producer.enqueue(reaction).is_err() {
+                defmt::warn!("Dropped reaction, the queue is full");
+            }
+        };
+
+        // Presets are handled by the firmware, the instrument receives only
+        // the values they restore
         while let Some(action) = input_actions_consumer.dequeue() {
-            let reaction = reducer::reduce(action, state);
-            if let Some(reaction) = reaction {
-                #[allow(clippy::ok_expect)]
-                input_reactions_producer
-                    .enqueue(reaction)
-                    .ok()
-                    .expect("the queue is full");
+            match reducer::reduce(action, state) {
+                Some(InputReaction::SavePreset(slot)) => match Snapshot::capture(state) {
+                    Ok(snapshot) => cx
+                        .shared
+                        .preset_to_save
+                        .lock(|preset| *preset = Some((slot, snapshot))),
+                    Err(_) => defmt::warn!("Failed to capture preset"),
+                },
+                Some(InputReaction::RecallPreset(slot)) => cx
+                    .shared
+                    .preset_to_recall
+                    .lock(|recall| *recall = Some(slot)),
+                Some(reaction) => {
+                    autosave.react(&reaction, state);
+                    pass_to_instrument(reaction);
+                }
+                None => (),
+            }
+        }
+
+        let cv = cx.shared.cv.lock(|cv| *cv);
+        if let Some(slot) = state.presets.follow_cv(&cv) {
+            for attribute in state.attributes.iter_mut() {
+                if let Value::Preset(value_preset) = &mut attribute.value {
+                    value_preset.slot = Some(slot);
+                }
             }
+            cx.shared
+                .preset_to_recall
+                .lock(|recall| *recall = Some(slot));
         }
 
+        // Changes made by the instrument are applied while holding the lock,
+        // they only touch values in place
+        cx.shared.instrument.lock(|instrument| {
+            while let Some(notification) = instrument.notification() {
+                notification::apply(notification, state);
+            }
+        });
+
+        let recalled = cx.shared.recalled_preset.lock(|recalled| recalled.take());
+        // Recalled values replace the current ones, so they get saved too
+        if let Some(snapshot) = recalled {
+            for reaction in snapshot.restore(state) {
+                pass_to_instrument(reaction);
+            }
+            autosave.touch();
+        }
+
+        // The snapshot is handed over to the storage task, so the state is
+        // not blocked by the SD card
+        if autosave.tick() {
+            match Snapshot::capture(state) {
+                Ok(snapshot) => cx.shared.snapshot.lock(|s| *s = Some(snapshot)),
+                Err(_) => defmt::warn!("Failed to capture state"),
+            }
+        }
+
+        // Clicks are delayed for a possible double click only where it has
+        // a meaning of its own
+        let double_click = reducer::accepts_double_click(state);
+        cx.shared.double_click.lock(|shared| *shared = double_click);
+
         #[allow(clippy::needless_borrow)] // It's not needless, it fails without it
         let view = (&*state).into();
         update_display::spawn(view).ok().unwrap();
@@ -250,13 +443,87 @@ mod app {
         update_state::spawn_after(1.millis()).unwrap();
     }
 
-    #[task(local = [display])]
+    #[task(local = [display], priority = 2)]
     fn update_display(cx: update_display::Context, view: View) {
         let display = cx.local.display;
         draw_view_on_display(display, &view).unwrap();
         display.flush().unwrap();
     }
 
+    fn instrument_file(instrument_name: &str, suffix: impl core::fmt::Display) -> FileName {
+        let mut file_name = FileName::new();
+        for c in instrument_name.chars() {
+            file_name.push(c.to_ascii_uppercase()).unwrap();
+        }
+        write!(file_name, "{}.TXT", suffix).unwrap();
+        file_name
+    }
+
+    // The boot menu has a single attribute selecting the instrument. The
+    // choice is kept as a snapshot of its state.
+    fn load_boot_menu_state(sd: &mut SDMMCBlockDevice) -> State {
+        let mut state = State::new("Instrument")
+            .with_attributes(&[Attribute::new("instrument")
+                .with_value_select(ValueSelect::new(INSTRUMENTS).unwrap())])
+            .unwrap();
+        match settings::load(sd, BOOT_FILE) {
+            Ok(Some(snapshot)) => {
+                snapshot.restore(&mut state);
+            }
+            Ok(None) => (),
+            Err(_) => defmt::warn!("Failed to load selected instrument"),
+        }
+        state
+    }
+
+    fn load_instrument_name(sd: &mut SDMMCBlockDevice) -> &'static str {
+        selected_instrument_name(&load_boot_menu_state(sd))
+    }
+
+    // The menu runs before any of the tasks, polling the encoder in a busy
+    // loop. The previously selected instrument is offered first, turning the
+    // encoder selects another and clicking confirms the choice. Other
+    // gestures are ignored.
+    fn select_instrument(
+        user_input: &mut UserInput,
+        display: &mut Display,
+        sd: &mut SDMMCBlockDevice,
+    ) -> &'static str {
+        let mut state = load_boot_menu_state(sd);
+        state.menu = Menu::Sub;
+        user_input.set_double_click(false);
+
+        let mut redraw = true;
+        while matches!(state.menu, Menu::Sub) {
+            if redraw {
+                #[allow(clippy::needless_borrow)] // It's not needless, it fails without it
+                let view: View = (&state).into();
+                draw_view_on_display(display, &view).unwrap();
+                display.flush().unwrap();
+            }
+            redraw = false;
+            for action in user_input.process().into_iter().filter(|a| a.is_plain()) {
+                reducer::reduce(action, &mut state);
+                redraw = true;
+            }
+            cortex_m::asm::delay(CYCLES_PER_MS);
+        }
+
+        match Snapshot::capture(&state) {
+            Ok(snapshot) if settings::save(sd, BOOT_FILE, &snapshot).is_ok() => (),
+            _ => defmt::warn!("Failed to save selected instrument"),
+        }
+
+        selected_instrument_name(&state)
+    }
+
+    fn selected_instrument_name(state: &State) -> &'static str {
+        match &state.attributes[0].value {
+            Value::Select(value_select) => value_select.available[value_select.selected],
+            _ => unreachable!(),
+        }
+    }
+
     #[task(local = [led])]
     fn toggle_indicator(cx: toggle_indicator::Context) {
         cx.local.led.toggle();
@@ -1,5 +1,6 @@
 use hal::device::SDMMC1;
-use hal::sdmmc::{SdCard, Sdmmc};
+use hal::sdmmc::{SdCard, Sdmmc, SdmmcBlockDevice};
 use stm32h7xx_hal as hal;
 
 pub type SDMMC = Sdmmc<SDMMC1, SdCard>;
+pub type SDMMCBlockDevice = SdmmcBlockDevice<SDMMC>;
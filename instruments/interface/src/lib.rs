@@ -1,13 +1,19 @@
 #![no_std]
 
+pub mod sd;
+pub mod settings;
+
 use embedded_sdmmc::blockdevice::BlockDevice;
 
 use proton_control::input_snapshot::InputSnapshot;
+use proton_control::output_request::OutputRequest;
+use proton_ui::notification::Notification;
 use proton_ui::reaction::Reaction;
 use proton_ui::state::State;
 
 pub trait Instrument {
     type Command: TryFrom<Reaction>;
+    type StorageTask: StorageTask;
     fn new(
         sample_rate: u32,
         memory_manager: &mut MemoryManager,
@@ -17,6 +23,21 @@ pub trait Instrument {
     fn process(&mut self, buffer: &mut [(f32, f32)], randomizer: &mut impl Rand);
     fn execute(&mut self, command: Self::Command);
     fn update_control(&mut self, snapshot: InputSnapshot);
+    fn control_output(&self) -> OutputRequest;
+    fn storage_task(&mut self) -> Option<Self::StorageTask>;
+    fn finish_storage_task(&mut self, task: Self::StorageTask);
+    /// Changes the instrument made on its own, to be shown in the UI. It is
+    /// polled until it returns `None`.
+    fn notification(&mut self) -> Option<Notification>;
+}
+
+/// Work requiring access to the SD card.
+///
+/// The instrument runs in the audio interrupt, where it cannot afford to wait
+/// for the SD card. Instead, it hands over a task that gets executed in a
+/// low-priority routine and then returned back via `finish_storage_task`.
+pub trait StorageTask {
+    fn run(&mut self, sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>);
 }
 
 pub use sirena::memory_manager::MemoryManager;
@@ -0,0 +1,36 @@
+//! Pieces of the SD card access shared by the firmware and instruments.
+
+use embedded_sdmmc::Error;
+
+/// Reason the FAT volume of the card could not be opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VolumeError {
+    /// The card is missing or cannot be read.
+    NoCard,
+    /// The card is readable, but it has no FAT volume.
+    NoVolume,
+}
+
+/// Tell a card that cannot be read apart from one without a FAT volume.
+pub fn volume_error<E: core::fmt::Debug>(error: Error<E>) -> VolumeError {
+    match error {
+        Error::DeviceError(_) => VolumeError::NoCard,
+        _ => VolumeError::NoVolume,
+    }
+}
+
+pub struct TimeSource;
+
+// There is no RTC to provide time, files are stamped with the epoch.
+impl embedded_sdmmc::TimeSource for TimeSource {
+    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
+        embedded_sdmmc::Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Snapshot of the UI state kept in a file in the root of the SD card.
+//!
+//! It is shared by the firmware of all instruments, so the state can be
+//! restored on boot no matter what the instrument stores on its own.
+
+use embedded_sdmmc::blockdevice::BlockDevice;
+use embedded_sdmmc::{Controller, Error, Mode, VolumeIdx};
+use proton_ui::snapshot::{Snapshot, SNAPSHOT_SIZE};
+
+use crate::sd::{volume_error, TimeSource, VolumeError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsError {
+    NoCard,
+    NoVolume,
+    FileSystem,
+    Read,
+    Write,
+    Invalid,
+}
+
+impl From<VolumeError> for SettingsError {
+    fn from(error: VolumeError) -> Self {
+        match error {
+            VolumeError::NoCard => Self::NoCard,
+            VolumeError::NoVolume => Self::NoVolume,
+        }
+    }
+}
+
+/// Read the snapshot from the file with the given name. A missing file is
+/// not an error, there is just nothing to restore.
+pub fn load(
+    sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    name: &str,
+) -> Result<Option<Snapshot>, SettingsError> {
+    let mut fat = Controller::new(sd, TimeSource);
+    let mut volume = fat.get_volume(VolumeIdx(0)).map_err(volume_error)?;
+    let directory = fat
+        .open_root_dir(&volume)
+        .map_err(|_| SettingsError::FileSystem)?;
+    let file = fat.open_file_in_dir(&mut volume, &directory, name, Mode::ReadOnly);
+    fat.close_dir(&volume, directory);
+    let mut file = match file {
+        Ok(file) => file,
+        Err(Error::FileNotFound) => return Ok(None),
+        Err(_) => return Err(SettingsError::FileSystem),
+    };
+
+    let mut bytes = [0; SNAPSHOT_SIZE];
+    let read = fat.read(&volume, &mut file, &mut bytes);
+    fat.close_file(&volume, file)
+        .map_err(|_| SettingsError::FileSystem)?;
+    let read = read.map_err(|_| SettingsError::Read)?;
+    Snapshot::from_bytes(&bytes[..read])
+        .map(Some)
+        .map_err(|_| SettingsError::Invalid)
+}
+
+/// Replace the content of the file with the given name by the snapshot.
+pub fn save(
+    sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    name: &str,
+    snapshot: &Snapshot,
+) -> Result<(), SettingsError> {
+    let mut fat = Controller::new(sd, TimeSource);
+    let mut volume = fat.get_volume(VolumeIdx(0)).map_err(volume_error)?;
+    let directory = fat
+        .open_root_dir(&volume)
+        .map_err(|_| SettingsError::FileSystem)?;
+    let file = fat.open_file_in_dir(
+        &mut volume,
+        &directory,
+        name,
+        Mode::ReadWriteCreateOrTruncate,
+    );
+    fat.close_dir(&volume, directory);
+    let mut file = file.map_err(|_| SettingsError::Write)?;
+
+    let bytes = snapshot.as_bytes();
+    let written = fat.write(&mut volume, &mut file, bytes);
+    fat.close_file(&volume, file)
+        .map_err(|_| SettingsError::FileSystem)?;
+    match written {
+        Ok(written) if written == bytes.len() => Ok(()),
+        _ => Err(SettingsError::Write),
+    }
+}
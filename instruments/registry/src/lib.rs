@@ -0,0 +1,144 @@
+//! All instruments available in the firmware, selectable at runtime.
+//!
+//! The firmware works with a single type implementing the `Instrument`
+//! trait. This enum wraps all the instruments and dispatches calls to the one
+//! selected during boot. Adding an instrument means adding a variant here and
+//! its name to `NAMES`.
+//!
+//! Names are used as prefixes of files the firmware keeps on the SD card, so
+//! they must not be longer than 7 characters.
+
+#![no_std]
+
+use core::convert::TryInto;
+
+use embedded_sdmmc::blockdevice::BlockDevice;
+use proton_control::input_snapshot::InputSnapshot;
+use proton_control::output_request::OutputRequest;
+use proton_instruments_interface::{
+    Instrument as InstrumentTrait, MemoryManager, Rand, StorageTask as StorageTaskTrait,
+};
+use proton_ui::notification::Notification;
+use proton_ui::reaction::Reaction;
+use proton_ui::state::State;
+
+/// Names of all the instruments. The first one is the default.
+pub const NAMES: &[&str] = &[TRAKY];
+
+const TRAKY: &str = "traky";
+
+pub enum Instrument {
+    Traky(proton_instruments_traky::Instrument),
+}
+
+pub enum StorageTask {
+    Traky(proton_instruments_traky::StorageTask),
+}
+
+/// Reactions are passed to the selected instrument, which converts them to
+/// its own commands. Those it does not understand are ignored.
+pub struct Command(Reaction);
+
+impl Instrument {
+    /// Initialize the instrument of the given name, falling back to the
+    /// default one if there is no such instrument.
+    pub fn with_name(
+        name: &str,
+        sample_rate: u32,
+        memory_manager: &mut MemoryManager,
+        sdmmc: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    ) -> Self {
+        match name {
+            TRAKY => Self::Traky(proton_instruments_traky::Instrument::new(
+                sample_rate,
+                memory_manager,
+                sdmmc,
+            )),
+            _ => Self::new(sample_rate, memory_manager, sdmmc),
+        }
+    }
+}
+
+impl InstrumentTrait for Instrument {
+    type Command = Command;
+    type StorageTask = StorageTask;
+
+    fn new(
+        sample_rate: u32,
+        memory_manager: &mut MemoryManager,
+        sdmmc: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    ) -> Self {
+        Self::with_name(NAMES[0], sample_rate, memory_manager, sdmmc)
+    }
+
+    fn state(&self) -> State {
+        match self {
+            Self::Traky(instrument) => instrument.state(),
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [(f32, f32)], randomizer: &mut impl Rand) {
+        match self {
+            Self::Traky(instrument) => instrument.process(buffer, randomizer),
+        }
+    }
+
+    fn execute(&mut self, command: Command) {
+        let Command(reaction) = command;
+        match self {
+            Self::Traky(instrument) => {
+                if let Ok(command) = reaction.try_into() {
+                    instrument.execute(command);
+                }
+            }
+        }
+    }
+
+    fn update_control(&mut self, snapshot: InputSnapshot) {
+        match self {
+            Self::Traky(instrument) => instrument.update_control(snapshot),
+        }
+    }
+
+    fn control_output(&self) -> OutputRequest {
+        match self {
+            Self::Traky(instrument) => instrument.control_output(),
+        }
+    }
+
+    fn storage_task(&mut self) -> Option<StorageTask> {
+        match self {
+            Self::Traky(instrument) => instrument.storage_task().map(StorageTask::Traky),
+        }
+    }
+
+    // Tasks are returned to the instrument that handed them over, the
+    // instrument cannot change while a task is running.
+    fn finish_storage_task(&mut self, task: StorageTask) {
+        match (self, task) {
+            (Self::Traky(instrument), StorageTask::Traky(task)) => {
+                instrument.finish_storage_task(task)
+            }
+        }
+    }
+
+    fn notification(&mut self) -> Option<Notification> {
+        match self {
+            Self::Traky(instrument) => instrument.notification(),
+        }
+    }
+}
+
+impl StorageTaskTrait for StorageTask {
+    fn run(&mut self, sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>) {
+        match self {
+            Self::Traky(task) => task.run(sd),
+        }
+    }
+}
+
+impl From<Reaction> for Command {
+    fn from(other: Reaction) -> Self {
+        Self(other)
+    }
+}
@@ -0,0 +1,108 @@
+//! Measure the CPU cost of processing a block of audio by the instrument.
+//!
+//! The instrument is driven through the same reactions the UI sends. There
+//! is no SD card, so the tape stays silent. That does not change the amount
+//! of work done by the resampler, nor by the grains.
+
+use std::convert::TryFrom;
+use std::mem::MaybeUninit;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use embedded_sdmmc::blockdevice::{Block, BlockCount, BlockDevice, BlockIdx};
+use proton_instruments_interface::{Instrument as _, MemoryManager, Rand};
+use proton_instruments_traky::{Command, Instrument};
+use proton_ui::reaction::Reaction;
+
+const SAMPLE_RATE: u32 = 48_000;
+const BLOCK_LENGTH: usize = 32;
+const MEMORY_SIZE: usize = 64 * 1024 * 1024;
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
+struct MissingCard;
+
+impl BlockDevice for MissingCard {
+    type Error = ();
+
+    fn read(&self, _: &mut [Block], _: BlockIdx, _: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn write(&self, _: &[Block], _: BlockIdx) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, ()> {
+        Err(())
+    }
+}
+
+struct Randomizer(u16);
+
+impl Rand for Randomizer {
+    fn generate(&mut self) -> u16 {
+        self.0 = self.0.wrapping_mul(25_173).wrapping_add(13_849);
+        self.0
+    }
+}
+
+fn instrument() -> Instrument {
+    let words = MEMORY_SIZE / core::mem::size_of::<MaybeUninit<u32>>();
+    let memory = vec![MaybeUninit::uninit(); words].into_boxed_slice();
+    let mut memory_manager = MemoryManager::from(Box::leak(memory));
+    Instrument::new(SAMPLE_RATE, &mut memory_manager, &mut MissingCard)
+}
+
+fn react(instrument: &mut Instrument, reaction: Reaction) {
+    instrument.execute(Command::try_from(reaction).unwrap());
+}
+
+fn bench(c: &mut Criterion) {
+    let mut instrument = instrument();
+    let mut randomizer = Randomizer(0);
+    let mut buffer = [(0.0, 0.0); BLOCK_LENGTH];
+
+    // With the pot at zero, the tape runs at the top of the speed range
+    react(&mut instrument, Reaction::SelectValue("speed", "0-2x"));
+    for interpolation in ["linear", "hermite", "sinc"] {
+        react(
+            &mut instrument,
+            Reaction::SelectValue("resampling", interpolation),
+        );
+        c.bench_function(&format!("instrument looper {interpolation}"), |b| {
+            b.iter(|| instrument.process(black_box(&mut buffer), &mut randomizer))
+        });
+    }
+
+    // Long dense grains keep all of them playing at once
+    react(
+        &mut instrument,
+        Reaction::SelectValue("resampling", "hermite"),
+    );
+    react(&mut instrument, Reaction::SelectValue("mode", "granular"));
+    react(&mut instrument, Reaction::SetValue("size", 1.0));
+    react(&mut instrument, Reaction::SetValue("density", 1.0));
+    react(&mut instrument, Reaction::SetValue("jitter", 1.0));
+    for window in ["hann", "triangle", "trapezoid"] {
+        react(&mut instrument, Reaction::SelectValue("window", window));
+        c.bench_function(&format!("instrument granular {window}"), |b| {
+            b.iter(|| instrument.process(black_box(&mut buffer), &mut randomizer))
+        });
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,193 @@
+//! Tape loop passing by several playback heads.
+//!
+//! Incoming audio is continuously written to a circular buffer in memory.
+//! Playback heads read it back at their offsets behind the record head and
+//! their mix is fed back to the recording. Heads are placed relative to the
+//! length of the loop, where zero turns the head off.
+//!
+//! Changing the speed of the tape stretches the delay of all heads, just
+//! like on a real tape echo, so wow and flutter bend the pitch of echoes.
+
+use crate::smoother::Smoother;
+
+pub const HEADS: usize = 3;
+
+// Feedback is kept below unity, so echoes always die out eventually.
+const MAX_FEEDBACK: f32 = 0.95;
+
+pub struct Delay<'a> {
+    buffer: &'a mut [(f32, f32)],
+    position: usize,
+    heads: [Smoother; HEADS],
+    levels: [Smoother; HEADS],
+    enabled: [bool; HEADS],
+    feedback: Smoother,
+}
+
+impl<'a> Delay<'a> {
+    pub fn new(buffer: &'a mut [(f32, f32)], sample_rate: u32) -> Self {
+        buffer.fill((0.0, 0.0));
+        Self {
+            buffer,
+            position: 0,
+            heads: [(); HEADS].map(|_| Smoother::new(0.0, sample_rate)),
+            levels: [(); HEADS].map(|_| Smoother::new(0.0, sample_rate)),
+            enabled: [false; HEADS],
+            feedback: Smoother::new(0.0, sample_rate),
+        }
+    }
+
+    /// Place the head to the given offset between 0.0 and 1.0 of the loop.
+    /// Moving a head makes it glide to the new position. A disabled head
+    /// fades out where it is and fades in right at its new position.
+    pub fn set_head(&mut self, index: usize, offset: f32) {
+        let enabled = offset > 0.0;
+        if enabled && !self.enabled[index] {
+            self.heads[index].reset(offset);
+        } else if enabled {
+            self.heads[index].set(offset);
+        }
+        self.levels[index].set(if enabled { 1.0 } else { 0.0 });
+        self.enabled[index] = enabled;
+    }
+
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback.set(feedback * MAX_FEEDBACK);
+    }
+
+    /// Record the input and return the mix of all heads. The recorded
+    /// feedback passes through the given function, so it can be colored
+    /// by the tape.
+    pub fn process(
+        &mut self,
+        input: (f32, f32),
+        speed: f32,
+        mut record: impl FnMut((f32, f32)) -> (f32, f32),
+    ) -> (f32, f32) {
+        let length = self.buffer.len();
+
+        let mut mix = (0.0, 0.0);
+        let mut levels = 0.0;
+        for (head, level) in self.heads.iter_mut().zip(self.levels.iter_mut()) {
+            let offset = head.next();
+            let level = level.next();
+            if level == 0.0 {
+                continue;
+            }
+            // Keep the head behind the record one, leaving space for
+            // interpolation
+            let delay = (offset * length as f32 / speed).clamp(1.0, (length - 2) as f32);
+            let frame = read(self.buffer, self.position, delay);
+            mix.0 += frame.0 * level;
+            mix.1 += frame.1 * level;
+            levels += level;
+        }
+        // Levels of heads are averaged, so enabling more heads does not
+        // make the delay louder
+        let gain = 1.0 / f32::max(levels, 1.0);
+        let mix = (mix.0 * gain, mix.1 * gain);
+
+        let feedback = self.feedback.next();
+        self.buffer[self.position] =
+            record((input.0 + mix.0 * feedback, input.1 + mix.1 * feedback));
+        self.position = (self.position + 1) % length;
+
+        mix
+    }
+}
+
+// Linear interpolation between frames recorded the given number of frames
+// before the position.
+fn read(buffer: &[(f32, f32)], position: usize, delay: f32) -> (f32, f32) {
+    let length = buffer.len();
+    let whole = delay as usize;
+    let fraction = delay - whole as f32;
+    let newer = buffer[(position + length - whole) % length];
+    let older = buffer[(position + length - whole - 1) % length];
+    (
+        newer.0 + (older.0 - newer.0) * fraction,
+        newer.1 + (older.1 - newer.1) * fraction,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 1_000;
+    const LENGTH: usize = 1_000;
+
+    fn impulse_response(delay: &mut Delay, frames: usize) -> [f32; 2 * LENGTH] {
+        let mut response = [0.0; 2 * LENGTH];
+        for (i, value) in response.iter_mut().enumerate().take(frames) {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            *value = delay.process((input, -input), 1.0, |x| x).0;
+        }
+        response
+    }
+
+    fn settle(delay: &mut Delay) {
+        for _ in 0..SAMPLE_RATE {
+            delay.process((0.0, 0.0), 1.0, |x| x);
+        }
+    }
+
+    #[test]
+    fn when_head_is_placed_it_echoes_input_after_its_offset() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut delay = Delay::new(&mut buffer, SAMPLE_RATE);
+        delay.set_head(0, 0.25);
+        settle(&mut delay);
+
+        let response = impulse_response(&mut delay, 2 * LENGTH);
+
+        assert!((response[250] - 1.0).abs() < 0.01);
+        let others: f32 = response.iter().map(|x| x.abs()).sum::<f32>() - response[250];
+        assert!(others < 0.01);
+    }
+
+    #[test]
+    fn when_feedback_is_set_echoes_repeat_and_decay() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut delay = Delay::new(&mut buffer, SAMPLE_RATE);
+        delay.set_head(0, 0.25);
+        delay.set_feedback(0.5);
+        settle(&mut delay);
+
+        let response = impulse_response(&mut delay, 2 * LENGTH);
+
+        assert!((response[250] - 1.0).abs() < 0.01);
+        assert!((response[500] - 0.5 * MAX_FEEDBACK).abs() < 0.01);
+        assert!(response[750] < response[500]);
+    }
+
+    #[test]
+    fn when_multiple_heads_are_enabled_their_echoes_are_averaged() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut delay = Delay::new(&mut buffer, SAMPLE_RATE);
+        delay.set_head(0, 0.1);
+        delay.set_head(1, 0.3);
+        settle(&mut delay);
+
+        let response = impulse_response(&mut delay, LENGTH);
+
+        assert!((response[100] - 0.5).abs() < 0.01);
+        assert!((response[300] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn when_speed_is_lowered_echoes_come_later() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut delay = Delay::new(&mut buffer, SAMPLE_RATE);
+        delay.set_head(0, 0.25);
+        settle(&mut delay);
+
+        let mut response = [0.0; LENGTH];
+        for (i, value) in response.iter_mut().enumerate() {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            *value = delay.process((input, input), 0.5, |x| x).0;
+        }
+
+        assert!((response[500] - 1.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,67 @@
+//! Interpret a CV input as a gate signal.
+//!
+//! Thresholds are apart from each other, so a noisy signal around them
+//! does not toggle the gate back and forth.
+
+const HIGH_THRESHOLD: f32 = 0.7;
+const LOW_THRESHOLD: f32 = 0.6;
+
+pub struct Gate {
+    high: bool,
+}
+
+impl Gate {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self { high: false }
+    }
+
+    /// Returns true if the gate just went high.
+    pub fn update(&mut self, value: f32) -> bool {
+        let was_high = self.high;
+        if value > HIGH_THRESHOLD {
+            self.high = true;
+        } else if value < LOW_THRESHOLD {
+            self.high = false;
+        }
+        self.high && !was_high
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.high
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_value_oscillates_between_thresholds_it_keeps_the_state() {
+        let mut gate = Gate::new();
+
+        gate.update(0.65);
+        assert!(!gate.is_high());
+
+        gate.update(0.8);
+        assert!(gate.is_high());
+
+        gate.update(0.65);
+        assert!(gate.is_high());
+
+        gate.update(0.1);
+        assert!(!gate.is_high());
+    }
+
+    #[test]
+    fn when_gate_goes_high_it_reports_rising_edge_once() {
+        let mut gate = Gate::new();
+
+        assert!(!gate.update(0.1));
+        assert!(gate.update(0.8));
+        assert!(!gate.update(0.8));
+        assert!(!gate.update(0.65));
+        assert!(!gate.update(0.1));
+        assert!(gate.update(0.9));
+    }
+}
@@ -0,0 +1,332 @@
+//! Cloud of short grains played from recently streamed audio.
+//!
+//! Samples are streamed from the SD card, so they cannot be accessed at
+//! random. Instead, the played sample is captured into a circular buffer in
+//! memory, and grains are scattered over its history. The position sets how
+//! far back from the capture the grains start. When the capture stops, the
+//! buffer freezes and grains keep playing what is in it.
+//!
+//! Grains are spawned by a scheduler with randomized intervals around the
+//! set density. Each grain gets its own pitch, randomized by the jitter,
+//! and is shaped by the selected window.
+
+use core::f32::consts::{LN_2, PI};
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+use heapless::Vec;
+use proton_instruments_interface::Rand;
+
+/// Maximum number of grains playing at once.
+pub const GRAINS: usize = 16;
+
+const MIN_SIZE: f32 = 0.01;
+const MAX_SIZE: f32 = 1.0;
+const MIN_DENSITY: f32 = 1.0;
+const MAX_DENSITY: f32 = 100.0;
+// Pitch of grains is randomized up to this many octaves up or down.
+const MAX_JITTER: f32 = 1.0;
+// Relative deviation of intervals between grains.
+const INTERVAL_SPREAD: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Hann,
+    Triangle,
+    Trapezoid,
+}
+
+impl Window {
+    // Amplitude at the given phase of the grain, between 0.0 and 1.0.
+    fn amplitude(self, phase: f32) -> f32 {
+        match self {
+            Window::Hann => 0.5 - 0.5 * (2.0 * PI * phase).cos(),
+            Window::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            Window::Trapezoid => (4.0 * phase).min(4.0 * (1.0 - phase)).min(1.0),
+        }
+    }
+}
+
+/// Length of grains in seconds for the size between 0.0 and 1.0.
+pub fn size_to_seconds(size: f32) -> f32 {
+    MIN_SIZE * (MAX_SIZE / MIN_SIZE).powf(size)
+}
+
+/// Number of grains per second for the density between 0.0 and 1.0.
+pub fn density_to_rate(density: f32) -> f32 {
+    MIN_DENSITY * (MAX_DENSITY / MIN_DENSITY).powf(density)
+}
+
+pub struct Granular<'a> {
+    buffer: &'a mut [(f32, f32)],
+    sample_rate: f32,
+    // Index where the following captured frame gets written.
+    head: usize,
+    grains: Vec<Grain, GRAINS>,
+    // Number of frames until the following grain is spawned.
+    countdown: f32,
+    position: f32,
+    size: f32,
+    density: f32,
+    jitter: f32,
+    window: Window,
+}
+
+struct Grain {
+    // Index within the buffer, moving by the step with every frame.
+    position: f32,
+    step: f32,
+    age: usize,
+    length: usize,
+}
+
+impl<'a> Granular<'a> {
+    pub fn new(buffer: &'a mut [(f32, f32)], sample_rate: u32) -> Self {
+        buffer.fill((0.0, 0.0));
+        Self {
+            buffer,
+            sample_rate: sample_rate as f32,
+            head: 0,
+            grains: Vec::new(),
+            countdown: 0.0,
+            position: 0.0,
+            size: 0.5,
+            density: 0.5,
+            jitter: 0.0,
+            window: Window::Hann,
+        }
+    }
+
+    /// Set how far back in the captured history the grains start, between
+    /// 0.0 for the most recent and 1.0 for the oldest.
+    pub fn set_position(&mut self, position: f32) {
+        self.position = position;
+    }
+
+    /// Set the length of grains, between 0.0 and 1.0.
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size;
+    }
+
+    /// Set the number of grains spawned per second, between 0.0 and 1.0.
+    pub fn set_density(&mut self, density: f32) {
+        self.density = density;
+    }
+
+    /// Set the randomization of grain pitch, between 0.0 and 1.0.
+    pub fn set_jitter(&mut self, jitter: f32) {
+        self.jitter = jitter;
+    }
+
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+    }
+
+    pub fn capture(&mut self, frame: (f32, f32)) {
+        self.buffer[self.head] = frame;
+        self.head = (self.head + 1) % self.buffer.len();
+    }
+
+    /// Mix of all playing grains for the following frame.
+    pub fn next_frame(&mut self, randomizer: &mut impl Rand) -> (f32, f32) {
+        let rate = density_to_rate(self.density);
+        let seconds = size_to_seconds(self.size);
+
+        self.countdown -= 1.0;
+        if self.countdown <= 0.0 {
+            let spread = INTERVAL_SPREAD * (2.0 * random(randomizer) - 1.0);
+            self.countdown += self.sample_rate / rate * (1.0 + spread);
+            self.spawn(seconds, randomizer);
+        }
+
+        let length = self.buffer.len();
+        let mut mix = (0.0, 0.0);
+        for grain in self.grains.iter_mut() {
+            let amplitude = self
+                .window
+                .amplitude(grain.age as f32 / grain.length as f32);
+            let frame = read(self.buffer, grain.position);
+            mix.0 += frame.0 * amplitude;
+            mix.1 += frame.1 * amplitude;
+            grain.position = (grain.position + grain.step) % length as f32;
+            grain.age += 1;
+        }
+        self.grains.retain(|grain| grain.age < grain.length);
+
+        // Overlapping grains would get louder with higher density
+        let overlap = rate * seconds;
+        let gain = 1.0 / overlap.max(1.0).sqrt();
+        (mix.0 * gain, mix.1 * gain)
+    }
+
+    // Grains read forward from their start, so they are placed far enough
+    // behind the capture not to run into it, and close enough not to get
+    // overwritten before they finish.
+    fn spawn(&mut self, seconds: f32, randomizer: &mut impl Rand) {
+        if self.grains.is_full() {
+            return;
+        }
+
+        let length = self.buffer.len() as f32;
+        let grain_length = (seconds * self.sample_rate).min(length / 4.0);
+        let octaves = self.jitter * MAX_JITTER * (2.0 * random(randomizer) - 1.0);
+        let step = (octaves * LN_2).exp();
+        let span = grain_length * step;
+        let room = (length - 2.0 - span - grain_length).max(0.0);
+        let delay = span + 1.0 + self.position * room;
+        let start = self.head as f32 - delay;
+        let start = if start < 0.0 { start + length } else { start };
+
+        let _ = self.grains.push(Grain {
+            position: start,
+            step,
+            age: 0,
+            length: grain_length as usize,
+        });
+    }
+}
+
+// Linear interpolation between frames around the position.
+fn read(buffer: &[(f32, f32)], position: f32) -> (f32, f32) {
+    let index = position as usize;
+    let fraction = position - index as f32;
+    let a = buffer[index % buffer.len()];
+    let b = buffer[(index + 1) % buffer.len()];
+    (a.0 + (b.0 - a.0) * fraction, a.1 + (b.1 - a.1) * fraction)
+}
+
+// Random number between 0.0 and 1.0.
+fn random(randomizer: &mut impl Rand) -> f32 {
+    randomizer.generate() as f32 / u16::MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 1_000;
+    const LENGTH: usize = 4_000;
+
+    struct Randomizer(u16);
+
+    impl Rand for Randomizer {
+        fn generate(&mut self) -> u16 {
+            self.0 = self.0.wrapping_mul(25_173).wrapping_add(13_849);
+            self.0
+        }
+    }
+
+    // Ramp of frames each carrying its own index, so the output tells where
+    // grains read from.
+    fn ramp(granular: &mut Granular) {
+        for i in 0..LENGTH {
+            granular.capture((i as f32, -(i as f32)));
+        }
+    }
+
+    #[test]
+    fn when_windows_are_sampled_they_start_and_end_silent() {
+        for window in [Window::Hann, Window::Triangle, Window::Trapezoid] {
+            assert!(window.amplitude(0.0).abs() < 1e-3);
+            assert!(window.amplitude(1.0).abs() < 1e-3);
+            assert!((window.amplitude(0.5) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn when_playing_without_jitter_grains_read_the_buffer_at_original_speed() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut granular = Granular::new(&mut buffer, SAMPLE_RATE);
+        granular.set_density(0.0);
+        granular.set_window(Window::Trapezoid);
+        ramp(&mut granular);
+
+        let mut randomizer = Randomizer(0);
+        let first = granular.next_frame(&mut randomizer);
+        assert_eq!(first, (0.0, 0.0));
+        // The grain has already moved by the first frame
+        let grain = &granular.grains[0];
+        assert!((grain.step - 1.0).abs() < 1e-6);
+        let start = grain.position;
+
+        // In the flat part of the window, the output is the read index
+        let length = grain.length;
+        for i in 1..length {
+            let frame = granular.next_frame(&mut randomizer);
+            let phase = i as f32 / length as f32;
+            if phase > 0.25 && phase < 0.75 {
+                let expected = start + (i - 1) as f32;
+                assert!((frame.0 - expected).abs() < 1e-3, "{:?}", frame);
+                assert_eq!(frame.0, -frame.1);
+            }
+        }
+    }
+
+    #[test]
+    fn when_position_is_moved_grains_start_further_back() {
+        let start = |position| {
+            let mut buffer = [(0.0, 0.0); LENGTH];
+            let mut granular = Granular::new(&mut buffer, SAMPLE_RATE);
+            granular.set_position(position);
+            ramp(&mut granular);
+            granular.next_frame(&mut Randomizer(0));
+            granular.grains[0].position
+        };
+
+        let recent = start(0.0);
+        let oldest = start(1.0);
+        assert!(recent > oldest);
+        // The grain spans the size, so it does not run into the capture
+        let seconds = size_to_seconds(0.5);
+        assert!(recent + seconds * SAMPLE_RATE as f32 <= LENGTH as f32);
+        assert!(oldest >= 0.0);
+    }
+
+    #[test]
+    fn when_density_is_raised_more_grains_are_spawned() {
+        let count = |density| {
+            let mut buffer = [(0.0, 0.0); LENGTH];
+            let mut granular = Granular::new(&mut buffer, SAMPLE_RATE);
+            granular.set_density(density);
+            granular.set_size(0.0);
+            let mut randomizer = Randomizer(0);
+            let mut spawned: i32 = 0;
+            for _ in 0..10 * SAMPLE_RATE {
+                let before = granular.grains.len();
+                granular.next_frame(&mut randomizer);
+                // Grains of the minimal size live for 10 frames
+                if granular.grains.len() > before {
+                    spawned += 1;
+                }
+            }
+            spawned
+        };
+
+        let sparse = count(0.0);
+        let dense = count(0.5);
+        assert!((sparse - 10).abs() <= 3, "{}", sparse);
+        assert!((dense - 100).abs() <= 10, "{}", dense);
+    }
+
+    #[test]
+    fn when_jitter_is_set_grains_get_different_pitch() {
+        let mut buffer = [(0.0, 0.0); LENGTH];
+        let mut granular = Granular::new(&mut buffer, SAMPLE_RATE);
+        granular.set_jitter(1.0);
+        granular.set_density(1.0);
+        let mut randomizer = Randomizer(0);
+
+        let mut min: f32 = 1.0;
+        let mut max: f32 = 1.0;
+        for _ in 0..SAMPLE_RATE {
+            granular.next_frame(&mut randomizer);
+            for grain in granular.grains.iter() {
+                assert!(grain.step >= 0.5 - 1e-3 && grain.step <= 2.0 + 1e-3);
+                min = min.min(grain.step);
+                max = max.max(grain.step);
+            }
+        }
+        assert!(min < 0.7 && max > 1.4);
+    }
+}
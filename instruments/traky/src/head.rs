@@ -0,0 +1,181 @@
+//! Frequency response of the playback head.
+//!
+//! Low frequencies get emphasized around the head bump, caused by the
+//! wavelength recorded on the tape getting close to the size of the head.
+//! High frequencies are lost with worn heads and slow tapes.
+
+use core::f32::consts::PI;
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+use crate::math::tan;
+
+const BUMP_FREQUENCY: f32 = 100.0;
+const BUMP_Q: f32 = 1.0;
+const BUMP_MAX_GAIN_DB: f32 = 6.0;
+const LOWPASS_MAX_CUTOFF: f32 = 20_000.0;
+const LOWPASS_MIN_CUTOFF: f32 = 2_000.0;
+const LOWPASS_Q: f32 = 0.707;
+
+pub struct Head {
+    sample_rate: f32,
+    bump: [Svf; 2],
+    lowpass: [Svf; 2],
+    // The lowpass is skipped while there is no loss, so it does not color
+    // the signal even slightly.
+    loss: bool,
+}
+
+impl Head {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut head = Self {
+            sample_rate: sample_rate as f32,
+            bump: [Svf::new(), Svf::new()],
+            lowpass: [Svf::new(), Svf::new()],
+            loss: false,
+        };
+        head.set(0.0, 0.0);
+        head
+    }
+
+    /// Set the amount of the head bump and the loss of high frequencies,
+    /// both between 0.0 and 1.0.
+    pub fn set(&mut self, bump: f32, loss: f32) {
+        let gain = 10.0.powf(bump * BUMP_MAX_GAIN_DB / 40.0);
+        let coefficients = Coefficients::bell(BUMP_FREQUENCY / self.sample_rate, BUMP_Q, gain);
+        for filter in self.bump.iter_mut() {
+            filter.coefficients = coefficients;
+        }
+
+        // Cutoff goes down exponentially, so the knob feels even
+        let cutoff = LOWPASS_MAX_CUTOFF * (LOWPASS_MIN_CUTOFF / LOWPASS_MAX_CUTOFF).powf(loss);
+        let coefficients = Coefficients::lowpass(cutoff / self.sample_rate, LOWPASS_Q);
+        for filter in self.lowpass.iter_mut() {
+            filter.coefficients = coefficients;
+        }
+        self.loss = loss > 0.0;
+    }
+
+    pub fn process(&mut self, frame: (f32, f32)) -> (f32, f32) {
+        let mut frame = (self.bump[0].process(frame.0), self.bump[1].process(frame.1));
+        if self.loss {
+            frame = (
+                self.lowpass[0].process(frame.0),
+                self.lowpass[1].process(frame.1),
+            );
+        }
+        frame
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Coefficients {
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    m0: f32,
+    m1: f32,
+    m2: f32,
+}
+
+impl Coefficients {
+    // Frequency is relative to the sample rate. Gain is the square root of
+    // the linear gain at the center frequency.
+    fn bell(frequency: f32, q: f32, gain: f32) -> Self {
+        let k = 1.0 / (q * gain);
+        Self::new(frequency, k, (1.0, k * (gain * gain - 1.0), 0.0))
+    }
+
+    fn lowpass(frequency: f32, q: f32) -> Self {
+        Self::new(frequency, 1.0 / q, (0.0, 0.0, 1.0))
+    }
+
+    fn new(frequency: f32, k: f32, (m0, m1, m2): (f32, f32, f32)) -> Self {
+        let g = tan(PI * frequency.min(0.49));
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        Self {
+            a1,
+            a2,
+            a3,
+            m0,
+            m1,
+            m2,
+        }
+    }
+}
+
+// State variable filter discretized with trapezoidal integration, staying
+// stable while its coefficients change.
+struct Svf {
+    coefficients: Coefficients,
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Svf {
+    fn new() -> Self {
+        Self {
+            coefficients: Coefficients::lowpass(0.25, LOWPASS_Q),
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        }
+    }
+
+    fn process(&mut self, v0: f32) -> f32 {
+        let c = &self.coefficients;
+        let v3 = v0 - self.ic2eq;
+        let v1 = c.a1 * self.ic1eq + c.a2 * v3;
+        let v2 = self.ic2eq + c.a2 * self.ic1eq + c.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+        c.m0 * v0 + c.m1 * v1 + c.m2 * v2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    fn amplitude(head: &mut Head, frequency: f32) -> f32 {
+        let mut peak: f32 = 0.0;
+        for i in 0..SAMPLE_RATE {
+            let x = (2.0 * PI * frequency * i as f32 / SAMPLE_RATE as f32).sin();
+            let (left, right) = head.process((x, -x));
+            assert!((left + right).abs() < 1e-4);
+            // Skip the settling of filters
+            if i > SAMPLE_RATE / 2 {
+                peak = peak.max(left.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn when_neutral_it_passes_signal_through() {
+        let mut head = Head::new(SAMPLE_RATE);
+        for frequency in [50.0, 100.0, 1_000.0, 10_000.0] {
+            assert!((amplitude(&mut head, frequency) - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn when_bump_is_set_it_emphasizes_low_frequencies() {
+        let mut head = Head::new(SAMPLE_RATE);
+        head.set(1.0, 0.0);
+        assert!((amplitude(&mut head, BUMP_FREQUENCY) - 2.0).abs() < 0.05);
+        assert!((amplitude(&mut head, 5_000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn when_loss_is_set_it_attenuates_high_frequencies() {
+        let mut head = Head::new(SAMPLE_RATE);
+        head.set(0.0, 1.0);
+        assert!((amplitude(&mut head, 200.0) - 1.0).abs() < 0.01);
+        assert!(amplitude(&mut head, 10_000.0) < 0.1);
+    }
+}
@@ -1,30 +1,211 @@
 #![no_std]
 
+mod delay;
+mod gate;
+mod granular;
+mod head;
+mod library;
+mod math;
 mod paginated_buffer;
+mod resampler;
+mod saturation;
+mod slices;
+mod smoother;
+mod stream;
+mod tape;
+mod wav;
+mod wow_flutter;
 
 use core::convert::TryFrom;
 use core::fmt;
 
-use micromath::F32Ext as _;
-
 use embedded_sdmmc::blockdevice::BlockDevice;
-use embedded_sdmmc::{Controller, Mode, VolumeIdx};
+use heapless::{Deque, Vec};
 use proton_control::input_snapshot::InputSnapshot;
+use proton_control::output_request::OutputRequest;
 use proton_instruments_interface::{
     Instrument as InstrumentTrait, MemoryManager, Rand as ProtonRandomizer,
+    StorageTask as StorageTaskTrait,
 };
+use proton_ui::browser::{Browser, FileName, MAX_DEPTH};
+use proton_ui::notification::Notification;
 use proton_ui::reaction::Reaction;
+use proton_ui::state::Page as MenuPage;
 use proton_ui::state::*;
 
+use crate::delay::Delay;
+use crate::gate::Gate;
+use crate::granular::{Granular, Window};
+use crate::head::Head;
+use crate::paginated_buffer::Page;
+use crate::resampler::{Interpolation, Resampler};
+use crate::saturation::saturate;
+use crate::slices::{OnsetDetector, Slices, MARKERS};
+use crate::smoother::Smoother;
+use crate::stream::{Recording, Stream, PAGES};
+use crate::tape::{Tape, TapeError};
+use crate::wow_flutter::WowFlutter;
+
 const NAME: &str = "Traky";
+const PLAYBACK_PAGE: &str = "playback";
+const TAPE_PAGE: &str = "tape";
+const DELAY_PAGE: &str = "delay";
+const GRAINS_PAGE: &str = "grains";
+const SLICES_PAGE: &str = "slices";
+const MARKERS_PAGE: &str = "markers";
+const ROUTING_PAGE: &str = "routing";
+const RECORD_PAGE: &str = "record";
+const SYSTEM_PAGE: &str = "system";
+const MODE_ATTRIBUTE: &str = "mode";
+const MODE_LOOPER: &str = "looper";
+const MODE_DELAY: &str = "delay";
+const MODE_GRANULAR: &str = "granular";
+const MODE_SLICE: &str = "slice";
+const SAMPLE_ATTRIBUTE: &str = "sample";
 const VOLUME_ATTRIBUTE: &str = "volume";
+const SPEED_ATTRIBUTE: &str = "speed";
+const SPEED_HALF_TO_ONE: &str = "0.5-1x";
+const SPEED_ZERO_TO_ONE: &str = "0-1x";
+const SPEED_ZERO_TO_TWO: &str = "0-2x";
+const SPEED_ZERO_TO_FOUR: &str = "0-4x";
+const DIRECTION_ATTRIBUTE: &str = "direction";
+const DIRECTION_FORWARD: &str = "forward";
+const DIRECTION_REVERSE: &str = "reverse";
+const PLAY_ATTRIBUTE: &str = "play";
+const RESTART_ATTRIBUTE: &str = "restart";
+const START_ATTRIBUTE: &str = "start";
+const END_ATTRIBUTE: &str = "end";
+const WOW_ATTRIBUTE: &str = "wow";
+const FLUTTER_ATTRIBUTE: &str = "flutter";
+const SATURATION_ATTRIBUTE: &str = "saturation";
+const BUMP_ATTRIBUTE: &str = "bump";
+const LOSS_ATTRIBUTE: &str = "hf loss";
+const HEAD_1_ATTRIBUTE: &str = "head 1";
+const HEAD_2_ATTRIBUTE: &str = "head 2";
+const HEAD_3_ATTRIBUTE: &str = "head 3";
+const FEEDBACK_ATTRIBUTE: &str = "feedback";
+const MIX_ATTRIBUTE: &str = "mix";
+const POSITION_ATTRIBUTE: &str = "position";
+const SIZE_ATTRIBUTE: &str = "size";
+const DENSITY_ATTRIBUTE: &str = "density";
+const JITTER_ATTRIBUTE: &str = "jitter";
+const WINDOW_ATTRIBUTE: &str = "window";
+const WINDOW_HANN: &str = "hann";
+const WINDOW_TRIANGLE: &str = "triangle";
+const WINDOW_TRAPEZOID: &str = "trapezoid";
+const SLICE_ATTRIBUTE: &str = "slice";
+const SLICING_ATTRIBUTE: &str = "slicing";
+const SLICING_ONSETS: &str = "onsets";
+const SLICING_EVEN_2: &str = "even 2";
+const SLICING_EVEN_4: &str = "even 4";
+const SLICING_EVEN_8: &str = "even 8";
+const MARKER_ATTRIBUTES: [&str; MARKERS] = [
+    "marker 1", "marker 2", "marker 3", "marker 4", "marker 5", "marker 6", "marker 7",
+];
+const CV_4_ATTRIBUTE: &str = "cv 4";
+const CV_5_ATTRIBUTE: &str = "cv 5";
+const CV_OFF: &str = "off";
+const INTERPOLATION_ATTRIBUTE: &str = "resampling";
+const INTERPOLATION_LINEAR: &str = "linear";
+const INTERPOLATION_HERMITE: &str = "hermite";
+const INTERPOLATION_SINC: &str = "sinc";
+const RECORD_ATTRIBUTE: &str = "record";
+const RECORD_MODE_ATTRIBUTE: &str = "rec mode";
+const RECORD_MODE_OVERDUB: &str = "overdub";
+const RECORD_MODE_REPLACE: &str = "replace";
+const CLEAR_ATTRIBUTE: &str = "clear";
+
+// Recording is armed while this CV input is high.
+const RECORD_GATE_INPUT: usize = 0;
+// Rising edge on this CV input returns the playback to the start of the loop.
+// In slice mode, it starts the selected slice, which plays while it is high.
+const RESET_TRIGGER_INPUT: usize = 1;
+// Direction set in the UI is flipped while this CV input is high.
+const REVERSE_GATE_INPUT: usize = 2;
+// Values of these CV inputs are added to parameters assigned to them in
+// the UI.
+const ASSIGNABLE_CV_INPUTS: [usize; 2] = [3, 4];
 
-const MAX_SAMPLE_LENGTH_IN_SECONDS: u32 = 20;
+const SAMPLE_NAME: &str = "project.wav";
+const PAGE_LENGTH: usize = 1 << 15;
+// Length of the tape loop of the delay, the longest delay of a head.
+const DELAY_SECONDS: f32 = 4.0;
+// Length of the history captured for grains.
+const GRANULAR_SECONDS: f32 = 4.0;
+// All the markers and the title may change at once.
+const NOTIFICATIONS: usize = 16;
 
 pub struct Instrument {
-    sample: Sample,
-    pointer: f32,
-    speed: f32,
+    sample_rate: u32,
+    mode: Mode,
+    stream: Stream<'static>,
+    tape: Option<Tape>,
+    browser: Browser,
+    requested_sample: Option<usize>,
+    resampler: Resampler,
+    delay: Delay<'static>,
+    granular: Granular<'static>,
+    mix: Smoother,
+    wow_flutter: WowFlutter,
+    head: Head,
+    saturation: Smoother,
+    parameters: [f32; PARAMETERS],
+    assignable_cv: [f32; 2],
+    cv_destination: [Option<Parameter>; 2],
+    play: bool,
+    reverse: bool,
+    volume_level: f32,
+    volume: Smoother,
+    speed: Smoother,
+    speed_range: (f32, f32),
+    pot: f32,
+    loop_start: f32,
+    loop_end: f32,
+    record: bool,
+    record_mode: Recording,
+    clear_requested: bool,
+    record_gate: Gate,
+    reset_trigger: Gate,
+    reverse_gate: Gate,
+    slices: Slices,
+    slicing: Slicing,
+    slice_task: Option<SliceTask>,
+    slices_dirty: bool,
+    // Page used to read the sample for the onset detection.
+    scratch_page: Option<Page<'static>>,
+    error: Option<TapeError>,
+    notifications: Deque<Notification, NOTIFICATIONS>,
+}
+
+pub struct StorageTask {
+    tape: Tape,
+    operation: Operation,
+    result: Result<(), TapeError>,
+}
+
+// Without a heap, the onset detector cannot be boxed. Tasks are rare, so
+// the size of the largest variant does not matter.
+#[allow(clippy::large_enum_variant)]
+enum Operation {
+    Load(Page<'static>),
+    Save(Page<'static>),
+    Open(Vec<FileName, MAX_DEPTH>),
+    Clear,
+    LoadSlices(Option<Slices>),
+    DetectSlices(OnsetDetector, Page<'static>),
+    SaveSlices(Slices),
+}
+
+// Analysis of the sample that replaces its slice markers. It is dropped
+// when another one is requested while it is in progress.
+#[allow(clippy::large_enum_variant)]
+enum SliceTask {
+    // Load markers stored with the sample, slice it if there are none.
+    Load,
+    // Slice the sample by the selected method.
+    Slice,
+    // Continue the onset detection from where the detector stopped.
+    Detect(OnsetDetector),
 }
 
 fn writter(destination: &mut dyn fmt::Write, value: f32) {
@@ -32,167 +213,1067 @@ fn writter(destination: &mut dyn fmt::Write, value: f32) {
     write!(destination, "{}%", value).unwrap();
 }
 
+fn size_writter(destination: &mut dyn fmt::Write, value: f32) {
+    let value = (granular::size_to_seconds(value) * 1000.0) as u32;
+    write!(destination, "{}ms", value).unwrap();
+}
+
+fn density_writter(destination: &mut dyn fmt::Write, value: f32) {
+    let value = granular::density_to_rate(value) as u32;
+    write!(destination, "{}/s", value).unwrap();
+}
+
+fn marker_writter(destination: &mut dyn fmt::Write, value: f32) {
+    if value > 0.0 {
+        let value = (value * 1000.0) as u32;
+        write!(destination, "{}.{}%", value / 10, value % 10).unwrap();
+    } else {
+        write!(destination, "off").unwrap();
+    }
+}
+
+fn head_writter(destination: &mut dyn fmt::Write, value: f32) {
+    if value > 0.0 {
+        let value = (value * DELAY_SECONDS * 1000.0) as u32;
+        write!(destination, "{}ms", value).unwrap();
+    } else {
+        write!(destination, "off").unwrap();
+    }
+}
+
 impl InstrumentTrait for Instrument {
     type Command = Command;
+    type StorageTask = StorageTask;
 
     fn new(
         sample_rate: u32,
         memory_manager: &mut MemoryManager,
         sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
     ) -> Self {
-        defmt::info!("Allocating buffer");
-        let mut sample = prepare_empty_sample(memory_manager, sample_rate);
+        defmt::info!("Allocating pages");
+        let pages = allocate_pages(memory_manager);
 
-        defmt::info!("Loading sample from SD");
-        load_sample_from_sd(sd, &mut sample);
+        defmt::info!("Allocating delay");
+        let delay_length = (DELAY_SECONDS * sample_rate as f32) as usize;
+        let delay = Delay::new(allocate_frames(memory_manager, delay_length), sample_rate);
 
-        defmt::info!("Initialization complete");
+        defmt::info!("Allocating grain buffer");
+        let granular_length = (GRANULAR_SECONDS * sample_rate as f32) as usize;
+        let granular = Granular::new(
+            allocate_frames(memory_manager, granular_length),
+            sample_rate,
+        );
+
+        defmt::info!("Allocating scratch page");
+        let scratch_page = Page::new(allocate_frames(memory_manager, PAGE_LENGTH), 0);
 
-        Self {
-            sample,
-            pointer: 0.0,
-            speed: 0.0,
+        defmt::info!("Listing samples on SD");
+        let browser = library::scan(sd).unwrap_or_else(|error| {
+            defmt::error!("Failed to list samples: {}", error);
+            Browser::new()
+        });
+
+        defmt::info!("Opening sample on SD");
+        let (tape, error) = match Tape::open(sd, &[SAMPLE_NAME], PAGE_LENGTH) {
+            Ok(tape) => (Some(tape), None),
+            Err(error) => {
+                defmt::error!("Failed to open tape: {}", error);
+                (None, Some(error))
+            }
+        };
+        let length = tape.as_ref().map_or(0, |t| t.length());
+        let source_rate = tape
+            .as_ref()
+            .and_then(|t| t.sample_rate())
+            .unwrap_or(sample_rate);
+
+        let mut instrument = Self {
+            sample_rate,
+            mode: Mode::Looper,
+            stream: Stream::new(pages, length),
+            resampler: Resampler::new(source_rate, sample_rate),
+            tape,
+            browser,
+            requested_sample: None,
+            delay,
+            granular,
+            mix: Smoother::new(0.5, sample_rate),
+            wow_flutter: WowFlutter::new(sample_rate),
+            head: Head::new(sample_rate),
+            saturation: Smoother::new(0.0, sample_rate),
+            parameters: Parameter::default_levels(),
+            assignable_cv: [0.0; 2],
+            cv_destination: [Some(Parameter::Wow), Some(Parameter::Saturation)],
+            play: true,
+            reverse: false,
+            volume_level: 1.0,
+            volume: Smoother::new(1.0, sample_rate),
+            speed: Smoother::new(1.0, sample_rate),
+            speed_range: (0.5, 1.0),
+            pot: 0.0,
+            loop_start: 0.0,
+            loop_end: 1.0,
+            record: false,
+            record_mode: Recording::Overdub,
+            clear_requested: false,
+            record_gate: Gate::new(),
+            reset_trigger: Gate::new(),
+            reverse_gate: Gate::new(),
+            slices: Slices::default(),
+            slicing: Slicing::Even(8),
+            slice_task: Some(SliceTask::Load),
+            slices_dirty: false,
+            scratch_page: Some(scratch_page),
+            error,
+            notifications: Deque::new(),
+        };
+
+        instrument.update_parameters();
+
+        defmt::info!("Loading initial pages and slices");
+        while let Some(mut task) = instrument.storage_task() {
+            task.run(sd);
+            instrument.finish_storage_task(task);
         }
+
+        defmt::info!("Initialization complete");
+
+        instrument
     }
 
     fn state(&self) -> State {
-        State::new(NAME)
-            .with_attributes(&[Attribute::new(VOLUME_ATTRIBUTE)
-                .with_value_f32(ValueF32::new(1.0).with_writter(writter))])
+        let title = self.error.map_or(NAME, |e| e.title());
+        let sample = match library::find(&self.browser, &[SAMPLE_NAME]) {
+            Some(index) => ValueFile::new().with_selected(index, &self.browser),
+            None => ValueFile::new(),
+        };
+        State::new(title)
+            .with_attributes(&[
+                Attribute::new(MODE_ATTRIBUTE).with_value_select(
+                    ValueSelect::new(&[MODE_LOOPER, MODE_DELAY, MODE_GRANULAR, MODE_SLICE])
+                        .unwrap(),
+                ),
+                Attribute::new(SAMPLE_ATTRIBUTE).with_value_file(sample),
+                Attribute::new(PLAY_ATTRIBUTE).with_value_bool(ValueBool::new(true)),
+                Attribute::new(VOLUME_ATTRIBUTE)
+                    .with_value_f32(ValueF32::new(1.0).with_writter(writter)),
+                Attribute::new(SPEED_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[
+                            SPEED_HALF_TO_ONE,
+                            SPEED_ZERO_TO_ONE,
+                            SPEED_ZERO_TO_TWO,
+                            SPEED_ZERO_TO_FOUR,
+                        ])
+                        .unwrap(),
+                    )
+                    .with_page(PLAYBACK_PAGE),
+                Attribute::new(DIRECTION_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[DIRECTION_FORWARD, DIRECTION_REVERSE]).unwrap(),
+                    )
+                    .with_page(PLAYBACK_PAGE),
+                Attribute::new(START_ATTRIBUTE)
+                    .with_value_f32(ValueF32::new(0.0).with_writter(writter))
+                    .with_page(PLAYBACK_PAGE),
+                Attribute::new(END_ATTRIBUTE)
+                    .with_value_f32(ValueF32::new(1.0).with_writter(writter))
+                    .with_page(PLAYBACK_PAGE),
+                Attribute::new(RESTART_ATTRIBUTE)
+                    .with_value_trigger()
+                    .with_page(PLAYBACK_PAGE),
+                Attribute::new(WOW_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Wow.default_level()).with_writter(writter),
+                    )
+                    .with_page(TAPE_PAGE),
+                Attribute::new(FLUTTER_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Flutter.default_level()).with_writter(writter),
+                    )
+                    .with_page(TAPE_PAGE),
+                Attribute::new(SATURATION_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Saturation.default_level()).with_writter(writter),
+                    )
+                    .with_page(TAPE_PAGE),
+                Attribute::new(BUMP_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Bump.default_level()).with_writter(writter),
+                    )
+                    .with_page(TAPE_PAGE),
+                Attribute::new(LOSS_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Loss.default_level()).with_writter(writter),
+                    )
+                    .with_page(TAPE_PAGE),
+                Attribute::new(HEAD_1_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Head1.default_level()).with_writter(head_writter),
+                    )
+                    .with_page(DELAY_PAGE),
+                Attribute::new(HEAD_2_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Head2.default_level()).with_writter(head_writter),
+                    )
+                    .with_page(DELAY_PAGE),
+                Attribute::new(HEAD_3_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Head3.default_level()).with_writter(head_writter),
+                    )
+                    .with_page(DELAY_PAGE),
+                Attribute::new(FEEDBACK_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Feedback.default_level()).with_writter(writter),
+                    )
+                    .with_page(DELAY_PAGE),
+                Attribute::new(MIX_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Mix.default_level()).with_writter(writter),
+                    )
+                    .with_page(DELAY_PAGE),
+                Attribute::new(POSITION_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Position.default_level()).with_writter(writter),
+                    )
+                    .with_page(GRAINS_PAGE),
+                Attribute::new(SIZE_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Size.default_level()).with_writter(size_writter),
+                    )
+                    .with_page(GRAINS_PAGE),
+                Attribute::new(DENSITY_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Density.default_level())
+                            .with_writter(density_writter),
+                    )
+                    .with_page(GRAINS_PAGE),
+                Attribute::new(JITTER_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Jitter.default_level()).with_writter(writter),
+                    )
+                    .with_page(GRAINS_PAGE),
+                Attribute::new(WINDOW_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[WINDOW_HANN, WINDOW_TRIANGLE, WINDOW_TRAPEZOID])
+                            .unwrap(),
+                    )
+                    .with_page(GRAINS_PAGE),
+                Attribute::new(SLICE_ATTRIBUTE)
+                    .with_value_f32(
+                        ValueF32::new(Parameter::Slice.default_level()).with_writter(writter),
+                    )
+                    .with_page(SLICES_PAGE),
+                Attribute::new(SLICING_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[
+                            SLICING_ONSETS,
+                            SLICING_EVEN_2,
+                            SLICING_EVEN_4,
+                            SLICING_EVEN_8,
+                        ])
+                        .unwrap()
+                        .with_selected(3),
+                    )
+                    .with_page(SLICES_PAGE),
+                self.marker_attribute(0),
+                self.marker_attribute(1),
+                self.marker_attribute(2),
+                self.marker_attribute(3),
+                self.marker_attribute(4),
+                self.marker_attribute(5),
+                self.marker_attribute(6),
+                Attribute::new(CV_4_ATTRIBUTE)
+                    .with_value_select(ValueSelect::new(&CV_DESTINATIONS).unwrap().with_selected(1))
+                    .with_page(ROUTING_PAGE),
+                Attribute::new(CV_5_ATTRIBUTE)
+                    .with_value_select(ValueSelect::new(&CV_DESTINATIONS).unwrap().with_selected(3))
+                    .with_page(ROUTING_PAGE),
+                Attribute::new(INTERPOLATION_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[
+                            INTERPOLATION_HERMITE,
+                            INTERPOLATION_SINC,
+                            INTERPOLATION_LINEAR,
+                        ])
+                        .unwrap(),
+                    )
+                    .with_page(SYSTEM_PAGE),
+                Attribute::new(RECORD_ATTRIBUTE)
+                    .with_value_bool(ValueBool::new(false))
+                    .with_page(RECORD_PAGE),
+                Attribute::new(RECORD_MODE_ATTRIBUTE)
+                    .with_value_select(
+                        ValueSelect::new(&[RECORD_MODE_OVERDUB, RECORD_MODE_REPLACE]).unwrap(),
+                    )
+                    .with_page(RECORD_PAGE),
+                Attribute::new(CLEAR_ATTRIBUTE)
+                    .with_value_trigger()
+                    .with_page(RECORD_PAGE),
+            ])
+            .unwrap()
+            .with_pages(&[
+                MenuPage::new(PLAYBACK_PAGE),
+                MenuPage::new(TAPE_PAGE),
+                MenuPage::new(DELAY_PAGE),
+                MenuPage::new(GRAINS_PAGE),
+                MenuPage::new(SLICES_PAGE),
+                MenuPage::new(MARKERS_PAGE).with_parent(SLICES_PAGE),
+                MenuPage::new(ROUTING_PAGE),
+                MenuPage::new(RECORD_PAGE),
+                MenuPage::new(SYSTEM_PAGE),
+            ])
             .unwrap()
+            .with_browser(self.browser.clone())
     }
 
-    fn process(&mut self, buffer: &mut [(f32, f32)], _randomizer: &mut impl ProtonRandomizer) {
-        for tuple in buffer.iter_mut() {
-            let pointer_usize = self.pointer as usize;
-            let tuple_a = self.sample.buffer[pointer_usize];
-            let tuple_b = if pointer_usize + 1 >= self.sample.length {
-                self.sample.buffer[0]
-            } else {
-                self.sample.buffer[pointer_usize + 1]
-            };
-
-            let new_tuple = {
-                (
-                    tuple_a.0 + (tuple_b.0 - tuple_a.0) * self.pointer.fract(),
-                    tuple_a.1 + (tuple_b.1 - tuple_a.1) * self.pointer.fract(),
-                )
-            };
-            *tuple = new_tuple;
+    fn process(&mut self, buffer: &mut [(f32, f32)], randomizer: &mut impl ProtonRandomizer) {
+        match self.mode {
+            Mode::Looper => self.process_looper(buffer, randomizer),
+            Mode::Delay => self.process_delay(buffer, randomizer),
+            Mode::Granular => self.process_granular(buffer, randomizer),
+            Mode::Slice => self.process_looper(buffer, randomizer),
+        }
+    }
 
-            self.pointer += 0.5 + 0.5 * self.speed;
-            if self.pointer as usize >= self.sample.length {
-                self.pointer = self.pointer.fract();
+    fn execute(&mut self, command: Command) {
+        match command {
+            Command::SetMode(mode) => {
+                // Slices set their own loop, the one from the UI is restored
+                // when leaving
+                if self.mode == Mode::Slice && mode != Mode::Slice {
+                    self.stream.set_loop(self.loop_start, self.loop_end);
+                }
+                self.mode = mode;
+                self.update_speed();
+                self.update_volume();
+                self.update_parameters();
+            }
+            Command::SetWindow(window) => self.granular.set_window(window),
+            Command::LoadSample(index) => self.requested_sample = Some(index),
+            Command::SetPlay(play) => {
+                self.play = play;
+                self.update_speed();
+                self.update_volume();
+            }
+            Command::Restart => self.restart(),
+            Command::SetVolume(volume) => {
+                self.volume_level = volume;
+                self.update_volume();
+            }
+            Command::SetSpeedRange(min, max) => {
+                self.speed_range = (min, max);
+                self.update_speed();
+            }
+            Command::SetReverse(reverse) => {
+                self.reverse = reverse;
+                self.update_direction();
+            }
+            Command::SetLoopStart(start) => {
+                self.loop_start = start;
+                self.update_loop();
+            }
+            Command::SetLoopEnd(end) => {
+                self.loop_end = end;
+                self.update_loop();
+            }
+            Command::SetParameter(parameter, value) => {
+                self.parameters[parameter as usize] = value;
+                self.update_parameters();
+            }
+            Command::AssignCv(input, destination) => {
+                self.cv_destination[input] = destination;
+                self.update_parameters();
+            }
+            Command::SetInterpolation(interpolation) => {
+                self.resampler.set_interpolation(interpolation)
+            }
+            Command::SetRecord(record) => {
+                self.record = record;
+                self.update_recording();
+            }
+            Command::SetRecordMode(mode) => {
+                self.record_mode = mode;
+                self.update_recording();
+            }
+            Command::ClearRecording => self.clear_requested = true,
+            Command::SetSlicing(slicing) => {
+                self.slicing = slicing;
+                self.slice_task = Some(SliceTask::Slice);
+            }
+            Command::SetMarker(index, position) => {
+                let frame = (position * self.stream.length() as f32) as usize;
+                self.slices.set_marker(index, frame);
+                self.slices_dirty = true;
             }
         }
     }
 
-    fn execute(&mut self, _command: Command) {}
-
     fn update_control(&mut self, snapshot: InputSnapshot) {
-        self.speed = 1.0 - snapshot.pot.value;
-    }
-}
-
-fn prepare_empty_sample(memory_manager: &mut MemoryManager, sample_rate: u32) -> Sample {
-    Sample::from_buffer(
-        memory_manager
-            .allocate(upper_power_of_two(2 * sample_rate * MAX_SAMPLE_LENGTH_IN_SECONDS) as usize)
-            .unwrap(),
-    )
-}
-
-fn load_sample_from_sd(
-    sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
-    sample: &mut Sample,
-) {
-    let mut fat = Controller::new(sd, TimeSource);
-    let mut volume = fat.get_volume(VolumeIdx(0)).unwrap();
-    let root_dir = fat.open_root_dir(&volume).unwrap();
-    let mut file = fat
-        .open_file_in_dir(&mut volume, &root_dir, "project.raw", Mode::ReadOnly)
-        .unwrap();
-
-    let mut buffer = [0u8; 512 * 2 * 64];
-    while !file.eof() {
-        let num_read = fat.read(&volume, &mut file, &mut buffer).unwrap();
-        let buffer_tuple = {
-            let pointer = &buffer as *const _ as *const (f32, f32);
-            let buffer_tuple =
-                unsafe { core::slice::from_raw_parts::<(f32, f32)>(pointer, num_read / 8) };
-            buffer_tuple
+        self.pot = snapshot.pot.value;
+        self.update_speed();
+        if self
+            .reset_trigger
+            .update(snapshot.cv[RESET_TRIGGER_INPUT].value)
+        {
+            self.restart();
+        }
+        self.update_volume();
+        self.reverse_gate
+            .update(snapshot.cv[REVERSE_GATE_INPUT].value);
+        self.update_direction();
+        self.record_gate
+            .update(snapshot.cv[RECORD_GATE_INPUT].value);
+        self.update_recording();
+        for (value, input) in self.assignable_cv.iter_mut().zip(ASSIGNABLE_CV_INPUTS) {
+            *value = snapshot.cv[input].value;
+        }
+        self.update_parameters();
+    }
+
+    fn control_output(&self) -> OutputRequest {
+        OutputRequest::default()
+    }
+
+    // Without a tape, there is nothing to load from and the stream stays
+    // silent. Recordings and slices are saved before another sample is
+    // opened or the recording is cleared, so they are stored next to the
+    // sample they belong to. Slicing waits until the playback has all pages
+    // it needs.
+    fn storage_task(&mut self) -> Option<StorageTask> {
+        self.tape.as_ref()?;
+        let operation = if let Some(page) = self.stream.page_to_save() {
+            Operation::Save(page)
+        } else if self.slices_dirty {
+            self.slices_dirty = false;
+            Operation::SaveSlices(self.slices)
+        } else if self.clear_requested {
+            self.clear_requested = false;
+            Operation::Clear
+        } else if let Some(index) = self.requested_sample.take() {
+            Operation::Open(self.browser.path(index))
+        } else if let Some(page) = self.stream.page_to_load() {
+            Operation::Load(page)
+        } else {
+            self.slice_operation()?
         };
-        for pair in buffer_tuple {
-            sample.buffer[sample.length] = *pair;
-            sample.length += 1;
+        let tape = self.tape.take().unwrap();
+        Some(StorageTask {
+            tape,
+            operation,
+            result: Ok(()),
+        })
+    }
+
+    fn finish_storage_task(&mut self, task: StorageTask) {
+        self.tape = Some(task.tape);
+        if let Err(error) = task.result {
+            defmt::error!("Storage task failed: {}", error);
+            self.error = Some(error);
+            self.notify(Notification::SetTitle(error.title()));
+        }
+        match task.operation {
+            Operation::Load(page) => self.stream.return_loaded_page(page),
+            Operation::Save(page) => self.stream.return_saved_page(page),
+            Operation::Open(_) if task.result.is_ok() => {
+                let tape = self.tape.as_ref().unwrap();
+                let source_rate = tape.sample_rate().unwrap_or(self.sample_rate);
+                self.resampler.reset(source_rate, self.sample_rate);
+                self.stream.replace_sample(tape.length());
+                self.set_slices(Slices::default());
+                self.slice_task = Some(SliceTask::Load);
+            }
+            Operation::Open(_) => (),
+            // Markers placed past the end of the sample would be lost, so
+            // they are loaded again
+            Operation::Clear if task.result.is_ok() => {
+                self.stream
+                    .replace_sample(self.tape.as_ref().unwrap().length());
+                self.set_slices(Slices::default());
+                self.slice_task = Some(SliceTask::Load);
+            }
+            Operation::Clear => (),
+            // Results of an analysis are dropped if another one was
+            // requested in the meantime
+            Operation::LoadSlices(slices) if task.result.is_ok() && self.slice_task.is_none() => {
+                match slices {
+                    Some(slices) => self.set_slices(slices),
+                    None => self.slice_task = Some(SliceTask::Slice),
+                }
+            }
+            Operation::LoadSlices(_) => (),
+            Operation::DetectSlices(detector, page) => {
+                if task.result.is_ok() && self.slice_task.is_none() {
+                    if page.length() == 0 || detector.position() >= self.stream.length() {
+                        self.set_slices(detector.finish());
+                        self.slices_dirty = true;
+                    } else {
+                        self.slice_task = Some(SliceTask::Detect(detector));
+                    }
+                }
+                self.scratch_page = Some(page);
+            }
+            Operation::SaveSlices(_) => (),
         }
     }
 
-    fat.close_file(&volume, file).unwrap();
-    fat.close_dir(&volume, root_dir);
+    fn notification(&mut self) -> Option<Notification> {
+        self.notifications.pop_front()
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Command {
-    SetVolume(f32),
-}
+impl Instrument {
+    fn process_looper(
+        &mut self,
+        buffer: &mut [(f32, f32)],
+        randomizer: &mut impl ProtonRandomizer,
+    ) {
+        for frame in buffer.iter_mut() {
+            let input = *frame;
+            let speed = self.speed.next() * self.wow_flutter.next(randomizer);
+            let volume = self.volume.next();
+            let drive = self.saturation.next();
+            let (left, right) = self
+                .resampler
+                .next_frame(speed, || self.stream.next_frame(input));
+            let (left, right) = self
+                .head
+                .process((saturate(left, drive), saturate(right, drive)));
+            *frame = (left * volume, right * volume);
+        }
+    }
 
-impl TryFrom<Reaction> for Command {
-    type Error = ();
+    // The sample is left aside, the delay records the input instead.
+    fn process_delay(&mut self, buffer: &mut [(f32, f32)], randomizer: &mut impl ProtonRandomizer) {
+        for frame in buffer.iter_mut() {
+            let input = *frame;
+            let speed = self.wow_flutter.next(randomizer);
+            let volume = self.volume.next();
+            let drive = self.saturation.next();
+            let mix = self.mix.next();
+            let wet = self.delay.process(input, speed, |(l, r)| {
+                (saturate(l, drive), saturate(r, drive))
+            });
+            let (left, right) = self.head.process(wet);
+            *frame = (
+                (input.0 + (left - input.0) * mix) * volume,
+                (input.1 + (right - input.1) * mix) * volume,
+            );
+        }
+    }
 
-    fn try_from(other: Reaction) -> Result<Self, Self::Error> {
-        match other {
-            Reaction::SetValue(VOLUME_ATTRIBUTE, value) => Ok(Command::SetVolume(value)),
-            _ => Err(()),
+    // Stopping freezes the captured history, grains keep playing from it.
+    // The pot is used to scan through the history, so the sample is captured
+    // at its original speed.
+    fn process_granular(
+        &mut self,
+        buffer: &mut [(f32, f32)],
+        randomizer: &mut impl ProtonRandomizer,
+    ) {
+        for frame in buffer.iter_mut() {
+            let input = *frame;
+            let speed = self.speed.next() * self.wow_flutter.next(randomizer);
+            let volume = self.volume.next();
+            let drive = self.saturation.next();
+            if self.play {
+                let captured = self
+                    .resampler
+                    .next_frame(speed, || self.stream.next_frame(input));
+                self.granular.capture(captured);
+            }
+            let (left, right) = self.granular.next_frame(randomizer);
+            let (left, right) = self
+                .head
+                .process((saturate(left, drive), saturate(right, drive)));
+            *frame = (left * volume, right * volume);
+        }
+    }
+
+    fn slice_operation(&mut self) -> Option<Operation> {
+        match self.slice_task.take()? {
+            SliceTask::Load => Some(Operation::LoadSlices(None)),
+            SliceTask::Slice => match self.slicing {
+                Slicing::Onsets => {
+                    let mut page = self.scratch_page.take().unwrap();
+                    page.reset(0);
+                    Some(Operation::DetectSlices(OnsetDetector::new(), page))
+                }
+                Slicing::Even(count) => {
+                    self.set_slices(Slices::even(self.stream.length(), count));
+                    Some(Operation::SaveSlices(self.slices))
+                }
+            },
+            SliceTask::Detect(detector) => {
+                let mut page = self.scratch_page.take().unwrap();
+                page.reset(detector.position());
+                Some(Operation::DetectSlices(detector, page))
+            }
+        }
+    }
+
+    // The slice is selected when triggered, so turning the knob or changing
+    // the CV does not cut off the slice that is playing.
+    fn restart(&mut self) {
+        if self.mode == Mode::Slice {
+            self.play_slice();
+        } else {
+            self.stream.reset();
+        }
+    }
+
+    fn play_slice(&mut self) {
+        let length = self.stream.length();
+        if length == 0 {
+            return;
+        }
+        let count = self.slices.count();
+        let index = (self.parameter_level(Parameter::Slice) * count as f32) as usize;
+        let (start, end) = self.slices.range(index, length);
+        self.stream
+            .set_loop(start as f32 / length as f32, end as f32 / length as f32);
+        self.stream.reset();
+    }
+
+    fn update_loop(&mut self) {
+        if self.mode != Mode::Slice {
+            self.stream.set_loop(self.loop_start, self.loop_end);
+        }
+    }
+
+    fn marker_position(&self, index: usize) -> f32 {
+        let length = self.stream.length();
+        if length > 0 {
+            self.slices.marker(index) as f32 / length as f32
+        } else {
+            0.0
+        }
+    }
+
+    fn marker_attribute(&self, index: usize) -> Attribute {
+        // Markers are stored next to the sample they belong to
+        Attribute::new(MARKER_ATTRIBUTES[index])
+            .with_value_f32(
+                ValueF32::new(self.marker_position(index))
+                    .with_step(0.001)
+                    .with_writter(marker_writter),
+            )
+            .with_persistent(false)
+            .with_page(MARKERS_PAGE)
+    }
+
+    // Markers found by the analysis or loaded from the card are shown in
+    // the UI, so they can be adjusted from there.
+    fn set_slices(&mut self, slices: Slices) {
+        self.slices = slices;
+        for (index, name) in MARKER_ATTRIBUTES.iter().enumerate() {
+            self.notify(Notification::SetValue(name, self.marker_position(index)));
+        }
+    }
+
+    // When the UI does not keep up, the oldest notification is dropped, as
+    // it is likely overridden by the newer ones anyway.
+    fn notify(&mut self, notification: Notification) {
+        if self.notifications.is_full() {
+            self.notifications.pop_front();
+        }
+        let _ = self.notifications.push_back(notification);
+    }
+
+    // Stopping slows the tape down to a halt, rather than cutting it off.
+    fn update_speed(&mut self) {
+        let speed = if self.mode == Mode::Granular {
+            1.0
+        } else {
+            let (min, max) = self.speed_range;
+            min + (max - min) * (1.0 - self.pot)
+        };
+        self.speed.set(if self.play { speed } else { 0.0 });
+    }
+
+    // While stopped, the last played frame is held by the resampler, so
+    // the output is faded out. Slices are heard only while their gate is
+    // high.
+    fn update_volume(&mut self) {
+        let audible = match self.mode {
+            Mode::Looper | Mode::Delay => self.play,
+            Mode::Granular => true,
+            Mode::Slice => self.play && self.reset_trigger.is_high(),
+        };
+        self.volume
+            .set(if audible { self.volume_level } else { 0.0 });
+    }
+
+    fn update_direction(&mut self) {
+        self.stream
+            .set_reverse(self.reverse != self.reverse_gate.is_high());
+    }
+
+    fn update_parameters(&mut self) {
+        self.wow_flutter.set(
+            self.parameter_level(Parameter::Wow),
+            self.parameter_level(Parameter::Flutter),
+        );
+        self.saturation
+            .set(self.parameter_level(Parameter::Saturation));
+        self.head.set(
+            self.parameter_level(Parameter::Bump),
+            self.parameter_level(Parameter::Loss),
+        );
+        for (i, parameter) in [Parameter::Head1, Parameter::Head2, Parameter::Head3]
+            .into_iter()
+            .enumerate()
+        {
+            self.delay.set_head(i, self.parameter_level(parameter));
         }
+        self.delay
+            .set_feedback(self.parameter_level(Parameter::Feedback));
+        self.mix.set(self.parameter_level(Parameter::Mix));
+        // In granular mode, the pot scans through the captured history
+        let pot = if self.mode == Mode::Granular {
+            self.pot
+        } else {
+            0.0
+        };
+        self.granular
+            .set_position((self.parameter_level(Parameter::Position) + pot).min(1.0));
+        self.granular
+            .set_size(self.parameter_level(Parameter::Size));
+        self.granular
+            .set_density(self.parameter_level(Parameter::Density));
+        self.granular
+            .set_jitter(self.parameter_level(Parameter::Jitter));
     }
+
+    // Level set in the UI, offset by CV inputs assigned to the parameter.
+    fn parameter_level(&self, parameter: Parameter) -> f32 {
+        let offset: f32 = self
+            .cv_destination
+            .iter()
+            .zip(self.assignable_cv)
+            .filter(|(destination, _)| **destination == Some(parameter))
+            .map(|(_, value)| value)
+            .sum();
+        (self.parameters[parameter as usize] + offset).clamp(0.0, 1.0)
+    }
+
+    fn update_recording(&mut self) {
+        let recording = if self.record || self.record_gate.is_high() {
+            self.record_mode
+        } else {
+            Recording::Off
+        };
+        self.stream.set_recording(recording);
+    }
+}
+
+impl StorageTaskTrait for StorageTask {
+    fn run(&mut self, sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>) {
+        self.result = match &mut self.operation {
+            Operation::Load(page) => self.tape.read_page(sd, page),
+            Operation::Save(page) => self.tape.write_page(sd, page),
+            Operation::Open(path) => {
+                let path: Vec<&str, MAX_DEPTH> = path.iter().map(|n| n.as_str()).collect();
+                Tape::open(sd, &path, PAGE_LENGTH).map(|tape| self.tape = tape)
+            }
+            Operation::Clear => self.tape.clear(sd),
+            Operation::LoadSlices(slices) => self.tape.read_slices(sd).map(|s| *slices = s),
+            Operation::DetectSlices(detector, page) => self
+                .tape
+                .read_page(sd, page)
+                .map(|_| detector.feed(page.frames())),
+            Operation::SaveSlices(slices) => self.tape.write_slices(sd, slices),
+        };
+    }
+}
+
+fn allocate_pages(memory_manager: &mut MemoryManager) -> [Page<'static>; PAGES] {
+    [(); PAGES].map(|_| Page::new(allocate_frames(memory_manager, PAGE_LENGTH), 0))
 }
 
-struct Sample {
-    pub buffer: &'static mut [(f32, f32)],
-    pub length: usize,
+fn allocate_frames(memory_manager: &mut MemoryManager, length: usize) -> &'static mut [(f32, f32)] {
+    let buffer_f32 = memory_manager.allocate(2 * length).unwrap();
+    let pointer = buffer_f32 as *mut _ as *mut (f32, f32);
+    unsafe { core::slice::from_raw_parts_mut::<(f32, f32)>(pointer, buffer_f32.len() / 2) }
 }
 
-impl Sample {
-    fn from_buffer(buffer_f32: &'static mut [f32]) -> Self {
-        let pointer = buffer_f32 as *const _ as *mut (f32, f32);
-        let buffer_tuple =
-            unsafe { core::slice::from_raw_parts_mut::<(f32, f32)>(pointer, buffer_f32.len() / 2) };
-        Self {
-            buffer: buffer_tuple,
-            length: 0,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Looper,
+    Delay,
+    Granular,
+    Slice,
+}
+
+/// Method used to place slice markers of samples that have none stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slicing {
+    Onsets,
+    Even(usize),
+}
+
+/// Continuous parameters, each of them set in the UI and optionally offset
+/// by a CV input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    Wow,
+    Flutter,
+    Saturation,
+    Bump,
+    Loss,
+    Head1,
+    Head2,
+    Head3,
+    Feedback,
+    Mix,
+    Position,
+    Size,
+    Density,
+    Jitter,
+    Slice,
+}
+
+const PARAMETERS: usize = Parameter::ALL.len();
+
+// Options of CV inputs, the off option is followed by names of attributes
+// of parameters they can be assigned to.
+const CV_DESTINATIONS: [&str; PARAMETERS + 1] = [
+    CV_OFF,
+    WOW_ATTRIBUTE,
+    FLUTTER_ATTRIBUTE,
+    SATURATION_ATTRIBUTE,
+    BUMP_ATTRIBUTE,
+    LOSS_ATTRIBUTE,
+    HEAD_1_ATTRIBUTE,
+    HEAD_2_ATTRIBUTE,
+    HEAD_3_ATTRIBUTE,
+    FEEDBACK_ATTRIBUTE,
+    MIX_ATTRIBUTE,
+    POSITION_ATTRIBUTE,
+    SIZE_ATTRIBUTE,
+    DENSITY_ATTRIBUTE,
+    JITTER_ATTRIBUTE,
+    SLICE_ATTRIBUTE,
+];
+
+impl Parameter {
+    const ALL: &'static [Self] = &[
+        Self::Wow,
+        Self::Flutter,
+        Self::Saturation,
+        Self::Bump,
+        Self::Loss,
+        Self::Head1,
+        Self::Head2,
+        Self::Head3,
+        Self::Feedback,
+        Self::Mix,
+        Self::Position,
+        Self::Size,
+        Self::Density,
+        Self::Jitter,
+        Self::Slice,
+    ];
+
+    // Level the parameter starts on, before it is set in the UI.
+    fn default_level(self) -> f32 {
+        match self {
+            Self::Head1 => 0.125,
+            Self::Feedback => 0.3,
+            Self::Mix | Self::Size | Self::Density => 0.5,
+            Self::Wow
+            | Self::Flutter
+            | Self::Saturation
+            | Self::Bump
+            | Self::Loss
+            | Self::Head2
+            | Self::Head3
+            | Self::Position
+            | Self::Jitter
+            | Self::Slice => 0.0,
         }
     }
-}
 
-fn upper_power_of_two(mut n: u32) -> u32 {
-    if n == 0 {
-        return 0;
+    fn default_levels() -> [f32; PARAMETERS] {
+        let mut levels = [0.0; PARAMETERS];
+        for parameter in Self::ALL {
+            levels[*parameter as usize] = parameter.default_level();
+        }
+        levels
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            WOW_ATTRIBUTE => Some(Self::Wow),
+            FLUTTER_ATTRIBUTE => Some(Self::Flutter),
+            SATURATION_ATTRIBUTE => Some(Self::Saturation),
+            BUMP_ATTRIBUTE => Some(Self::Bump),
+            LOSS_ATTRIBUTE => Some(Self::Loss),
+            HEAD_1_ATTRIBUTE => Some(Self::Head1),
+            HEAD_2_ATTRIBUTE => Some(Self::Head2),
+            HEAD_3_ATTRIBUTE => Some(Self::Head3),
+            FEEDBACK_ATTRIBUTE => Some(Self::Feedback),
+            MIX_ATTRIBUTE => Some(Self::Mix),
+            POSITION_ATTRIBUTE => Some(Self::Position),
+            SIZE_ATTRIBUTE => Some(Self::Size),
+            DENSITY_ATTRIBUTE => Some(Self::Density),
+            JITTER_ATTRIBUTE => Some(Self::Jitter),
+            SLICE_ATTRIBUTE => Some(Self::Slice),
+            _ => None,
+        }
     }
+}
 
-    n -= 1;
-    n |= n >> 1;
-    n |= n >> 2;
-    n |= n >> 4;
-    n |= n >> 8;
-    n |= n >> 16;
-    n += 1;
-    n
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    SetMode(Mode),
+    SetWindow(Window),
+    LoadSample(usize),
+    SetPlay(bool),
+    Restart,
+    SetVolume(f32),
+    SetSpeedRange(f32, f32),
+    SetReverse(bool),
+    SetLoopStart(f32),
+    SetLoopEnd(f32),
+    SetParameter(Parameter, f32),
+    AssignCv(usize, Option<Parameter>),
+    SetInterpolation(Interpolation),
+    SetRecord(bool),
+    SetRecordMode(Recording),
+    ClearRecording,
+    SetSlicing(Slicing),
+    SetMarker(usize, f32),
 }
 
-pub struct TimeSource;
+impl TryFrom<Reaction> for Command {
+    type Error = ();
 
-// This is just a placeholder TimeSource. In a real world application
-// one would probably use the RTC to provide time.
-impl embedded_sdmmc::TimeSource for TimeSource {
-    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
-        embedded_sdmmc::Timestamp {
-            year_since_1970: 0,
-            zero_indexed_month: 0,
-            zero_indexed_day: 0,
-            hours: 0,
-            minutes: 0,
-            seconds: 0,
+    fn try_from(other: Reaction) -> Result<Self, Self::Error> {
+        match other {
+            Reaction::SelectValue(MODE_ATTRIBUTE, MODE_LOOPER) => {
+                Ok(Command::SetMode(Mode::Looper))
+            }
+            Reaction::SelectValue(MODE_ATTRIBUTE, MODE_DELAY) => Ok(Command::SetMode(Mode::Delay)),
+            Reaction::SelectValue(MODE_ATTRIBUTE, MODE_GRANULAR) => {
+                Ok(Command::SetMode(Mode::Granular))
+            }
+            Reaction::SelectValue(MODE_ATTRIBUTE, MODE_SLICE) => Ok(Command::SetMode(Mode::Slice)),
+            Reaction::SelectFile(SAMPLE_ATTRIBUTE, index) => Ok(Command::LoadSample(index)),
+            Reaction::SetBool(PLAY_ATTRIBUTE, play) => Ok(Command::SetPlay(play)),
+            Reaction::Trigger(RESTART_ATTRIBUTE) => Ok(Command::Restart),
+            Reaction::SetValue(VOLUME_ATTRIBUTE, value) => Ok(Command::SetVolume(value)),
+            Reaction::SelectValue(SPEED_ATTRIBUTE, SPEED_HALF_TO_ONE) => {
+                Ok(Command::SetSpeedRange(0.5, 1.0))
+            }
+            Reaction::SelectValue(SPEED_ATTRIBUTE, SPEED_ZERO_TO_ONE) => {
+                Ok(Command::SetSpeedRange(0.0, 1.0))
+            }
+            Reaction::SelectValue(SPEED_ATTRIBUTE, SPEED_ZERO_TO_TWO) => {
+                Ok(Command::SetSpeedRange(0.0, 2.0))
+            }
+            Reaction::SelectValue(SPEED_ATTRIBUTE, SPEED_ZERO_TO_FOUR) => {
+                Ok(Command::SetSpeedRange(0.0, 4.0))
+            }
+            Reaction::SelectValue(DIRECTION_ATTRIBUTE, value) => {
+                Ok(Command::SetReverse(value == DIRECTION_REVERSE))
+            }
+            Reaction::SetValue(START_ATTRIBUTE, value) => Ok(Command::SetLoopStart(value)),
+            Reaction::SetValue(END_ATTRIBUTE, value) => Ok(Command::SetLoopEnd(value)),
+            Reaction::SetValue(WOW_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Wow, value))
+            }
+            Reaction::SetValue(FLUTTER_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Flutter, value))
+            }
+            Reaction::SetValue(SATURATION_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Saturation, value))
+            }
+            Reaction::SetValue(BUMP_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Bump, value))
+            }
+            Reaction::SetValue(LOSS_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Loss, value))
+            }
+            Reaction::SetValue(HEAD_1_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Head1, value))
+            }
+            Reaction::SetValue(HEAD_2_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Head2, value))
+            }
+            Reaction::SetValue(HEAD_3_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Head3, value))
+            }
+            Reaction::SetValue(FEEDBACK_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Feedback, value))
+            }
+            Reaction::SetValue(MIX_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Mix, value))
+            }
+            Reaction::SetValue(POSITION_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Position, value))
+            }
+            Reaction::SetValue(SIZE_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Size, value))
+            }
+            Reaction::SetValue(DENSITY_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Density, value))
+            }
+            Reaction::SetValue(JITTER_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Jitter, value))
+            }
+            Reaction::SetValue(SLICE_ATTRIBUTE, value) => {
+                Ok(Command::SetParameter(Parameter::Slice, value))
+            }
+            Reaction::SelectValue(SLICING_ATTRIBUTE, SLICING_ONSETS) => {
+                Ok(Command::SetSlicing(Slicing::Onsets))
+            }
+            Reaction::SelectValue(SLICING_ATTRIBUTE, SLICING_EVEN_2) => {
+                Ok(Command::SetSlicing(Slicing::Even(2)))
+            }
+            Reaction::SelectValue(SLICING_ATTRIBUTE, SLICING_EVEN_4) => {
+                Ok(Command::SetSlicing(Slicing::Even(4)))
+            }
+            Reaction::SelectValue(SLICING_ATTRIBUTE, SLICING_EVEN_8) => {
+                Ok(Command::SetSlicing(Slicing::Even(8)))
+            }
+            Reaction::SelectValue(WINDOW_ATTRIBUTE, WINDOW_HANN) => {
+                Ok(Command::SetWindow(Window::Hann))
+            }
+            Reaction::SelectValue(WINDOW_ATTRIBUTE, WINDOW_TRIANGLE) => {
+                Ok(Command::SetWindow(Window::Triangle))
+            }
+            Reaction::SelectValue(WINDOW_ATTRIBUTE, WINDOW_TRAPEZOID) => {
+                Ok(Command::SetWindow(Window::Trapezoid))
+            }
+            Reaction::SelectValue(CV_4_ATTRIBUTE, value) => {
+                Ok(Command::AssignCv(0, Parameter::from_name(value)))
+            }
+            Reaction::SelectValue(CV_5_ATTRIBUTE, value) => {
+                Ok(Command::AssignCv(1, Parameter::from_name(value)))
+            }
+            Reaction::SelectValue(INTERPOLATION_ATTRIBUTE, INTERPOLATION_LINEAR) => {
+                Ok(Command::SetInterpolation(Interpolation::Linear))
+            }
+            Reaction::SelectValue(INTERPOLATION_ATTRIBUTE, INTERPOLATION_HERMITE) => {
+                Ok(Command::SetInterpolation(Interpolation::Hermite))
+            }
+            Reaction::SelectValue(INTERPOLATION_ATTRIBUTE, INTERPOLATION_SINC) => {
+                Ok(Command::SetInterpolation(Interpolation::Sinc))
+            }
+            Reaction::SetBool(RECORD_ATTRIBUTE, record) => Ok(Command::SetRecord(record)),
+            Reaction::SelectValue(RECORD_MODE_ATTRIBUTE, RECORD_MODE_OVERDUB) => {
+                Ok(Command::SetRecordMode(Recording::Overdub))
+            }
+            Reaction::SelectValue(RECORD_MODE_ATTRIBUTE, RECORD_MODE_REPLACE) => {
+                Ok(Command::SetRecordMode(Recording::Replace))
+            }
+            Reaction::Trigger(CLEAR_ATTRIBUTE) => Ok(Command::ClearRecording),
+            Reaction::SetValue(name, value) => MARKER_ATTRIBUTES
+                .iter()
+                .position(|marker| *marker == name)
+                .map(|index| Command::SetMarker(index, value))
+                .ok_or(()),
+            _ => Err(()),
         }
     }
 }
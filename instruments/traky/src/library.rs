@@ -0,0 +1,93 @@
+//! Listing of samples available on the SD card.
+//!
+//! The card is scanned once on boot, directory by directory, collecting WAV
+//! files and directories into a flat list consumed by the UI browser.
+//! Directories nested deeper than the browser can reach are not entered.
+
+use core::fmt::Write as _;
+
+use embedded_sdmmc::blockdevice::BlockDevice;
+use embedded_sdmmc::{Controller, VolumeIdx};
+use heapless::{String, Vec};
+use proton_instruments_interface::sd::{volume_error, TimeSource};
+use proton_ui::browser::{Browser, Entry, MAX_DEPTH};
+
+use crate::tape::{open_directory, TapeError};
+
+const SAMPLE_EXTENSION: &[u8] = b"WAV";
+
+pub fn scan(
+    sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+) -> Result<Browser, TapeError> {
+    let mut fat = Controller::new(sd, TimeSource);
+    let volume = fat.get_volume(VolumeIdx(0)).map_err(volume_error)?;
+
+    let mut browser = Browser::new();
+    let mut full = false;
+
+    // Index of the scanned directory, None stands for the root. Entries of
+    // each scanned directory are appended to the end of the list, so the
+    // loop reaches them later.
+    let mut scanned = None;
+    loop {
+        let path = scanned.map_or_else(Vec::new, |index| browser.path(index));
+        let directories: Vec<&str, MAX_DEPTH> = path.iter().map(|n| n.as_str()).collect();
+        let directory =
+            open_directory(&mut fat, &volume, &directories).map_err(|_| TapeError::FileSystem)?;
+        let listed = fat.iterate_dir(&volume, &directory, |entry| {
+            let attributes = entry.attributes;
+            if attributes.is_volume() || attributes.is_hidden() || attributes.is_system() {
+                return;
+            }
+            let is_directory = attributes.is_directory();
+            if is_directory {
+                if entry.name.base_name().starts_with(b".") {
+                    return;
+                }
+            } else if entry.name.extension() != SAMPLE_EXTENSION {
+                return;
+            }
+
+            let mut name = String::<12>::new();
+            write!(name, "{}", entry.name).unwrap();
+            let entry = if is_directory {
+                Entry::directory(&name, scanned)
+            } else {
+                Entry::file(&name, scanned)
+            };
+            full |= browser.push(entry).is_err();
+        });
+        fat.close_dir(&volume, directory);
+        listed.map_err(|_| TapeError::FileSystem)?;
+
+        // Leave space for the file at the end of the path
+        let next = browser
+            .entries
+            .iter()
+            .enumerate()
+            .skip(scanned.map_or(0, |index| index + 1))
+            .find(|(i, e)| e.is_directory && browser.path(*i).len() < MAX_DEPTH - 1);
+        match next {
+            Some((index, _)) => scanned = Some(index),
+            None => break,
+        }
+    }
+
+    if full {
+        defmt::warn!("Too many files on SD card, some are not listed");
+    }
+
+    Ok(browser)
+}
+
+/// Index of the entry on the given path, if it was listed.
+pub fn find(browser: &Browser, path: &[&str]) -> Option<usize> {
+    (0..browser.entries.len()).find(|index| {
+        let entry_path = browser.path(*index);
+        entry_path.len() == path.len()
+            && entry_path
+                .iter()
+                .zip(path)
+                .all(|(a, b)| a.as_str().eq_ignore_ascii_case(b))
+    })
+}
@@ -0,0 +1,56 @@
+//! Trigonometric functions precise around zero.
+//!
+//! The sine of micromath is off by up to 1e-3 even around zero. That is fine
+//! for modulation, but not when the result gets divided by a small number,
+//! like in sinc, or when it sets coefficients of a filter tuned far below
+//! the sample rate.
+
+use core::f32::consts::PI;
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+/// Odd polynomial keeping the error relative to the result.
+pub fn sin(x: f32) -> f32 {
+    let x = x - 2.0 * PI * (x / (2.0 * PI)).round();
+    let x = if x > PI / 2.0 {
+        PI - x
+    } else if x < -PI / 2.0 {
+        -PI - x
+    } else {
+        x
+    };
+    let x2 = x * x;
+    x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))))
+}
+
+pub fn sin_cos(x: f32) -> (f32, f32) {
+    (sin(x), sin(x + PI / 2.0))
+}
+
+pub fn tan(x: f32) -> f32 {
+    let (sin, cos) = sin_cos(x);
+    sin / cos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_argument_is_small_it_keeps_relative_precision() {
+        for x in [1e-5, 1e-3, 0.1] {
+            assert!((sin(x) - x).abs() < x * x);
+            assert!((tan(x) - x).abs() < x * x);
+        }
+    }
+
+    #[test]
+    fn when_argument_is_out_of_first_period_it_wraps_around() {
+        for x in [-7.0, -2.0, 0.5, 3.0, 10.0] {
+            let (sin, cos) = sin_cos(x);
+            assert!((sin * sin + cos * cos - 1.0).abs() < 1e-4);
+            assert!((sin - super::sin(x + 2.0 * PI)).abs() < 1e-4);
+        }
+    }
+}
@@ -1,18 +1,5 @@
 //! Manipulate persistent buffers with lengths that are exceeding memory capacity.
 //!
-//! # TODO
-//!
-//! * Rename recording to writing
-//! * Rename paginated buffer to something better... cursor? scroller?
-//! * Rewrite this so it contains the whole subsystem of "buffer"
-//! * Separate modules and unit tests for page, paginated_buffer, caller, cacher
-//! * Root would contain e2e tests that would exercise the full subsystem
-//! * Instead of running in multiple rutines, it would keep global queues and
-//!   instances. It would then run every piece of the test in a scope {} and
-//!   pass data over those queues
-//! * SD card interaction would be also faked, but without much abstraction since
-//!   proper SD abstraction with messages will be defined only later
-//!
 //! # Requirements
 //!
 //! * Can handle loops from 32 sample length up to tens of minutes.
@@ -20,105 +7,235 @@
 //! * Recorded audio is being saved even while recording is in progress.
 //! * Can immediatelly jump to the beginning of the sample and start playing.
 //! * Saving and loading can be done in another routine.
-//!
-//! # Architecture
-//!
-//! * No pages are owned by the buffer.
-//! * Caller is responsible for:
-//!   * Providing new empty pages on request.
-//!   * Persisting returned buffers.
-//!   * Doing the two listed above with RT guarantees.
-//! * Each of the page contains:
-//!   * Fixed-size array of data.
-//!   * "Dirty" flag.
-//!   * Length of recorded data.
-//!   * Start address, relative to the parent sample.
-//!
-//! # Flow starting from fresh
-//!
-//! 1. Caller initializes empty page on Pool and passes Box to the buffer.
-//! 2. Buffer stores the Box in its struct.
-//! 3. Caller passes input audio, info about armed channels.
-//! 4. Buffer writes the audio into its active page and returns output audio.
-//! 5. Caller asks the buffer whether it is full, if it is, it takes its page and passes
-//!    a fresh one to it again.
-//! 6. Since this was the first page, caller stores it in its cache.
-//! 7. Caller passess the dirty page to SD save queue.
-//! 8. Buffer continues recording, until its full again, swaps the page.
-//! 9. Caller passes the dirty page to save queue.
-//! 10. This continues for some time, until position reset is triggered.
-//! 11. With reset armed, caller will force buffer to return its current buffer,
-//!     and it will pass a clone of the start page to it.
-//! 12. Caller recognizes that the next page is available on SD, it will send
-//!     a request for SD loader to pull it. It should be eventually available
-//!     in a loaded queue.
-//! 13. The loaded page is then passed to buffer instead of empty pages used before.
-//! 14. At some point, midway through the sample, recording stops.
-//! 15. Any new samples will be returned like before, except now they will not
-//!     be dirty and thus just thrown away.
-//!
-//! # Flow starting from a loaded sample
-//!
-//! 1. The caller recognizes there is a sample available and it reads its length.
-//! 2. The caller loads the first page, queues fetching of the second one, if there is one.
-//! 3. The caller passes the first page to the buffer.
-//! 4. Business as usual.
-//!
-//! # Working with samples shorter than a single page
-//!
-//! 1. Buffer gets an inpulse to reset midway through the first page.
-//! 2. The caller takes page from the buffer, clones it for save queue, clones it for
-//!    its own cache and passes it back to the buffer.
-//!
-//! # Optimizations
-//!
-//! * There may be a queue of empty pages, initialized in the background so they
-//!   are readily available.
 
-struct PaginatedBuffer {
-    active_page: Option<Page>,
+pub struct PaginatedBuffer<'a> {
+    active_page: Option<Page<'a>>,
+    position: usize,
+    range: (usize, usize),
+    reverse: bool,
     recording: bool,
+    overdub: bool,
+    bounded: bool,
 }
 
-impl PaginatedBuffer {
-    fn new() -> Self {
+impl<'a> PaginatedBuffer<'a> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
         Self {
             active_page: None,
+            position: 0,
+            range: (0, usize::MAX),
+            reverse: false,
             recording: false,
+            overdub: false,
+            bounded: false,
         }
     }
 
-    fn set_page(&mut self, page: Page) {
+    pub fn set_page(&mut self, page: Page<'a>) {
         self.active_page = Some(page);
+        self.position = 0;
+        self.range = (0, usize::MAX);
     }
 
-    fn take_page(&mut self) -> Page {
+    /// Move within the active page. In reverse, the frame preceding the
+    /// position is played next.
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    /// Limit the playback to a part of the active page.
+    pub fn set_range(&mut self, start: usize, end: usize) {
+        self.range = (start, end);
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    pub fn take_page(&mut self) -> Page<'a> {
         self.active_page.take().unwrap()
     }
 
-    fn arm_recording(&mut self, record: bool) {
+    pub fn has_page(&self) -> bool {
+        self.active_page.is_some()
+    }
+
+    pub fn page(&self) -> Option<&Page<'a>> {
+        self.active_page.as_ref()
+    }
+
+    pub fn page_mut(&mut self) -> Option<&mut Page<'a>> {
+        self.active_page.as_mut()
+    }
+
+    pub fn arm_recording(&mut self, record: bool) {
         self.recording = record;
     }
 
-    fn process(&self, _buffer: &mut [f32]) {}
+    /// Mix the input with the content of the page instead of replacing it.
+    pub fn set_overdub(&mut self, overdub: bool) {
+        self.overdub = overdub;
+    }
 
-    fn is_page_full(&self) -> bool {
-        false
+    /// Keep the recording within the current length of the page.
+    pub fn set_bounded(&mut self, bounded: bool) {
+        self.bounded = bounded;
     }
 
-    fn reset_position(&self) {}
+    /// Replace input audio in the buffer with the content of the active page.
+    ///
+    /// If recording is armed, the input audio is written into the page. The
+    /// processing stops once the end of the page is reached. Returns the
+    /// number of processed frames, so the caller can swap pages and process
+    /// the rest of the buffer.
+    pub fn process(&mut self, buffer: &mut [(f32, f32)]) -> usize {
+        let end = self.page_end();
+        let (recording, overdub, reverse) = (self.recording, self.overdub, self.reverse);
+        let page = match self.active_page.as_mut() {
+            Some(page) => page,
+            None => return 0,
+        };
+
+        let frames = if reverse {
+            buffer.len().min(self.position.saturating_sub(self.range.0))
+        } else {
+            buffer.len().min(end.saturating_sub(self.position))
+        };
+        for frame in buffer[..frames].iter_mut() {
+            if reverse {
+                self.position -= 1;
+            }
+            let position = self.position;
+
+            let played = if position < page.length {
+                page.data[position]
+            } else {
+                (0.0, 0.0)
+            };
+
+            if recording {
+                page.data[position] = if overdub {
+                    (played.0 + frame.0, played.1 + frame.1)
+                } else {
+                    *frame
+                };
+                page.length = page.length.max(position + 1);
+                page.dirty = true;
+            }
+
+            *frame = played;
+            if !reverse {
+                self.position += 1;
+            }
+        }
+
+        frames
+    }
+
+    /// Whether the playback reached the end of the page, or its start when
+    /// in reverse.
+    pub fn is_page_full(&self) -> bool {
+        if self.reverse {
+            self.position <= self.range.0
+        } else {
+            self.position >= self.page_end()
+        }
+    }
+
+    // While recording, the page can be filled up to its capacity. During
+    // playback, it ends with the last recorded frame. This makes the last
+    // page of a sample shorter than the rest.
+    fn page_end(&self) -> usize {
+        match &self.active_page {
+            Some(page) if self.recording && !self.bounded => page.capacity(),
+            Some(page) => page.length.min(self.range.1),
+            None => 0,
+        }
+    }
+}
+
+/// Portion of a sample, backed by memory provided by the caller.
+pub struct Page<'a> {
+    data: &'a mut [(f32, f32)],
+    dirty: bool,
+    length: usize,
+    start: usize,
 }
 
-#[derive(Clone)]
-struct Page {}
+impl<'a> Page<'a> {
+    pub fn new(data: &'a mut [(f32, f32)], start: usize) -> Self {
+        Self {
+            data,
+            dirty: false,
+            length: 0,
+            start,
+        }
+    }
+
+    /// Reuse the memory of the page for another portion of the sample.
+    pub fn reset(&mut self, start: usize) {
+        self.dirty = false;
+        self.length = 0;
+        self.start = start;
+    }
+
+    #[cfg(test)]
+    pub fn load(&mut self, start: usize, frames: &[(f32, f32)]) {
+        assert!(frames.len() <= self.capacity(), "frames do not fit");
+        self.reset(start);
+        self.fill(|data| {
+            data[..frames.len()].copy_from_slice(frames);
+            frames.len()
+        });
+    }
+
+    /// Let the callback write into the whole memory of the page. It returns
+    /// the number of frames that were written.
+    pub fn fill(&mut self, fill: impl FnOnce(&mut [(f32, f32)]) -> usize) {
+        self.length = fill(self.data).min(self.capacity());
+    }
+
+    /// Continue copying the content of another page, at most given number
+    /// of frames at once. Returns true once the copy is complete.
+    pub fn copy_from(&mut self, source: &Page, frames: usize) -> bool {
+        let from = self.length;
+        let to = (from + frames).min(source.length);
+        self.data[from..to].copy_from_slice(&source.data[from..to]);
+        self.length = to;
+        self.length == source.length
+    }
+
+    pub fn frames(&self) -> &[(f32, f32)] {
+        &self.data[..self.length]
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
 
-impl Page {
-    fn new() -> Self {
-        Self {}
+    pub fn capacity(&self) -> usize {
+        self.data.len()
     }
 
-    fn is_dirty(&self) -> bool {
-        false
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
     }
 }
 
@@ -126,17 +243,73 @@ impl Page {
 mod tests {
     use super::*;
 
+    const PAGE_LENGTH: usize = 64;
+    const BLOCK_LENGTH: usize = 32;
+
+    type Memory = [(f32, f32); PAGE_LENGTH];
+
+    struct Sd {
+        pages: [Memory; 4],
+        lengths: [usize; 4],
+    }
+
+    impl Sd {
+        fn new() -> Self {
+            Self {
+                pages: [[(0.0, 0.0); PAGE_LENGTH]; 4],
+                lengths: [0; 4],
+            }
+        }
+
+        // Simulates saving on SD card, after which the page is in sync with
+        // its persisted copy.
+        fn save(&mut self, index: usize, page: &mut Page) {
+            self.pages[index][..page.length()].copy_from_slice(page.frames());
+            self.lengths[index] = page.length();
+            page.mark_clean();
+        }
+
+        fn load(&self, index: usize, page: &mut Page) {
+            page.load(
+                index * PAGE_LENGTH,
+                &self.pages[index][..self.lengths[index]],
+            );
+        }
+    }
+
+    fn block(offset: usize) -> [(f32, f32); BLOCK_LENGTH] {
+        let mut block = [(0.0, 0.0); BLOCK_LENGTH];
+        for (i, frame) in block.iter_mut().enumerate() {
+            *frame = frame_at(offset + i);
+        }
+        block
+    }
+
+    fn frame_at(index: usize) -> (f32, f32) {
+        (index as f32, -(index as f32))
+    }
+
+    fn assert_frames(frames: &[(f32, f32)], offset: usize) {
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(*frame, frame_at(offset + i));
+        }
+    }
+
+    fn assert_silence(frames: &[(f32, f32)]) {
+        assert!(frames.iter().all(|f| *f == (0.0, 0.0)));
+    }
+
     #[test]
     fn full_flow_starting_from_nothing_with_long_recording() {
-        // TODO: Pass Page reference token (pointing to a place in a memory singleton)
-        let mut sd: [Option<Page>; 4] = [None, None, None, None];
-        let mut page_1_cache: Option<Page> = None;
+        let mut memory = [[(0.0, 0.0); PAGE_LENGTH]; 3];
+        let [memory_1, memory_2, memory_3] = &mut memory;
+        let mut sd = Sd::new();
 
         let mut buffer = PaginatedBuffer::new();
 
         // 1. Caller initializes empty page on Pool and passes Box to the buffer.
         // 2. Buffer stores the Box in its struct.
-        let page_1 = Page::new();
+        let page_1 = Page::new(memory_1, 0);
         buffer.set_page(page_1);
 
         // 3. Caller passes input audio, info about armed channels.
@@ -144,79 +317,270 @@ mod tests {
         // 5. Caller asks the buffer whether it is full, if it is, it takes its page and passes
         //    a fresh one to it again.
         buffer.arm_recording(true);
-        let mut recorded_audio = [0.0; 32];
+        let mut offset = 0;
         loop {
-            buffer.process(&mut recorded_audio);
+            let mut audio = block(offset);
+            assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH);
+            assert_silence(&audio);
+            offset += BLOCK_LENGTH;
             if buffer.is_page_full() {
                 break;
             }
         }
-        let page_1 = buffer.take_page();
-        let page_2 = Page::new();
+        let mut page_1 = buffer.take_page();
+        let page_2 = Page::new(memory_2, PAGE_LENGTH);
         buffer.set_page(page_2);
+        assert_eq!(page_1.length(), PAGE_LENGTH);
+        assert_frames(page_1.frames(), 0);
 
         // 6. Since this was the first page, caller stores it in its cache.
         // 7. Caller passess the dirty page to SD save queue.
-        if page_1.is_dirty() {
-            sd[0] = Some(page_1.clone());
-            page_1_cache = Some(page_1);
-        }
+        assert!(page_1.is_dirty());
+        sd.save(0, &mut page_1);
+        let page_1_cache = page_1;
 
         // 8. Buffer continues recording, until its full again, swaps the page.
         // 9. Caller passes the dirty page to save queue.
-        buffer.arm_recording(true);
-        let mut recorded_audio = [0.0; 32];
         loop {
-            buffer.process(&mut recorded_audio);
+            let mut audio = block(offset);
+            assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH);
+            assert_silence(&audio);
+            offset += BLOCK_LENGTH;
             if buffer.is_page_full() {
                 break;
             }
         }
-        let page_2 = buffer.take_page();
-        let page_3 = Page::new();
+        let mut page_2 = buffer.take_page();
+        let page_3 = Page::new(memory_3, 2 * PAGE_LENGTH);
         buffer.set_page(page_3);
-        if page_2.is_dirty() {
-            sd[1] = Some(page_2);
-        }
+        assert!(page_2.is_dirty());
+        assert_eq!(page_2.start(), PAGE_LENGTH);
+        assert_frames(page_2.frames(), PAGE_LENGTH);
+        sd.save(1, &mut page_2);
 
         // 10. This continues for some time, until position reset is triggered.
-        buffer.arm_recording(true);
-        let mut recorded_audio = [0.0; 32];
-        buffer.process(&mut recorded_audio);
-        buffer.process(&mut recorded_audio);
-        buffer.process(&mut recorded_audio);
-        buffer.reset_position();
+        let mut audio = block(offset);
+        buffer.process(&mut audio);
+        assert!(!buffer.is_page_full());
+        buffer.set_position(0);
 
         // 11. With reset armed, caller will force buffer to return its current buffer,
         //     and it will pass a clone of the start page to it.
-        let page_3 = buffer.take_page();
-        if page_3.is_dirty() {
-            sd[2] = Some(page_3);
-        }
-        buffer.set_page(page_1_cache.unwrap());
+        let mut page_3 = buffer.take_page();
+        assert!(page_3.is_dirty());
+        assert_eq!(page_3.length(), BLOCK_LENGTH);
+        assert_frames(page_3.frames(), 2 * PAGE_LENGTH);
+        sd.save(2, &mut page_3);
+        buffer.set_page(page_1_cache);
 
         // 12. Caller recognizes that the next page is available on SD, it will send
         //     a request for SD loader to pull it. It should be eventually available
         //     in a loaded queue.
-        let page_2 = sd[1].clone().unwrap();
+        let mut next_page = page_3;
+        sd.load(1, &mut next_page);
+        assert!(!next_page.is_dirty());
+
+        // The start page is played back while it is being overwritten.
+        let mut offset = 1000;
+        loop {
+            let mut audio = block(offset);
+            buffer.process(&mut audio);
+            assert_frames(&audio, offset - 1000);
+            offset += BLOCK_LENGTH;
+            if buffer.is_page_full() {
+                break;
+            }
+        }
+        let mut page_1 = buffer.take_page();
+        assert!(page_1.is_dirty());
+        assert_frames(page_1.frames(), 1000);
+        sd.save(0, &mut page_1);
 
         // 13. The loaded page is then passed to buffer instead of empty pages used before.
-        buffer.set_page(page_2);
+        buffer.set_page(next_page);
 
         // 14. At some point, midway through the sample, recording stops.
-        buffer.arm_recording(true);
-        let mut recorded_audio = [0.0; 32];
-        buffer.process(&mut recorded_audio);
-        buffer.process(&mut recorded_audio);
+        let mut audio = block(offset);
+        buffer.process(&mut audio);
+        assert_frames(&audio, PAGE_LENGTH);
         buffer.arm_recording(false);
 
         // 15. Any new samples will be returned like before, except now they will not
         //     be dirty and thus just thrown away.
+        let mut audio = block(offset + BLOCK_LENGTH);
+        buffer.process(&mut audio);
+        assert_frames(&audio, PAGE_LENGTH + BLOCK_LENGTH);
+        assert!(buffer.is_page_full());
+        let mut page_2 = buffer.take_page();
+        assert!(page_2.is_dirty());
+        assert_frames(&page_2.frames()[..BLOCK_LENGTH], offset);
+        assert_frames(&page_2.frames()[BLOCK_LENGTH..], PAGE_LENGTH + BLOCK_LENGTH);
+        sd.save(1, &mut page_2);
+
+        let mut next_page = page_1;
+        sd.load(2, &mut next_page);
+        buffer.set_page(next_page);
+        let mut audio = block(0);
+        assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH);
+        assert_frames(&audio, 2 * PAGE_LENGTH);
+        assert!(buffer.is_page_full());
+        assert!(!buffer.take_page().is_dirty());
+        assert_eq!(sd.lengths[3], 0);
+    }
+
+    #[test]
+    fn full_flow_starting_with_existing_long_sample() {
+        const SAMPLE_LENGTH: usize = 2 * PAGE_LENGTH + BLOCK_LENGTH / 2;
+        let mut sd = Sd::new();
+        for (i, page) in sd.pages.iter_mut().enumerate() {
+            for (j, frame) in page.iter_mut().enumerate() {
+                *frame = frame_at(i * PAGE_LENGTH + j);
+            }
+        }
+        sd.lengths = [PAGE_LENGTH, PAGE_LENGTH, BLOCK_LENGTH / 2, 0];
+        let mut memory = [[(0.0, 0.0); PAGE_LENGTH]; 2];
+        let [memory_1, memory_2] = &mut memory;
+
+        let mut buffer = PaginatedBuffer::new();
+
+        // 1. The caller recognizes there is a sample available and it reads its length.
+        let length: usize = sd.lengths.iter().sum();
+        let pages = sd.lengths.iter().filter(|l| **l > 0).count();
+        assert_eq!(length, SAMPLE_LENGTH);
+
+        // 2. The caller loads the first page, queues fetching of the second one, if there is one.
+        let mut page = Page::new(memory_1, 0);
+        sd.load(0, &mut page);
+        let mut next_page = Page::new(memory_2, 0);
+        sd.load(1, &mut next_page);
+        let mut next_index = 2;
+
+        // 3. The caller passes the first page to the buffer.
+        buffer.set_page(page);
+
+        // 4. Business as usual.
+        let mut played = [(0.0, 0.0); 2 * SAMPLE_LENGTH];
+        let mut next_page = Some(next_page);
+        for audio in played.chunks_mut(BLOCK_LENGTH / 2) {
+            let mut processed = 0;
+            while processed < audio.len() {
+                processed += buffer.process(&mut audio[processed..]);
+                if buffer.is_page_full() {
+                    let mut page = buffer.take_page();
+                    assert!(!page.is_dirty());
+                    buffer.set_page(next_page.take().unwrap());
+                    sd.load(next_index % pages, &mut page);
+                    next_page = Some(page);
+                    next_index += 1;
+                }
+            }
+        }
+
+        assert_frames(&played[..SAMPLE_LENGTH], 0);
+        assert_frames(&played[SAMPLE_LENGTH..], 0);
+    }
+
+    #[test]
+    fn full_flow_starting_from_nothing_with_short_sample() {
+        let recording_length = BLOCK_LENGTH + BLOCK_LENGTH / 4;
+        let mut memory = [(0.0, 0.0); PAGE_LENGTH];
+        let mut sd = Sd::new();
+
+        let mut buffer = PaginatedBuffer::new();
+        buffer.set_page(Page::new(&mut memory, 0));
+        buffer.arm_recording(true);
+        let mut audio = block(0);
+        buffer.process(&mut audio);
+        let mut audio = block(BLOCK_LENGTH);
+        buffer.process(&mut audio[..BLOCK_LENGTH / 4]);
+
+        // 1. Buffer gets an inpulse to reset midway through the first page.
+        buffer.set_position(0);
+        buffer.arm_recording(false);
+
+        // 2. The caller takes page from the buffer, clones it for save queue, clones it for
+        //    its own cache and passes it back to the buffer.
+        let mut page = buffer.take_page();
+        assert!(page.is_dirty());
+        assert_eq!(page.length(), recording_length);
+        sd.save(0, &mut page);
+        buffer.set_page(page);
+
+        // The recorded loop is then played back repeatedly.
+        for _ in 0..3 {
+            let mut audio = block(1000);
+            assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH);
+            assert_frames(&audio, 0);
+            let mut audio = block(1000);
+            assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH / 4);
+            assert_frames(&audio[..BLOCK_LENGTH / 4], BLOCK_LENGTH);
+            assert!(buffer.is_page_full());
+            buffer.set_position(0);
+        }
+
+        assert_eq!(sd.lengths[0], recording_length);
+        assert_frames(&sd.pages[0][..recording_length], 0);
+    }
+
+    #[test]
+    fn when_overdubbing_bounded_page_it_mixes_input_and_keeps_length() {
+        let mut memory = [(0.0, 0.0); PAGE_LENGTH];
+        let mut page = Page::new(&mut memory, 0);
+        page.load(0, &block(0));
+
+        let mut buffer = PaginatedBuffer::new();
+        buffer.set_page(page);
+        buffer.arm_recording(true);
+        buffer.set_overdub(true);
+        buffer.set_bounded(true);
+
+        let mut audio = [(1.0, 1.0); BLOCK_LENGTH * 2];
+        assert_eq!(buffer.process(&mut audio), BLOCK_LENGTH);
+        assert_frames(&audio[..BLOCK_LENGTH], 0);
+        assert!(buffer.is_page_full());
+
+        let page = buffer.take_page();
+        assert!(page.is_dirty());
+        assert_eq!(page.length(), BLOCK_LENGTH);
+        for (i, frame) in page.frames().iter().enumerate() {
+            assert_eq!(*frame, (i as f32 + 1.0, -(i as f32) + 1.0));
+        }
     }
 
     #[test]
-    fn full_flow_starting_with_existing_long_sample() {}
+    fn when_copying_page_in_steps_it_ends_with_identical_content() {
+        let mut memory = [[(0.0, 0.0); PAGE_LENGTH]; 2];
+        let [memory_1, memory_2] = &mut memory;
+        let mut source = Page::new(memory_1, 0);
+        source.load(0, &block(0));
+        let mut copy = Page::new(memory_2, 0);
+
+        let mut steps = 1;
+        while !copy.copy_from(&source, 5) {
+            steps += 1;
+        }
+
+        assert_eq!(steps, BLOCK_LENGTH.div_ceil(5));
+        assert_eq!(copy.length(), BLOCK_LENGTH);
+        assert_frames(copy.frames(), 0);
+    }
 
     #[test]
-    fn full_flow_starting_from_nothing_with_short_sample() {}
+    fn when_playing_in_reverse_it_stops_at_start_of_range() {
+        let mut memory = [(0.0, 0.0); PAGE_LENGTH];
+        let mut page = Page::new(&mut memory, 0);
+        page.load(0, &block(0));
+        let mut buffer = PaginatedBuffer::new();
+        buffer.set_page(page);
+        buffer.set_reverse(true);
+        buffer.set_position(BLOCK_LENGTH);
+        buffer.set_range(4, BLOCK_LENGTH);
+
+        let mut frames = [(0.0, 0.0); BLOCK_LENGTH];
+        assert_eq!(buffer.process(&mut frames), BLOCK_LENGTH - 4);
+        assert!(buffer.is_page_full());
+        for (i, frame) in frames[..BLOCK_LENGTH - 4].iter().enumerate() {
+            assert_eq!(*frame, frame_at(BLOCK_LENGTH - 1 - i));
+        }
+    }
 }
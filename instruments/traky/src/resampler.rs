@@ -0,0 +1,336 @@
+//! Convert the sample rate of the streamed sample to the one of the codec.
+//!
+//! Samples are too long to be converted on load, so the conversion happens
+//! during streaming. The same interpolation is used to change the playback
+//! speed.
+
+use core::f32::consts::PI;
+
+use crate::math::sin_cos;
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+// Number of source frames kept around the interpolated position. The
+// position lies between the two frames in the middle.
+const TAPS: usize = 16;
+const MIDDLE: usize = TAPS / 2 - 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Linear interpolation between two neighbouring frames.
+    Linear,
+    /// Cubic Hermite interpolation over four neighbouring frames.
+    Hermite,
+    /// Windowed sinc over all the kept frames. When the source is read
+    /// faster than the target rate, the cutoff of the filter is lowered
+    /// accordingly, so the playback does not alias.
+    Sinc,
+}
+
+pub struct Resampler {
+    ratio: f32,
+    interpolation: Interpolation,
+    // Frames around the current position, from the oldest.
+    frames: [(f32, f32); TAPS],
+    fraction: f32,
+}
+
+impl Resampler {
+    pub fn new(source_rate: u32, target_rate: u32) -> Self {
+        Self {
+            ratio: source_rate as f32 / target_rate as f32,
+            interpolation: Interpolation::Hermite,
+            frames: [(0.0, 0.0); TAPS],
+            fraction: 0.0,
+        }
+    }
+
+    /// Start over with a source of another sample rate.
+    pub fn reset(&mut self, source_rate: u32, target_rate: u32) {
+        self.ratio = source_rate as f32 / target_rate as f32;
+        self.frames = [(0.0, 0.0); TAPS];
+        self.fraction = 0.0;
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// Interpolate the following frame, pulling as many frames from the
+    /// source as needed. Speed of 1.0 keeps the original pitch.
+    pub fn next_frame(&mut self, speed: f32, mut source: impl FnMut() -> (f32, f32)) -> (f32, f32) {
+        let step = self.ratio * speed;
+        let t = self.fraction;
+        let frame = match self.interpolation {
+            Interpolation::Linear => {
+                let [b, c] = [self.frames[MIDDLE], self.frames[MIDDLE + 1]];
+                (linear(b.0, c.0, t), linear(b.1, c.1, t))
+            }
+            Interpolation::Hermite => {
+                let [a, b, c, d] = [
+                    self.frames[MIDDLE - 1],
+                    self.frames[MIDDLE],
+                    self.frames[MIDDLE + 1],
+                    self.frames[MIDDLE + 2],
+                ];
+                (
+                    hermite(a.0, b.0, c.0, d.0, t),
+                    hermite(a.1, b.1, c.1, d.1, t),
+                )
+            }
+            Interpolation::Sinc => sinc(&self.frames, t, (1.0 / step).min(1.0)),
+        };
+
+        self.fraction += step;
+        while self.fraction >= 1.0 {
+            self.fraction -= 1.0;
+            self.frames.rotate_left(1);
+            self.frames[TAPS - 1] = source();
+        }
+
+        frame
+    }
+}
+
+fn linear(x0: f32, x1: f32, t: f32) -> f32 {
+    x0 + (x1 - x0) * t
+}
+
+fn hermite(xm1: f32, x0: f32, x1: f32, x2: f32, t: f32) -> f32 {
+    let c1 = 0.5 * (x1 - xm1);
+    let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+    let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+    ((c3 * t + c2) * t + c1) * t + x0
+}
+
+// Sinc with the given cutoff relative to the source Nyquist frequency,
+// shaped by a Hann window. Sines of consecutive taps are calculated by
+// rotation, so only the first tap needs the costly approximation. The
+// result is normalized by the sum of the weights to keep the DC gain.
+fn sinc(frames: &[(f32, f32); TAPS], t: f32, cutoff: f32) -> (f32, f32) {
+    let half_width = (TAPS / 2) as f32;
+    let x0 = -(MIDDLE as f32) - t;
+
+    let (mut sinc_sin, mut sinc_cos) = sin_cos(PI * cutoff * x0);
+    let (sinc_step_sin, sinc_step_cos) = sin_cos(PI * cutoff);
+    let (mut window_sin, mut window_cos) = sin_cos(PI * x0 / half_width);
+    let (window_step_sin, window_step_cos) = sin_cos(PI / half_width);
+
+    let mut sum = (0.0, 0.0);
+    let mut weights = 0.0;
+    for (i, frame) in frames.iter().enumerate() {
+        let x = x0 + i as f32;
+        let sinc = if x.abs() < 1e-4 {
+            1.0
+        } else {
+            sinc_sin / (PI * cutoff * x)
+        };
+        let weight = sinc * (0.5 + 0.5 * window_cos);
+        sum.0 += frame.0 * weight;
+        sum.1 += frame.1 * weight;
+        weights += weight;
+
+        (sinc_sin, sinc_cos) = (
+            sinc_sin * sinc_step_cos + sinc_cos * sinc_step_sin,
+            sinc_cos * sinc_step_cos - sinc_sin * sinc_step_sin,
+        );
+        (window_sin, window_cos) = (
+            window_sin * window_step_cos + window_cos * window_step_sin,
+            window_cos * window_step_cos - window_sin * window_step_sin,
+        );
+    }
+
+    (sum.0 / weights, sum.1 / weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(unused_imports)]
+    use micromath::F32Ext as _;
+
+    const TARGET_RATE: u32 = 48_000;
+    // Number of source frames pulled before the first one appears on the
+    // output.
+    const LATENCY: usize = TAPS - MIDDLE;
+
+    // Sine source with frequency relative to its sample rate
+    struct Sine {
+        phase: f32,
+        step: f32,
+    }
+
+    impl Sine {
+        fn new(frequency: f32, sample_rate: u32) -> Self {
+            Self {
+                phase: 0.0,
+                step: frequency / sample_rate as f32,
+            }
+        }
+
+        fn next(&mut self) -> (f32, f32) {
+            let value = (2.0 * core::f32::consts::PI * self.phase).sin();
+            self.phase += self.step;
+            if self.phase >= 1.0 {
+                self.phase -= 1.0;
+            }
+            (value, -value)
+        }
+    }
+
+    fn measure_frequency(resampler: &mut Resampler, sine: &mut Sine) -> f32 {
+        // Skip the frames that were in the resampler before the sine, and
+        // the ringing of sinc caused by the sine starting abruptly
+        for _ in 0..TARGET_RATE / 100 {
+            resampler.next_frame(1.0, || sine.next());
+        }
+
+        let mut previous = resampler.next_frame(1.0, || sine.next()).0;
+        let mut first_crossing = None;
+        let mut last_crossing = 0.0;
+        let mut crossings = 0;
+        for i in 1..TARGET_RATE {
+            let current = resampler.next_frame(1.0, || sine.next()).0;
+            if previous < 0.0 && current >= 0.0 {
+                let crossing = (i - 1) as f32 + previous / (previous - current);
+                if first_crossing.is_none() {
+                    first_crossing = Some(crossing);
+                } else {
+                    crossings += 1;
+                }
+                last_crossing = crossing;
+            }
+            previous = current;
+        }
+
+        let period = (last_crossing - first_crossing.unwrap()) / crossings as f32;
+        TARGET_RATE as f32 / period
+    }
+
+    #[test]
+    fn when_rates_match_it_passes_frames_through() {
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Sinc,
+        ] {
+            let mut resampler = Resampler::new(TARGET_RATE, TARGET_RATE);
+            resampler.set_interpolation(interpolation);
+            let mut index = 0;
+            let mut source = || {
+                index += 1;
+                (index as f32, -(index as f32))
+            };
+
+            for _ in 0..LATENCY {
+                resampler.next_frame(1.0, &mut source);
+            }
+            for i in 1..100 {
+                let frame = resampler.next_frame(1.0, &mut source);
+                // Sinc is calculated from approximated sines
+                assert!((frame.0 - i as f32).abs() < 0.001 * i as f32, "{:?}", frame);
+                assert!((frame.1 + i as f32).abs() < 0.001 * i as f32, "{:?}", frame);
+            }
+        }
+    }
+
+    #[test]
+    fn when_sample_rate_differs_it_keeps_the_pitch() {
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Sinc,
+        ] {
+            for source_rate in [22_050, 44_100, 96_000] {
+                let mut resampler = Resampler::new(source_rate, TARGET_RATE);
+                resampler.set_interpolation(interpolation);
+                let mut sine = Sine::new(440.0, source_rate);
+
+                let frequency = measure_frequency(&mut resampler, &mut sine);
+
+                assert!(
+                    (frequency - 440.0).abs() < 0.1,
+                    "{:?} from {}: {}",
+                    interpolation,
+                    source_rate,
+                    frequency
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn when_speed_is_doubled_it_plays_octave_higher() {
+        let mut resampler = Resampler::new(44_100, TARGET_RATE);
+        let mut sine = Sine::new(440.0, 44_100);
+
+        let mut previous = 0.0;
+        let mut crossings = 0;
+        for _ in 0..TARGET_RATE {
+            let current = resampler.next_frame(2.0, || sine.next()).0;
+            if previous < 0.0 && current >= 0.0 {
+                crossings += 1;
+            }
+            previous = current;
+        }
+
+        assert!((crossings - 880i32).abs() <= 1);
+    }
+
+    #[test]
+    fn when_interpolating_over_more_frames_it_reconstructs_the_signal_more_precisely() {
+        let frequency = 5_000.0;
+        let source_rate = 44_100;
+
+        let error = |interpolation| {
+            let mut resampler = Resampler::new(source_rate, TARGET_RATE);
+            resampler.set_interpolation(interpolation);
+            let mut sine = Sine::new(frequency, source_rate);
+            // The first pulled frame reaches the interpolated position
+            // after the latency.
+            let step = source_rate as f32 / TARGET_RATE as f32;
+            let mut reference = Sine::new(frequency, TARGET_RATE);
+            reference.phase = 1.0 - LATENCY as f32 * frequency / source_rate as f32;
+
+            let mut sum = 0.0;
+            for i in 0..4800 {
+                let output = resampler.next_frame(1.0, || sine.next()).0;
+                let expected = reference.next().0;
+                if i as f32 * step > LATENCY as f32 {
+                    sum += (output - expected) * (output - expected);
+                }
+            }
+            sum
+        };
+
+        let linear = error(Interpolation::Linear);
+        let hermite = error(Interpolation::Hermite);
+        let sinc = error(Interpolation::Sinc);
+        assert!(hermite < linear / 4.0, "{} {}", hermite, linear);
+        assert!(sinc < hermite, "{} {}", sinc, hermite);
+    }
+
+    #[test]
+    fn when_speed_pushes_signal_over_nyquist_sinc_filters_it_out() {
+        let energy = |interpolation| {
+            let mut resampler = Resampler::new(TARGET_RATE, TARGET_RATE);
+            resampler.set_interpolation(interpolation);
+            let mut sine = Sine::new(20_000.0, TARGET_RATE);
+
+            let mut sum = 0.0;
+            for i in 0..4800 {
+                let output = resampler.next_frame(2.0, || sine.next()).0;
+                if i > LATENCY {
+                    sum += output * output;
+                }
+            }
+            sum
+        };
+
+        let linear = energy(Interpolation::Linear);
+        let sinc = energy(Interpolation::Sinc);
+        assert!(sinc < linear / 10.0, "{} {}", sinc, linear);
+    }
+}
@@ -0,0 +1,52 @@
+//! Soft saturation of the magnetic tape.
+//!
+//! The more the tape is driven, the more it compresses peaks of the signal.
+//! The level of signal reaching full scale is kept, so turning the drive up
+//! adds harmonics and density, rather than loudness.
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+// Gain applied to the signal when fully driven.
+const MAX_GAIN: f32 = 5.0;
+
+/// Saturate the value with the given drive between 0.0 and 1.0. No drive
+/// passes the signal through untouched.
+pub fn saturate(x: f32, drive: f32) -> f32 {
+    let gain = 1.0 + drive * (MAX_GAIN - 1.0);
+    let saturated = soft_clip(x * gain) / soft_clip(gain);
+    x + (saturated - x) * drive
+}
+
+// Rational approximation of tanh, reaching 1.0 at 3.0.
+fn soft_clip(x: f32) -> f32 {
+    let x = x.clamp(-3.0, 3.0);
+    x * (27.0 + x * x) / (27.0 + 9.0 * x * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_not_driven_it_passes_signal_through() {
+        for x in [-2.0, -0.5, 0.0, 0.3, 1.0] {
+            assert_eq!(saturate(x, 0.0), x);
+        }
+    }
+
+    #[test]
+    fn when_driven_it_compresses_peaks_and_keeps_full_scale() {
+        assert!((saturate(1.0, 1.0) - 1.0).abs() < 1e-6);
+        assert!(saturate(3.0, 1.0) <= 1.0);
+        assert!(saturate(-3.0, 1.0) >= -1.0);
+        assert!(saturate(0.2, 1.0) > 0.5);
+
+        let mut previous = saturate(-2.0, 0.5);
+        for i in -19..=20 {
+            let current = saturate(i as f32 / 10.0, 0.5);
+            assert!(current > previous);
+            previous = current;
+        }
+    }
+}
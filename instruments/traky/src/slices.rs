@@ -0,0 +1,295 @@
+//! Split the sample into slices played on demand.
+//!
+//! Slices are delimited by markers placed anywhere within the sample. The
+//! first slice always starts at the beginning of the sample, markers start
+//! the following ones. Markers can be placed evenly, found by the onset
+//! detector, or moved one by one. They are kept in the order they were set,
+//! so each of them stays bound to its own attribute in the UI, and sorted
+//! only when slices get looked up.
+//!
+//! The onset detector follows the energy of the signal in short hops and
+//! marks hops that are considerably louder than the ones preceding them.
+//! Only the strongest onsets are kept, as many as there are markers.
+
+use heapless::Vec;
+
+/// Number of markers, there is one more slice than that.
+pub const MARKERS: usize = 7;
+
+/// Size of markers stored on the SD card.
+pub const SLICES_SIZE: usize = MARKERS * 4;
+
+// Length of hops in frames, the precision of detected onsets.
+const HOP: usize = 256;
+// Number of hops the average energy follows.
+const AVERAGE_HOPS: f32 = 8.0;
+// Energy of the hop relative to the average needed to mark an onset.
+const THRESHOLD: f32 = 4.0;
+// Hops quieter than this are never marked.
+const MIN_ENERGY: f32 = 1e-4;
+// Minimal distance between onsets in frames.
+const MIN_GAP: usize = 16 * HOP;
+// Number of the strongest candidates kept during the detection.
+const CANDIDATES: usize = 4 * MARKERS;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Slices {
+    // Frames where slices start, zero for unused markers.
+    markers: [usize; MARKERS],
+}
+
+impl Slices {
+    /// Split the sample of the given length into given number of slices of
+    /// equal length.
+    pub fn even(length: usize, count: usize) -> Self {
+        let mut slices = Self::default();
+        let count = count.clamp(1, MARKERS + 1);
+        for (i, marker) in slices.markers.iter_mut().take(count - 1).enumerate() {
+            *marker = length * (i + 1) / count;
+        }
+        slices
+    }
+
+    pub fn marker(&self, index: usize) -> usize {
+        self.markers[index]
+    }
+
+    /// Place the marker to the given frame, zero removes it.
+    pub fn set_marker(&mut self, index: usize, frame: usize) {
+        self.markers[index] = frame;
+    }
+
+    /// Number of slices, markers placed on the same frame count once.
+    pub fn count(&self) -> usize {
+        self.starts().len()
+    }
+
+    /// Start and end frames of the slice. Slices past the count play the
+    /// last one.
+    pub fn range(&self, index: usize, length: usize) -> (usize, usize) {
+        let starts = self.starts();
+        let index = index.min(starts.len() - 1);
+        let end = starts.get(index + 1).copied().unwrap_or(length);
+        (starts[index].min(length), end.min(length))
+    }
+
+    fn starts(&self) -> Vec<usize, { MARKERS + 1 }> {
+        let mut markers = self.markers;
+        markers.sort_unstable();
+        let mut starts: Vec<usize, { MARKERS + 1 }> = Vec::new();
+        starts.push(0).unwrap();
+        for marker in markers {
+            if marker > *starts.last().unwrap() {
+                starts.push(marker).unwrap();
+            }
+        }
+        starts
+    }
+
+    pub fn to_bytes(self) -> [u8; SLICES_SIZE] {
+        let mut bytes = [0; SLICES_SIZE];
+        for (chunk, marker) in bytes.chunks_exact_mut(4).zip(self.markers) {
+            chunk.copy_from_slice(&(marker as u32).to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != SLICES_SIZE {
+            return None;
+        }
+        let mut slices = Self::default();
+        for (marker, chunk) in slices.markers.iter_mut().zip(bytes.chunks_exact(4)) {
+            *marker = u32::from_le_bytes(chunk.try_into().unwrap()) as usize;
+        }
+        Some(slices)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OnsetDetector {
+    // Number of frames fed so far.
+    position: usize,
+    hop_energy: f32,
+    hop_length: usize,
+    average: f32,
+    last_onset: Option<usize>,
+    // Frames of onsets with their strength.
+    candidates: Vec<(usize, f32), CANDIDATES>,
+}
+
+impl OnsetDetector {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            position: 0,
+            hop_energy: 0.0,
+            hop_length: 0,
+            average: 0.0,
+            last_onset: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Number of frames fed so far, where the following ones continue.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Continue the analysis with following frames of the sample.
+    pub fn feed(&mut self, frames: &[(f32, f32)]) {
+        for frame in frames {
+            self.hop_energy += frame.0 * frame.0 + frame.1 * frame.1;
+            self.hop_length += 1;
+            self.position += 1;
+            if self.hop_length == HOP {
+                self.finish_hop();
+            }
+        }
+    }
+
+    fn finish_hop(&mut self) {
+        let energy = self.hop_energy / HOP as f32;
+        let start = self.position - HOP;
+        let strength = energy / (self.average + MIN_ENERGY);
+        let far_enough = self.last_onset.is_none_or(|o| start - o >= MIN_GAP);
+        if start > 0 && energy > MIN_ENERGY && strength > THRESHOLD && far_enough {
+            self.add_candidate(start, strength);
+            self.last_onset = Some(start);
+        }
+        self.average += (energy - self.average) / AVERAGE_HOPS;
+        self.hop_energy = 0.0;
+        self.hop_length = 0;
+    }
+
+    // When there is no space left, the weakest candidate gets replaced.
+    fn add_candidate(&mut self, frame: usize, strength: f32) {
+        if self.candidates.push((frame, strength)).is_err() {
+            let (weakest, _) = self
+                .candidates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.1.total_cmp(&b.1))
+                .unwrap();
+            if self.candidates[weakest].1 < strength {
+                self.candidates[weakest] = (frame, strength);
+            }
+        }
+    }
+
+    /// Place markers on the strongest onsets, in the order of the sample.
+    pub fn finish(mut self) -> Slices {
+        self.candidates.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        self.candidates.truncate(MARKERS);
+        self.candidates.sort_unstable_by_key(|c| c.0);
+        let mut slices = Slices::default();
+        for (marker, candidate) in slices.markers.iter_mut().zip(self.candidates.iter()) {
+            *marker = candidate.0;
+        }
+        slices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_split_evenly_slices_have_equal_length() {
+        let slices = Slices::even(800, 4);
+
+        assert_eq!(slices.count(), 4);
+        assert_eq!(slices.range(0, 800), (0, 200));
+        assert_eq!(slices.range(3, 800), (600, 800));
+        assert_eq!(slices.range(5, 800), (600, 800));
+    }
+
+    #[test]
+    fn when_markers_are_moved_past_each_other_slices_stay_sorted() {
+        let mut slices = Slices::even(800, 3);
+        slices.set_marker(0, 700);
+        slices.set_marker(2, 700);
+
+        assert_eq!(slices.count(), 3);
+        assert_eq!(slices.range(0, 800), (0, 533));
+        assert_eq!(slices.range(1, 800), (533, 700));
+        assert_eq!(slices.range(2, 800), (700, 800));
+    }
+
+    #[test]
+    fn when_serialized_it_can_be_restored() {
+        let mut slices = Slices::even(1_000_000, 8);
+        slices.set_marker(3, 123_456);
+
+        let restored = Slices::from_bytes(&slices.to_bytes());
+
+        assert_eq!(restored, Some(slices));
+        assert_eq!(Slices::from_bytes(&[0; 3]), None);
+    }
+
+    // Feed the detector with the given number of frames of the signal, in
+    // chunks not aligned to hops.
+    fn feed(detector: &mut OnsetDetector, length: usize, signal: impl Fn(usize) -> f32) {
+        let mut chunk = [(0.0, 0.0); 1_000];
+        for start in (0..length).step_by(chunk.len()) {
+            let end = (start + chunk.len()).min(length);
+            for (i, frame) in chunk.iter_mut().enumerate() {
+                let value = signal(start + i);
+                *frame = (value, value);
+            }
+            detector.feed(&chunk[..end - start]);
+        }
+    }
+
+    #[test]
+    fn when_sample_has_hits_it_marks_their_onsets() {
+        let hits = [10_000, 30_000, 45_000];
+        let mut detector = OnsetDetector::new();
+        // Decaying bursts of a square wave
+        feed(&mut detector, 60_000, |i| {
+            let hit = hits.iter().rev().find(|h| **h <= i);
+            hit.map_or(0.0, |h| {
+                let age = (i - h) as f32;
+                let envelope = 1.0 / (1.0 + age / 2_000.0);
+                envelope * if i % 40 < 20 { 0.5 } else { -0.5 }
+            })
+        });
+        assert_eq!(detector.position(), 60_000);
+
+        let slices = detector.finish();
+
+        assert_eq!(slices.count(), 4);
+        for (i, hit) in hits.iter().enumerate() {
+            let (start, _) = slices.range(i + 1, 60_000);
+            assert!(start <= *hit && start + HOP > *hit, "{} {}", start, hit);
+        }
+    }
+
+    #[test]
+    fn when_there_are_too_many_onsets_it_keeps_the_strongest() {
+        let mut detector = OnsetDetector::new();
+        // Short hits on odd multiples of the gap, every other one louder
+        let length = 40 * MIN_GAP;
+        feed(&mut detector, length, |i| {
+            let hit = i / MIN_GAP;
+            if hit % 2 == 1 && i % MIN_GAP < HOP {
+                if hit / 2 % 2 == 1 {
+                    1.0
+                } else {
+                    0.1
+                }
+            } else {
+                0.0
+            }
+        });
+
+        let slices = detector.finish();
+
+        assert_eq!(slices.count(), MARKERS + 1);
+        for i in 0..MARKERS {
+            let (start, _) = slices.range(i + 1, length);
+            assert_eq!(start % MIN_GAP, 0);
+            assert_eq!(start / MIN_GAP / 2 % 2, 1);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Smooth out changes of parameters, so they do not cause audible steps.
+//!
+//! Parameters are set from the UI or control inputs, both running far below
+//! the audio rate. Applying them directly would introduce zipper noise.
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+// Time in seconds it takes to get about two thirds of the way to the target.
+const TIME_CONSTANT: f32 = 0.01;
+
+pub struct Smoother {
+    value: f32,
+    target: f32,
+    coefficient: f32,
+}
+
+impl Smoother {
+    pub fn new(value: f32, sample_rate: u32) -> Self {
+        Self {
+            value,
+            target: value,
+            coefficient: 1.0 - (-1.0 / (TIME_CONSTANT * sample_rate as f32)).exp(),
+        }
+    }
+
+    pub fn set(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Jump to the value immediately.
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+    }
+
+    /// Move towards the target by one sample.
+    pub fn next(&mut self) -> f32 {
+        self.value += (self.target - self.value) * self.coefficient;
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    #[test]
+    fn when_target_changes_it_gets_there_gradually() {
+        let mut smoother = Smoother::new(0.0, SAMPLE_RATE);
+        smoother.set(1.0);
+
+        let mut previous = 0.0;
+        for _ in 0..SAMPLE_RATE / 100 {
+            let value = smoother.next();
+            assert!(value > previous);
+            assert!(value - previous < 0.01);
+            previous = value;
+        }
+        for _ in 0..SAMPLE_RATE / 10 {
+            smoother.next();
+        }
+
+        assert!((smoother.next() - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,816 @@
+//! Play and record samples that are too long to fit into memory.
+//!
+//! The stream is the caller described in the paginated buffer. It runs in
+//! the audio interrupt, passing pages to the paginated buffer. It never
+//! touches the SD card itself. Instead, it issues pages that need to be
+//! loaded or saved and expects them to be returned, once they are processed
+//! in another routine.
+//!
+//! The playback loops within a region of the sample, either forward or in
+//! reverse. The page where the playback returns once it reaches the end of
+//! the loop, the head page, is always kept in memory so the playback can
+//! jump to it immediately. Besides that, the stream prefetches the page
+//! following the one that is currently played.
+//!
+//! Recorded pages are saved once the playback leaves them. The head page
+//! cannot leave the memory, so it is gradually copied into a spare page
+//! and that copy is saved instead.
+//!
+//! When recording starts on an empty tape, the sample grows until the
+//! recording stops. Recording into an existing sample keeps its length.
+
+use heapless::Vec;
+
+use crate::paginated_buffer::{Page, PaginatedBuffer};
+
+pub const PAGES: usize = 6;
+
+// Number of frames of the head page copied with every processed frame.
+const COPY_STEP: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recording {
+    Off,
+    Overdub,
+    Replace,
+}
+
+pub struct Stream<'a> {
+    buffer: PaginatedBuffer<'a>,
+    page_length: usize,
+    sample_length: usize,
+    // Requested loop relative to the sample length and its actual frames
+    loop_fractions: (f32, f32),
+    loop_start: usize,
+    loop_end: usize,
+    reverse: bool,
+    head: usize,
+    head_page: Option<Page<'a>>,
+    head_page_copy: Option<Page<'a>>,
+    next_page: Option<Page<'a>>,
+    free_pages: Vec<Page<'a>, PAGES>,
+    dirty_pages: Vec<Page<'a>, PAGES>,
+    // Position where the playback continues once the page containing it is
+    // available. In reverse, the frame preceding the cursor is played next.
+    cursor: usize,
+    loading: bool,
+    recording: Recording,
+    growing: bool,
+}
+
+impl<'a> Stream<'a> {
+    pub fn new(pages: [Page<'a>; PAGES], sample_length: usize) -> Self {
+        let page_length = pages[0].capacity();
+        let mut free_pages = Vec::new();
+        for page in pages {
+            free_pages.push(page).ok().unwrap();
+        }
+        let mut stream = Self {
+            buffer: PaginatedBuffer::new(),
+            page_length,
+            sample_length,
+            loop_fractions: (0.0, 1.0),
+            loop_start: 0,
+            loop_end: 0,
+            reverse: false,
+            head: 0,
+            head_page: None,
+            head_page_copy: None,
+            next_page: None,
+            free_pages,
+            dirty_pages: Vec::new(),
+            cursor: 0,
+            loading: false,
+            recording: Recording::Off,
+            growing: false,
+        };
+        stream.update_loop();
+        stream
+    }
+
+    /// Read the following frame of the sample, recording the input frame
+    /// into it if armed.
+    ///
+    /// If the needed page is not loaded yet, silence is returned and the
+    /// playback waits until the page arrives.
+    pub fn next_frame(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let mut frame = [input];
+        if self.buffer.process(&mut frame) == 0 {
+            frame[0] = (0.0, 0.0);
+        }
+        self.turn_page_if_full();
+        self.copy_head_page();
+        frame[0]
+    }
+
+    /// Length of the sample in frames, growing while recording on an empty
+    /// tape.
+    pub fn length(&self) -> usize {
+        self.sample_length
+    }
+
+    pub fn set_recording(&mut self, recording: Recording) {
+        let armed = recording != Recording::Off;
+        if armed && self.recording == Recording::Off && self.sample_length == 0 && !self.reverse {
+            self.growing = true;
+            self.update_loop();
+        } else if !armed && self.growing {
+            self.stop_growing();
+        }
+
+        self.recording = recording;
+        self.buffer.arm_recording(armed);
+        self.buffer.set_overdub(recording == Recording::Overdub);
+        self.buffer.set_bounded(!self.growing);
+
+        // Once the sample stops growing, its end might have been just reached
+        self.turn_page_if_full();
+    }
+
+    /// Set the looped region of the sample, relative to its length.
+    pub fn set_loop(&mut self, start: f32, end: f32) {
+        self.loop_fractions = (start, end);
+        self.update_loop();
+
+        let position = match self.buffer.page() {
+            Some(page) => page.start() + self.buffer.position(),
+            None => self.cursor,
+        };
+        if position < self.loop_start || position > self.loop_end {
+            self.reset();
+        } else {
+            self.turn_page_if_full();
+        }
+    }
+
+    pub fn set_reverse(&mut self, reverse: bool) {
+        if reverse == self.reverse {
+            return;
+        }
+        self.reverse = reverse;
+        self.buffer.set_reverse(reverse);
+        // Growing only makes sense when the recording moves forward
+        if reverse && self.growing {
+            self.stop_growing();
+            self.buffer.set_bounded(true);
+        }
+        self.update_loop();
+        self.turn_page_if_full();
+    }
+
+    /// Start over with another sample of the given length. All pages are
+    /// returned to the free pool, so nothing may be loaded or saved at the
+    /// moment. Recording the played page that was not saved yet is lost.
+    pub fn replace_sample(&mut self, sample_length: usize) {
+        let buffer_page = if self.buffer.has_page() {
+            Some(self.buffer.take_page())
+        } else {
+            None
+        };
+        let pages = [
+            buffer_page,
+            self.head_page.take(),
+            self.head_page_copy.take(),
+            self.next_page.take(),
+        ];
+        let dirty_pages = core::mem::take(&mut self.dirty_pages);
+        for mut page in pages.into_iter().flatten().chain(dirty_pages) {
+            page.mark_clean();
+            self.free_pages.push(page).ok().unwrap();
+        }
+
+        self.sample_length = sample_length;
+        self.growing = false;
+        self.update_loop();
+        self.cursor = self.loop_target();
+        let recording = self.recording;
+        self.recording = Recording::Off;
+        self.set_recording(recording);
+    }
+
+    /// Take a page that should be loaded from the SD card. Its start address
+    /// is already set. Only one page is being loaded at a time.
+    pub fn page_to_load(&mut self) -> Option<Page<'a>> {
+        if self.loading {
+            return None;
+        }
+
+        let start = if self.head_page.is_none() && !self.is_playing_page(self.head) {
+            self.head
+        } else {
+            let upcoming = self.upcoming_page()?;
+            match &self.next_page {
+                Some(page) if page.start() == upcoming => return None,
+                Some(_) => {
+                    let stale_page = self.next_page.take().unwrap();
+                    self.free_pages.push(stale_page).ok().unwrap();
+                }
+                None => (),
+            }
+            upcoming
+        };
+
+        let mut page = self.free_pages.pop()?;
+        page.reset(start);
+        self.loading = true;
+        Some(page)
+    }
+
+    pub fn return_loaded_page(&mut self, page: Page<'a>) {
+        self.loading = false;
+
+        if page.start() == self.head && self.head_page.is_none() && !self.is_playing_page(self.head)
+        {
+            self.head_page = Some(page);
+        } else if Some(page.start()) == self.upcoming_page() {
+            self.next_page = Some(page);
+        } else {
+            self.free_pages.push(page).ok().unwrap();
+        }
+
+        if !self.buffer.has_page() {
+            self.set_expected_page();
+        }
+    }
+
+    /// Take a recorded page that should be saved on the SD card. Saving
+    /// should be prioritized over loading, so a page is never loaded before
+    /// its recent recording is saved.
+    pub fn page_to_save(&mut self) -> Option<Page<'a>> {
+        self.dirty_pages.pop()
+    }
+
+    pub fn return_saved_page(&mut self, mut page: Page<'a>) {
+        page.mark_clean();
+        self.free_pages.push(page).ok().unwrap();
+
+        if !self.buffer.has_page() {
+            self.set_expected_page();
+        }
+    }
+
+    fn turn_page_if_full(&mut self) {
+        if self.buffer.has_page() && self.buffer.is_page_full() {
+            self.turn_page();
+        }
+    }
+
+    fn turn_page(&mut self) {
+        let page = self.buffer.take_page();
+        let start = page.start();
+        let following = start + page.length();
+        self.stash_page(page);
+
+        if self.growing {
+            self.sample_length = following;
+            self.update_loop();
+            self.cursor = following;
+        } else if self.reverse {
+            self.cursor = if start > self.loop_start {
+                start
+            } else {
+                self.loop_end
+            };
+        } else if following >= self.loop_end {
+            self.cursor = self.loop_start;
+        } else {
+            self.cursor = following;
+        }
+        self.set_expected_page();
+    }
+
+    /// Leave the current page and continue from where the loop begins.
+    pub fn reset(&mut self) {
+        if self.buffer.has_page() {
+            let page = self.buffer.take_page();
+            self.stash_page(page);
+        }
+        self.cursor = self.loop_target();
+        self.set_expected_page();
+    }
+
+    fn stash_page(&mut self, page: Page<'a>) {
+        if page.start() == self.head && self.head_page.is_none() {
+            self.head_page = Some(page);
+        } else if page.is_dirty() {
+            self.dirty_pages.push(page).ok().unwrap();
+        } else {
+            self.free_pages.push(page).ok().unwrap();
+        }
+    }
+
+    fn set_expected_page(&mut self) {
+        let expected_page = self.page_of(self.cursor);
+        let page = if expected_page == self.head {
+            self.head_page.take()
+        } else if matches!(&self.next_page, Some(p) if p.start() == expected_page) {
+            self.next_page.take()
+        } else if self.growing {
+            self.blank_page(expected_page)
+        } else {
+            None
+        };
+
+        if let Some(page) = page {
+            let position = self.cursor - page.start();
+            self.buffer.set_page(page);
+            self.update_range();
+            if self.reverse {
+                self.buffer
+                    .set_position(position.min(self.loop_end - expected_page));
+            } else {
+                self.buffer.set_position(position);
+            }
+        }
+    }
+
+    fn blank_page(&mut self, start: usize) -> Option<Page<'a>> {
+        let mut page = self.free_pages.pop()?;
+        page.reset(start);
+        Some(page)
+    }
+
+    fn stop_growing(&mut self) {
+        self.growing = false;
+        if let Some(page) = self.buffer.page() {
+            self.sample_length = self.sample_length.max(page.start() + page.length());
+        }
+        self.update_loop();
+    }
+
+    // Convert the requested loop into frames. While growing, the loop
+    // covers the whole sample.
+    fn update_loop(&mut self) {
+        let length = self.sample_length;
+        let (start, end) = if self.growing || length == 0 {
+            (0, length)
+        } else {
+            let (start, end) = self.loop_fractions;
+            let start = ((length as f32 * start) as usize).min(length - 1);
+            let end = ((length as f32 * end) as usize).max(start + 1).min(length);
+            (start, end)
+        };
+        self.loop_start = start;
+        self.loop_end = end;
+        self.update_range();
+        self.update_head();
+    }
+
+    fn update_range(&mut self) {
+        if let Some(start) = self.buffer.page().map(|p| p.start()) {
+            self.buffer.set_range(
+                self.loop_start.saturating_sub(start),
+                self.loop_end.saturating_sub(start),
+            );
+        }
+    }
+
+    // The old head page is released. If its copy was in progress, the page
+    // gets saved whole instead.
+    fn update_head(&mut self) {
+        let head = self.page_of(self.loop_target());
+        if head == self.head {
+            return;
+        }
+        let old_head = self.head;
+        self.head = head;
+
+        if let Some(copy) = self.head_page_copy.take() {
+            self.free_pages.push(copy).ok().unwrap();
+            if let Some(page) = self.head_page.as_mut() {
+                page.mark_dirty();
+            } else if let Some(page) = self.buffer.page_mut() {
+                if page.start() == old_head {
+                    page.mark_dirty();
+                }
+            }
+        }
+        if let Some(page) = self.head_page.take() {
+            self.stash_page(page);
+        }
+        if matches!(&self.next_page, Some(p) if p.start() == head) {
+            self.head_page = self.next_page.take();
+        }
+    }
+
+    // The copy is taken from wherever the head page currently is. One free
+    // page is always kept aside, so loading and growing is not blocked.
+    fn copy_head_page(&mut self) {
+        let head = self.head;
+        if let Some(mut copy) = self.head_page_copy.take() {
+            let source = match (&self.head_page, self.buffer.page()) {
+                (Some(page), _) => page,
+                (None, Some(page)) if page.start() == head => page,
+                _ => {
+                    self.free_pages.push(copy).ok().unwrap();
+                    return;
+                }
+            };
+            if copy.copy_from(source, COPY_STEP) {
+                self.dirty_pages.push(copy).ok().unwrap();
+            } else {
+                self.head_page_copy = Some(copy);
+            }
+            return;
+        }
+
+        if self.free_pages.len() < 2 {
+            return;
+        }
+        let source = match (self.head_page.as_mut(), self.buffer.page_mut()) {
+            (Some(page), _) => page,
+            (None, Some(page)) if page.start() == head => page,
+            _ => return,
+        };
+        if source.is_dirty() {
+            // Anything recorded into the page after this point will mark
+            // it dirty again and trigger another copy.
+            source.mark_clean();
+            self.head_page_copy = self.blank_page(head);
+        }
+    }
+
+    // The head page is always cached. If it is up next, the page following
+    // it is prefetched instead.
+    fn upcoming_page(&self) -> Option<usize> {
+        let upcoming = match self.buffer.page() {
+            Some(page) => self.following_page(page.start()).unwrap_or(self.head),
+            None => self.page_of(self.cursor),
+        };
+        if upcoming == self.head {
+            self.following_page(self.head)
+        } else {
+            Some(upcoming)
+        }
+    }
+
+    // Page played after the given one, unless the loop ends there.
+    fn following_page(&self, start: usize) -> Option<usize> {
+        if self.reverse {
+            if start > self.loop_start {
+                Some(start - self.page_length)
+            } else {
+                None
+            }
+        } else if start + self.page_length < self.loop_end {
+            Some(start + self.page_length)
+        } else {
+            None
+        }
+    }
+
+    // Where the playback continues once it reaches the end of the loop.
+    fn loop_target(&self) -> usize {
+        if self.reverse {
+            self.loop_end
+        } else {
+            self.loop_start
+        }
+    }
+
+    fn page_of(&self, cursor: usize) -> usize {
+        let frame = if self.reverse {
+            cursor.saturating_sub(1)
+        } else {
+            cursor
+        };
+        frame / self.page_length * self.page_length
+    }
+
+    fn is_playing_page(&self, start: usize) -> bool {
+        matches!(self.buffer.page(), Some(page) if page.start() == start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_LENGTH: usize = 16;
+    const SAMPLE_LENGTH: usize = 2 * PAGE_LENGTH + PAGE_LENGTH / 2;
+    const SILENCE: (f32, f32) = (0.0, 0.0);
+
+    type Memory = [[(f32, f32); PAGE_LENGTH]; PAGES];
+
+    struct Sd {
+        frames: [(f32, f32); 4 * PAGE_LENGTH],
+        length: usize,
+    }
+
+    impl Sd {
+        fn new(length: usize) -> Self {
+            let mut frames = [SILENCE; 4 * PAGE_LENGTH];
+            for (i, frame) in frames[..length].iter_mut().enumerate() {
+                *frame = frame_at(i);
+            }
+            Self { frames, length }
+        }
+
+        fn serve(&mut self, stream: &mut Stream<'_>) -> bool {
+            if let Some(page) = stream.page_to_save() {
+                let start = page.start();
+                self.frames[start..start + page.length()].copy_from_slice(page.frames());
+                self.length = self.length.max(start + page.length());
+                stream.return_saved_page(page);
+                true
+            } else if let Some(mut page) = stream.page_to_load() {
+                let start = page.start();
+                page.fill(|data| {
+                    let length = PAGE_LENGTH.min(self.length - start);
+                    data[..length].copy_from_slice(&self.frames[start..start + length]);
+                    length
+                });
+                stream.return_loaded_page(page);
+                true
+            } else {
+                false
+            }
+        }
+
+        fn serve_all(&mut self, stream: &mut Stream<'_>) {
+            while self.serve(stream) {}
+        }
+    }
+
+    fn frame_at(index: usize) -> (f32, f32) {
+        (index as f32, -(index as f32))
+    }
+
+    fn stream(memory: &mut Memory, sample_length: usize) -> Stream<'_> {
+        let pages = memory.each_mut().map(|memory| Page::new(memory, 0));
+        Stream::new(pages, sample_length)
+    }
+
+    #[test]
+    fn when_pages_are_served_it_plays_the_sample_in_loop() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+
+        for i in 0..3 * SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i % SAMPLE_LENGTH));
+            sd.serve(&mut stream);
+        }
+    }
+
+    #[test]
+    fn when_page_is_not_served_in_time_it_plays_silence_and_then_continues() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        assert!(sd.serve(&mut stream));
+
+        for i in 0..PAGE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i));
+        }
+        for _ in 0..10 {
+            assert_eq!(stream.next_frame((1.0, 1.0)), SILENCE);
+        }
+
+        assert!(sd.serve(&mut stream));
+        for i in PAGE_LENGTH..2 * PAGE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i));
+        }
+    }
+
+    #[test]
+    fn when_sample_fits_into_single_page_it_does_not_load_anything_else() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, PAGE_LENGTH / 2);
+        let mut page = stream.page_to_load().unwrap();
+        page.load(0, &[(1.0, 1.0); PAGE_LENGTH / 2]);
+        stream.return_loaded_page(page);
+
+        for _ in 0..2 * PAGE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), (1.0, 1.0));
+            assert!(stream.page_to_load().is_none());
+        }
+    }
+
+    #[test]
+    fn when_sample_is_empty_it_plays_silence() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, 0);
+        let page = stream.page_to_load().unwrap();
+        stream.return_loaded_page(page);
+
+        assert_eq!(stream.next_frame(SILENCE), SILENCE);
+        assert!(stream.page_to_load().is_none());
+    }
+
+    #[test]
+    fn when_recording_on_empty_tape_it_grows_the_sample_and_saves_it() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, 0);
+        let mut sd = Sd::new(0);
+        sd.serve_all(&mut stream);
+
+        stream.set_recording(Recording::Replace);
+        for i in 0..SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame(frame_at(i)), SILENCE);
+            sd.serve(&mut stream);
+        }
+        stream.set_recording(Recording::Off);
+
+        for i in 0..3 * SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i % SAMPLE_LENGTH));
+            sd.serve(&mut stream);
+        }
+        sd.serve_all(&mut stream);
+
+        assert_eq!(sd.length, SAMPLE_LENGTH);
+        for i in 0..SAMPLE_LENGTH {
+            assert_eq!(sd.frames[i], frame_at(i));
+        }
+    }
+
+    #[test]
+    fn when_overdubbing_it_mixes_input_into_the_sample_and_saves_it() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+
+        stream.set_recording(Recording::Overdub);
+        for i in 0..SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame((1.0, 1.0)), frame_at(i));
+            sd.serve(&mut stream);
+        }
+        stream.set_recording(Recording::Off);
+
+        for _ in 0..2 {
+            for i in 0..SAMPLE_LENGTH {
+                let expected = (frame_at(i).0 + 1.0, frame_at(i).1 + 1.0);
+                assert_eq!(stream.next_frame(SILENCE), expected);
+                sd.serve(&mut stream);
+            }
+        }
+        sd.serve_all(&mut stream);
+
+        assert_eq!(sd.length, SAMPLE_LENGTH);
+        for i in 0..SAMPLE_LENGTH {
+            assert_eq!(sd.frames[i], (frame_at(i).0 + 1.0, frame_at(i).1 + 1.0));
+        }
+    }
+
+    #[test]
+    fn when_replacing_part_of_sample_it_keeps_its_length() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+
+        for _ in 0..2 * PAGE_LENGTH {
+            stream.next_frame(SILENCE);
+            sd.serve(&mut stream);
+        }
+        stream.set_recording(Recording::Replace);
+        for _ in 2 * PAGE_LENGTH..SAMPLE_LENGTH + PAGE_LENGTH / 2 {
+            stream.next_frame((1.0, 1.0));
+            sd.serve(&mut stream);
+        }
+        stream.set_recording(Recording::Off);
+        for _ in 0..2 * SAMPLE_LENGTH {
+            stream.next_frame(SILENCE);
+            sd.serve(&mut stream);
+        }
+        sd.serve_all(&mut stream);
+
+        assert_eq!(sd.length, SAMPLE_LENGTH);
+        for i in 0..SAMPLE_LENGTH {
+            let expected = if !(PAGE_LENGTH / 2..2 * PAGE_LENGTH).contains(&i) {
+                (1.0, 1.0)
+            } else {
+                frame_at(i)
+            };
+            assert_eq!(sd.frames[i], expected);
+        }
+    }
+
+    #[test]
+    fn when_sample_is_replaced_it_plays_the_new_one_from_start() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+        for _ in 0..PAGE_LENGTH + PAGE_LENGTH / 2 {
+            stream.next_frame(SILENCE);
+            sd.serve(&mut stream);
+        }
+
+        stream.replace_sample(PAGE_LENGTH + PAGE_LENGTH / 2);
+        let mut sd = Sd::new(PAGE_LENGTH + PAGE_LENGTH / 2);
+        sd.serve_all(&mut stream);
+
+        for i in 0..2 * SAMPLE_LENGTH {
+            assert_eq!(
+                stream.next_frame(SILENCE),
+                frame_at(i % (PAGE_LENGTH + PAGE_LENGTH / 2))
+            );
+            sd.serve(&mut stream);
+        }
+    }
+
+    // Play given number of frames, serving the SD card after each of them,
+    // and return the frame that was played last.
+    fn play(stream: &mut Stream<'_>, sd: &mut Sd, frames: usize) -> (f32, f32) {
+        let mut frame = SILENCE;
+        for _ in 0..frames {
+            frame = stream.next_frame(SILENCE);
+            sd.serve(stream);
+        }
+        frame
+    }
+
+    #[test]
+    fn when_looping_part_of_sample_it_plays_only_the_region() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        stream.set_loop(0.25, 0.75);
+        sd.serve_all(&mut stream);
+
+        for i in 0..3 * SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(10 + i % 20));
+            sd.serve(&mut stream);
+        }
+    }
+
+    #[test]
+    fn when_playing_in_reverse_it_loops_from_end_to_start() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        stream.set_reverse(true);
+        sd.serve_all(&mut stream);
+
+        let mut previous = play(&mut stream, &mut sd, 1);
+        for _ in 0..3 * SAMPLE_LENGTH {
+            let frame = stream.next_frame(SILENCE);
+            sd.serve(&mut stream);
+            let expected = (previous.0 as usize + SAMPLE_LENGTH - 1) % SAMPLE_LENGTH;
+            assert_eq!(frame, frame_at(expected));
+            previous = frame;
+        }
+    }
+
+    #[test]
+    fn when_reversing_in_the_middle_it_plays_back_the_same_frames() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+
+        assert_eq!(play(&mut stream, &mut sd, 20), frame_at(19));
+        stream.set_reverse(true);
+        sd.serve_all(&mut stream);
+
+        for i in (0..20).rev() {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i));
+            sd.serve(&mut stream);
+        }
+    }
+
+    #[test]
+    fn when_loop_moves_away_from_playback_it_jumps_to_its_start() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        sd.serve_all(&mut stream);
+        play(&mut stream, &mut sd, 5);
+
+        stream.set_loop(0.5, 1.0);
+        sd.serve_all(&mut stream);
+
+        for i in 0..2 * SAMPLE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(20 + i % 20));
+            sd.serve(&mut stream);
+        }
+    }
+
+    #[test]
+    fn when_reset_it_continues_from_start_of_loop_immediately() {
+        let mut memory = [[SILENCE; PAGE_LENGTH]; PAGES];
+        let mut stream = stream(&mut memory, SAMPLE_LENGTH);
+        let mut sd = Sd::new(SAMPLE_LENGTH);
+        stream.set_loop(0.25, 1.0);
+        sd.serve_all(&mut stream);
+        play(&mut stream, &mut sd, 25);
+
+        stream.reset();
+
+        // The head page is cached, there is no need to wait for the card
+        for i in 10..PAGE_LENGTH {
+            assert_eq!(stream.next_frame(SILENCE), frame_at(i));
+        }
+        sd.serve_all(&mut stream);
+        for i in 0..SAMPLE_LENGTH {
+            assert_eq!(
+                stream.next_frame(SILENCE),
+                frame_at(10 + (PAGE_LENGTH - 10 + i) % 30)
+            );
+            sd.serve(&mut stream);
+        }
+    }
+}
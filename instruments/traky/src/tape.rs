@@ -0,0 +1,607 @@
+//! Access to the sample and its recordings stored on the SD card.
+//!
+//! The sample file is opened only once and its handle is kept around. This
+//! lets the FAT driver remember the last visited cluster, so loading of
+//! consecutive pages does not need to walk through the whole allocation
+//! table again. The same goes for the directory containing the sample.
+//!
+//! The sample file itself is never modified. Recorded pages are stored next
+//! to it in separate files of raw frames. Their name starts with a prefix
+//! assigned to the sample, followed by the page index (e.g. `A1F00003.PAG`),
+//! so samples sharing a directory do not mix their recordings. When present,
+//! they take precedence over the respective part of the sample. The FAT
+//! driver cannot overwrite a file in place without growing it, so each page
+//! is saved by truncating its file and writing it anew. Slice markers of
+//! the sample are stored the same way, in a file sharing the prefix (e.g.
+//! `A1FSLICE.SLC`).
+//!
+//! Names are limited to 8.3 characters and the driver cannot create
+//! directories, so the prefix is derived from a hash of the sample name.
+//! Each prefix is claimed by an owner file (e.g. `A1FOWNER.TAP`) holding the
+//! full name of the sample and the page length its recordings were split
+//! by. When the prefix is owned by another sample, the following ones are
+//! probed, so colliding hashes do not mix recordings either.
+
+use core::fmt::Write as _;
+
+use embedded_sdmmc::blockdevice::BlockDevice;
+use embedded_sdmmc::{Controller, Directory, Error, File, Mode, ShortFileName, Volume, VolumeIdx};
+use heapless::String;
+use proton_instruments_interface::sd::{volume_error, TimeSource, VolumeError};
+
+use crate::paginated_buffer::Page;
+use crate::slices::{Slices, SLICES_SIZE};
+use crate::wav::{self, Header};
+
+const FRAME_SIZE: usize = core::mem::size_of::<(f32, f32)>();
+const PAGE_EXTENSION: &str = "PAG";
+const SLICES_EXTENSION: &str = "SLC";
+const OWNER_EXTENSION: &str = "TAP";
+// Full 8.3 name of the sample followed by the page length.
+const OWNER_NAME_SIZE: usize = 12;
+const OWNER_SIZE: usize = OWNER_NAME_SIZE + 4;
+// Number of prefixes tried before giving up on samples sharing the hash.
+const PREFIX_PROBES: u16 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum TapeError {
+    NoCard,
+    NoVolume,
+    FileSystem,
+    InvalidSample(wav::Error),
+    PageLength,
+    Read,
+    Write,
+}
+
+impl TapeError {
+    /// Short description that fits on the display.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::NoCard => "No SD card",
+            Self::NoVolume => "Card not formatted",
+            Self::FileSystem => "SD card error",
+            Self::InvalidSample(_) => "Invalid sample",
+            Self::PageLength => "Old recording",
+            Self::Read => "SD read failed",
+            Self::Write => "SD write failed",
+        }
+    }
+}
+
+impl From<VolumeError> for TapeError {
+    fn from(error: VolumeError) -> Self {
+        match error {
+            VolumeError::NoCard => Self::NoCard,
+            VolumeError::NoVolume => Self::NoVolume,
+        }
+    }
+}
+
+pub struct Tape {
+    volume: Volume,
+    directory: Directory,
+    prefix: u16,
+    owner: [u8; OWNER_SIZE],
+    owned: bool,
+    sample: Option<Sample>,
+    page_length: usize,
+    length: usize,
+}
+
+struct Sample {
+    file: File,
+    header: Header,
+}
+
+impl Tape {
+    /// Open the sample on the given path and look up recorded pages. Both
+    /// are optional, the tape may start empty. The path consists of names of
+    /// directories followed by the name of the sample.
+    pub fn open(
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+        path: &[&str],
+        page_length: usize,
+    ) -> Result<Self, TapeError> {
+        let (name, directories) = path.split_last().ok_or(TapeError::FileSystem)?;
+        let mut fat = Controller::new(sd, TimeSource);
+        let mut volume = fat.get_volume(VolumeIdx(0)).map_err(volume_error)?;
+        let owner = owner_record(name, page_length).ok_or(TapeError::FileSystem)?;
+        let directory =
+            open_directory(&mut fat, &volume, directories).map_err(|_| TapeError::FileSystem)?;
+        let (prefix, owned) =
+            match find_prefix(&mut fat, &mut volume, &directory, name_hash(name), &owner) {
+                Ok(found) => found,
+                Err(error) => {
+                    fat.close_dir(&volume, directory);
+                    return Err(error);
+                }
+            };
+
+        let sample = match fat.open_file_in_dir(&mut volume, &directory, name, Mode::ReadOnly) {
+            Ok(mut file) => {
+                let mut read_failed = false;
+                let header = wav::parse_header(file.length(), |offset, buffer| {
+                    if file.seek_from_start(offset).is_err() {
+                        return 0;
+                    }
+                    fat.read(&volume, &mut file, buffer).unwrap_or_else(|_| {
+                        read_failed = true;
+                        0
+                    })
+                })
+                .map_err(|error| {
+                    if read_failed {
+                        TapeError::Read
+                    } else {
+                        TapeError::InvalidSample(error)
+                    }
+                });
+                match header {
+                    Ok(header) => Some(Sample { file, header }),
+                    Err(error) => {
+                        fat.close_dir(&volume, directory);
+                        return Err(error);
+                    }
+                }
+            }
+            Err(Error::FileNotFound) => None,
+            Err(_) => {
+                fat.close_dir(&volume, directory);
+                return Err(TapeError::FileSystem);
+            }
+        };
+
+        let mut length = sample.as_ref().map_or(0, |s| s.header.length());
+        let listed = fat.iterate_dir(&volume, &directory, |entry| {
+            if let Some(index) = page_index(&entry.name, prefix) {
+                length = length.max(index * page_length + entry.size as usize / FRAME_SIZE);
+            }
+        });
+        if listed.is_err() {
+            if let Some(sample) = sample {
+                let _ = fat.close_file(&volume, sample.file);
+            }
+            fat.close_dir(&volume, directory);
+            return Err(TapeError::FileSystem);
+        }
+
+        Ok(Self {
+            volume,
+            directory,
+            prefix,
+            owner,
+            owned,
+            sample,
+            page_length,
+            length,
+        })
+    }
+
+    /// Length of the tape in frames.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Sample rate of the sample, if there is any.
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample.as_ref().map(|s| s.header.format.sample_rate)
+    }
+
+    /// Fill the page with frames starting on its start address.
+    pub fn read_page(
+        &mut self,
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+        page: &mut Page,
+    ) -> Result<(), TapeError> {
+        let mut fat = Controller::new(sd, TimeSource);
+        let name = page_name(page.start() / page.capacity(), self.prefix);
+        let recorded =
+            fat.open_file_in_dir(&mut self.volume, &self.directory, &name, Mode::ReadOnly);
+
+        match recorded {
+            Ok(mut file) => {
+                let mut result = Ok(());
+                page.fill(|data| {
+                    let bytes = wav::as_bytes_mut(data);
+                    match fat.read(&self.volume, &mut file, bytes) {
+                        Ok(num_read) => num_read / FRAME_SIZE,
+                        Err(_) => {
+                            result = Err(TapeError::Read);
+                            0
+                        }
+                    }
+                });
+                fat.close_file(&self.volume, file)
+                    .map_err(|_| TapeError::FileSystem)?;
+                result
+            }
+            Err(Error::FileNotFound) => self.read_sample(&mut fat, page),
+            Err(_) => Err(TapeError::FileSystem),
+        }
+    }
+
+    fn read_sample<D: BlockDevice>(
+        &mut self,
+        fat: &mut Controller<D, TimeSource>,
+        page: &mut Page,
+    ) -> Result<(), TapeError>
+    where
+        D::Error: core::fmt::Debug,
+    {
+        let sample = match self.sample.as_mut() {
+            Some(sample) => sample,
+            None => {
+                page.fill(|_| 0);
+                return Ok(());
+            }
+        };
+
+        let format = sample.header.format;
+        let frame_size = format.frame_size();
+        let frames = page
+            .capacity()
+            .min(sample.header.length().saturating_sub(page.start()));
+        sample
+            .file
+            .seek_from_start(sample.header.data_offset + (page.start() * frame_size) as u32)
+            .map_err(|_| TapeError::Read)?;
+        let mut result = Ok(());
+        page.fill(|data| {
+            // Raw data are read into the beginning of the page and decoded in place
+            let bytes = &mut wav::as_bytes_mut(data)[..frames * frame_size];
+            let num_read = match fat.read(&self.volume, &mut sample.file, bytes) {
+                Ok(num_read) => num_read,
+                Err(_) => {
+                    result = Err(TapeError::Read);
+                    0
+                }
+            };
+            let num_frames = num_read / frame_size;
+            format.decode_in_place(data, num_frames);
+            num_frames
+        });
+        result
+    }
+
+    /// Store recorded frames of the page in its own file.
+    pub fn write_page(
+        &mut self,
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+        page: &Page,
+    ) -> Result<(), TapeError> {
+        let mut fat = Controller::new(sd, TimeSource);
+        self.claim(&mut fat)?;
+        let name = page_name(page.start() / page.capacity(), self.prefix);
+        let mut file = fat
+            .open_file_in_dir(
+                &mut self.volume,
+                &self.directory,
+                &name,
+                Mode::ReadWriteCreateOrTruncate,
+            )
+            .map_err(|_| TapeError::Write)?;
+
+        let written = fat.write(&mut self.volume, &mut file, wav::as_bytes(page.frames()));
+        fat.close_file(&self.volume, file)
+            .map_err(|_| TapeError::FileSystem)?;
+        match written {
+            Ok(written) if written == page.length() * FRAME_SIZE => (),
+            _ => return Err(TapeError::Write),
+        }
+
+        self.length = self.length.max(page.start() + page.length());
+        Ok(())
+    }
+
+    /// Read slice markers stored next to the sample, if there are any.
+    pub fn read_slices(
+        &mut self,
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    ) -> Result<Option<Slices>, TapeError> {
+        let mut fat = Controller::new(sd, TimeSource);
+        let name = slices_name(self.prefix);
+        let mut file =
+            match fat.open_file_in_dir(&mut self.volume, &self.directory, &name, Mode::ReadOnly) {
+                Ok(file) => file,
+                Err(Error::FileNotFound) => return Ok(None),
+                Err(_) => return Err(TapeError::FileSystem),
+            };
+
+        let mut bytes = [0; SLICES_SIZE];
+        let read = fat.read(&self.volume, &mut file, &mut bytes);
+        fat.close_file(&self.volume, file)
+            .map_err(|_| TapeError::FileSystem)?;
+        match read {
+            // A file of another size is ignored, as if the slices were lost
+            Ok(read) => Ok(Slices::from_bytes(&bytes[..read])),
+            Err(_) => Err(TapeError::Read),
+        }
+    }
+
+    pub fn write_slices(
+        &mut self,
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+        slices: &Slices,
+    ) -> Result<(), TapeError> {
+        let mut fat = Controller::new(sd, TimeSource);
+        self.claim(&mut fat)?;
+        let name = slices_name(self.prefix);
+        let mut file = fat
+            .open_file_in_dir(
+                &mut self.volume,
+                &self.directory,
+                &name,
+                Mode::ReadWriteCreateOrTruncate,
+            )
+            .map_err(|_| TapeError::Write)?;
+
+        let written = fat.write(&mut self.volume, &mut file, &slices.to_bytes());
+        fat.close_file(&self.volume, file)
+            .map_err(|_| TapeError::FileSystem)?;
+        match written {
+            Ok(SLICES_SIZE) => Ok(()),
+            _ => Err(TapeError::Write),
+        }
+    }
+
+    /// Delete all recorded pages, leaving only the sample on the tape.
+    pub fn clear(
+        &mut self,
+        sd: &mut impl BlockDevice<Error = impl core::fmt::Debug>,
+    ) -> Result<(), TapeError> {
+        let mut fat = Controller::new(sd, TimeSource);
+        let pages = self.length.div_ceil(self.page_length);
+        for index in 0..pages {
+            let name = page_name(index, self.prefix);
+            match fat.delete_file_in_dir(&self.volume, &self.directory, &name) {
+                Ok(()) | Err(Error::FileNotFound) => (),
+                Err(_) => return Err(TapeError::Write),
+            }
+        }
+        self.length = self.sample.as_ref().map_or(0, |s| s.header.length());
+        Ok(())
+    }
+
+    // The owner file is written only once something is stored under the
+    // prefix, so merely browsing samples does not litter the card.
+    fn claim<D: BlockDevice>(
+        &mut self,
+        fat: &mut Controller<D, TimeSource>,
+    ) -> Result<(), TapeError>
+    where
+        D::Error: core::fmt::Debug,
+    {
+        if self.owned {
+            return Ok(());
+        }
+        let name = owner_name(self.prefix);
+        let mut file = fat
+            .open_file_in_dir(
+                &mut self.volume,
+                &self.directory,
+                &name,
+                Mode::ReadWriteCreateOrTruncate,
+            )
+            .map_err(|_| TapeError::Write)?;
+
+        let written = fat.write(&mut self.volume, &mut file, &self.owner);
+        fat.close_file(&self.volume, file)
+            .map_err(|_| TapeError::FileSystem)?;
+        match written {
+            Ok(OWNER_SIZE) => {
+                self.owned = true;
+                Ok(())
+            }
+            _ => Err(TapeError::Write),
+        }
+    }
+}
+
+// Probe prefixes starting from the hash of the name, until one owned by
+// the sample or a free one is found.
+fn find_prefix<D: BlockDevice>(
+    fat: &mut Controller<D, TimeSource>,
+    volume: &mut Volume,
+    directory: &Directory,
+    hash: u16,
+    owner: &[u8; OWNER_SIZE],
+) -> Result<(u16, bool), TapeError>
+where
+    D::Error: core::fmt::Debug,
+{
+    for probe in 0..PREFIX_PROBES {
+        let prefix = (hash + probe) & 0xfff;
+        let name = owner_name(prefix);
+        let mut file = match fat.open_file_in_dir(volume, directory, &name, Mode::ReadOnly) {
+            Ok(file) => file,
+            Err(Error::FileNotFound) => return Ok((prefix, false)),
+            Err(_) => return Err(TapeError::FileSystem),
+        };
+
+        let mut stored = [0; OWNER_SIZE];
+        let read = fat.read(volume, &mut file, &mut stored);
+        fat.close_file(volume, file)
+            .map_err(|_| TapeError::FileSystem)?;
+        match read {
+            Ok(OWNER_SIZE) if stored[..OWNER_NAME_SIZE] == owner[..OWNER_NAME_SIZE] => {
+                // Pages are named by their index, so they cannot be mapped
+                // onto pages of another length
+                return if stored == *owner {
+                    Ok((prefix, true))
+                } else {
+                    Err(TapeError::PageLength)
+                };
+            }
+            Ok(_) => (),
+            Err(_) => return Err(TapeError::Read),
+        }
+    }
+    Err(TapeError::FileSystem)
+}
+
+/// Walk from the root through the given directories and open the last one.
+pub fn open_directory<D: BlockDevice>(
+    fat: &mut Controller<D, TimeSource>,
+    volume: &Volume,
+    directories: &[&str],
+) -> Result<Directory, Error<D::Error>>
+where
+    D::Error: core::fmt::Debug,
+{
+    let mut directory = fat.open_root_dir(volume)?;
+    for name in directories {
+        let child = fat.open_dir(volume, &directory, name);
+        fat.close_dir(volume, directory);
+        directory = child?;
+    }
+    Ok(directory)
+}
+
+// The name is stored in upper case, as FAT does not preserve the case of
+// short names.
+fn owner_record(name: &str, page_length: usize) -> Option<[u8; OWNER_SIZE]> {
+    if name.len() > OWNER_NAME_SIZE {
+        return None;
+    }
+    let mut record = [0; OWNER_SIZE];
+    for (stored, byte) in record.iter_mut().zip(name.bytes()) {
+        *stored = byte.to_ascii_uppercase();
+    }
+    record[OWNER_NAME_SIZE..].copy_from_slice(&(page_length as u32).to_le_bytes());
+    Some(record)
+}
+
+// FNV-1a folded into the three hexadecimal digits available in the name.
+fn name_hash(name: &str) -> u16 {
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte.to_ascii_uppercase() as u32).wrapping_mul(0x0100_0193)
+    });
+    ((hash ^ (hash >> 12) ^ (hash >> 24)) & 0xfff) as u16
+}
+
+fn page_name(index: usize, prefix: u16) -> String<12> {
+    let mut name = String::new();
+    write!(name, "{:03X}{:05}.{}", prefix, index, PAGE_EXTENSION).unwrap();
+    name
+}
+
+fn slices_name(prefix: u16) -> String<12> {
+    let mut name = String::new();
+    write!(name, "{:03X}SLICE.{}", prefix, SLICES_EXTENSION).unwrap();
+    name
+}
+
+fn owner_name(prefix: u16) -> String<12> {
+    let mut name = String::new();
+    write!(name, "{:03X}OWNER.{}", prefix, OWNER_EXTENSION).unwrap();
+    name
+}
+
+fn page_index(name: &ShortFileName, prefix: u16) -> Option<usize> {
+    let base_name = name.base_name();
+    if name.extension() != PAGE_EXTENSION.as_bytes() || base_name.len() != 8 {
+        return None;
+    }
+    let (hash, index) = base_name.split_at(3);
+    let hash = hash.iter().try_fold(0, |hash, byte| {
+        let digit = (*byte as char).to_digit(16)?;
+        Some(hash * 16 + digit as u16)
+    })?;
+    if hash != prefix {
+        return None;
+    }
+    index.iter().try_fold(0, |index, byte| {
+        let digit = (*byte as char).to_digit(10)?;
+        Some(index * 10 + digit as usize)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx};
+
+    struct MissingCard;
+
+    impl BlockDevice for MissingCard {
+        type Error = ();
+
+        fn read(&self, _: &mut [Block], _: BlockIdx, _: &str) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn write(&self, _: &[Block], _: BlockIdx) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, ()> {
+            Err(())
+        }
+    }
+
+    struct BlankCard;
+
+    impl BlockDevice for BlankCard {
+        type Error = ();
+
+        fn read(&self, blocks: &mut [Block], _: BlockIdx, _: &str) -> Result<(), ()> {
+            for block in blocks.iter_mut() {
+                *block = Block::new();
+            }
+            Ok(())
+        }
+
+        fn write(&self, _: &[Block], _: BlockIdx) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, ()> {
+            Ok(BlockCount(1024))
+        }
+    }
+
+    #[test]
+    fn when_card_is_missing_it_reports_an_error() {
+        let result = Tape::open(&mut MissingCard, &["project.wav"], 32);
+        assert_eq!(result.err(), Some(TapeError::NoCard));
+    }
+
+    #[test]
+    fn when_card_is_not_formatted_it_reports_an_error() {
+        let result = Tape::open(&mut BlankCard, &["project.wav"], 32);
+        assert_eq!(result.err(), Some(TapeError::NoVolume));
+    }
+
+    #[test]
+    fn when_parsing_page_names_it_accepts_only_indexed_pages() {
+        let name = |name: &str| ShortFileName::create_from_str(name).unwrap();
+
+        let prefix = name_hash("project.wav");
+
+        assert_eq!(page_index(&name(&page_name(42, prefix)), prefix), Some(42));
+        assert_eq!(page_index(&name(&page_name(42, prefix + 1)), prefix), None);
+        assert_eq!(page_index(&name("00000042.WAV"), prefix), None);
+        assert_eq!(page_index(&name("PROJECT.PAG"), prefix), None);
+        assert_eq!(page_index(&name(&slices_name(prefix)), prefix), None);
+        assert_eq!(page_index(&name(&owner_name(prefix)), prefix), None);
+    }
+
+    #[test]
+    fn when_recording_owner_it_keeps_full_name_and_page_length() {
+        let record = owner_record("project.wav", 32).unwrap();
+
+        assert_eq!(&record[..12], b"PROJECT.WAV\0");
+        assert_eq!(record, owner_record("PROJECT.WAV", 32).unwrap());
+        assert_ne!(record, owner_record("PROJECT.WAV", 64).unwrap());
+        assert_ne!(record, owner_record("KICK.WAV", 32).unwrap());
+        assert_eq!(owner_record("too long name.wav", 32), None);
+    }
+
+    #[test]
+    fn when_hashing_names_it_ignores_case() {
+        assert_eq!(name_hash("project.wav"), name_hash("PROJECT.WAV"));
+        assert_ne!(name_hash("PROJECT.WAV"), name_hash("KICK.WAV"));
+    }
+}
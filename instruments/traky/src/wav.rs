@@ -0,0 +1,460 @@
+//! Parse RIFF/WAV files and decode their audio data.
+//!
+//! Supported are 16 and 24 bit integer PCM and 32 bit float, either in mono
+//! or stereo. Mono samples are played on both channels.
+
+const RIFF_HEADER_SIZE: u32 = 12;
+const CHUNK_HEADER_SIZE: u32 = 8;
+const MAX_FORMAT_SIZE: usize = 40;
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    NotRiff,
+    NotWave,
+    MissingFormat,
+    MissingData,
+    InvalidChunk,
+    UnsupportedFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Pcm16,
+    Pcm24,
+    Float32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub format: Format,
+    /// Offset of the audio data from the start of the file in bytes.
+    pub data_offset: u32,
+    /// Length of the audio data in bytes.
+    pub data_length: u32,
+}
+
+impl Header {
+    /// Length of the audio data in frames.
+    pub fn length(&self) -> usize {
+        self.data_length as usize / self.format.frame_size()
+    }
+}
+
+/// Walk through chunks of the file until both format and data are found.
+///
+/// The callback reads bytes from the given offset of the file into the
+/// buffer and returns how many of them were read. The length of the file
+/// is used to cut off data chunks claiming to be longer than the file.
+pub fn parse_header(
+    file_length: u32,
+    mut read: impl FnMut(u32, &mut [u8]) -> usize,
+) -> Result<Header, Error> {
+    let mut riff = [0; RIFF_HEADER_SIZE as usize];
+    if read(0, &mut riff) < riff.len() || &riff[0..4] != b"RIFF" {
+        return Err(Error::NotRiff);
+    }
+    if &riff[8..12] != b"WAVE" {
+        return Err(Error::NotWave);
+    }
+
+    let mut format = None;
+    let mut offset = RIFF_HEADER_SIZE;
+    loop {
+        let mut chunk = [0; CHUNK_HEADER_SIZE as usize];
+        if read(offset, &mut chunk) < chunk.len() {
+            return Err(if format.is_none() {
+                Error::MissingFormat
+            } else {
+                Error::MissingData
+            });
+        }
+        let id = &chunk[0..4];
+        let size = u32_at(&chunk, 4);
+        let body = offset
+            .checked_add(CHUNK_HEADER_SIZE)
+            .ok_or(Error::InvalidChunk)?;
+
+        if id == b"fmt " {
+            let mut buffer = [0; MAX_FORMAT_SIZE];
+            let length = (size as usize).min(MAX_FORMAT_SIZE);
+            let read = read(body, &mut buffer[..length]);
+            format = Some(parse_format(&buffer[..read])?);
+        } else if id == b"data" {
+            let format = format.ok_or(Error::MissingFormat)?;
+            let data_length = size.min(file_length.saturating_sub(body));
+            let data_length = data_length - data_length % format.frame_size() as u32;
+            return Ok(Header {
+                format,
+                data_offset: body,
+                data_length,
+            });
+        }
+
+        // Chunks are aligned to words, odd sizes are followed by a padding
+        offset = body
+            .checked_add(size)
+            .and_then(|end| end.checked_add(size % 2))
+            .ok_or(Error::InvalidChunk)?;
+    }
+}
+
+fn parse_format(bytes: &[u8]) -> Result<Format, Error> {
+    if bytes.len() < 16 {
+        return Err(Error::MissingFormat);
+    }
+
+    let tag = match u16_at(bytes, 0) {
+        FORMAT_EXTENSIBLE if bytes.len() >= 26 => u16_at(bytes, 24),
+        tag => tag,
+    };
+    let channels = u16_at(bytes, 2);
+    let sample_rate = u32_at(bytes, 4);
+    let bits_per_sample = u16_at(bytes, 14);
+
+    let encoding = match (tag, bits_per_sample) {
+        (FORMAT_PCM, 16) => Encoding::Pcm16,
+        (FORMAT_PCM, 24) => Encoding::Pcm24,
+        (FORMAT_FLOAT, 32) => Encoding::Float32,
+        _ => return Err(Error::UnsupportedFormat),
+    };
+    if channels != 1 && channels != 2 {
+        return Err(Error::UnsupportedFormat);
+    }
+
+    Ok(Format {
+        encoding,
+        channels,
+        sample_rate,
+    })
+}
+
+impl Format {
+    pub fn frame_size(&self) -> usize {
+        self.sample_size() * self.channels as usize
+    }
+
+    fn sample_size(&self) -> usize {
+        match self.encoding {
+            Encoding::Pcm16 => 2,
+            Encoding::Pcm24 => 3,
+            Encoding::Float32 => 4,
+        }
+    }
+
+    pub fn decode_frame(&self, bytes: &[u8]) -> (f32, f32) {
+        let left = self.decode_sample(bytes);
+        if self.channels == 1 {
+            (left, left)
+        } else {
+            (left, self.decode_sample(&bytes[self.sample_size()..]))
+        }
+    }
+
+    fn decode_sample(&self, bytes: &[u8]) -> f32 {
+        match self.encoding {
+            Encoding::Pcm16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            Encoding::Pcm24 => {
+                let value = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                value as f32 / 8_388_608.0
+            }
+            Encoding::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Decode frames that were read as raw bytes into the beginning of the
+    /// same buffer.
+    ///
+    /// Encoded frames are never larger than decoded ones. Going from the end
+    /// of the buffer, no encoded frame gets overwritten before it is decoded.
+    pub fn decode_in_place(&self, data: &mut [(f32, f32)], frames: usize) {
+        let frame_size = self.frame_size();
+        for i in (0..frames).rev() {
+            let frame = {
+                let bytes = as_bytes(data);
+                self.decode_frame(&bytes[i * frame_size..(i + 1) * frame_size])
+            };
+            data[i] = frame;
+        }
+    }
+}
+
+pub fn as_bytes(data: &[(f32, f32)]) -> &[u8] {
+    let pointer = data as *const _ as *const u8;
+    unsafe { core::slice::from_raw_parts(pointer, core::mem::size_of_val(data)) }
+}
+
+pub fn as_bytes_mut(data: &mut [(f32, f32)]) -> &mut [u8] {
+    let pointer = data as *mut _ as *mut u8;
+    unsafe { core::slice::from_raw_parts_mut(pointer, core::mem::size_of_val(data)) }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct File {
+        bytes: [u8; 256],
+        length: usize,
+    }
+
+    impl File {
+        fn new() -> Self {
+            Self {
+                bytes: [0; 256],
+                length: 0,
+            }
+        }
+
+        fn push(&mut self, bytes: &[u8]) -> &mut Self {
+            self.bytes[self.length..self.length + bytes.len()].copy_from_slice(bytes);
+            self.length += bytes.len();
+            self
+        }
+
+        fn riff(&mut self) -> &mut Self {
+            self.push(b"RIFF").push(&[0; 4]).push(b"WAVE")
+        }
+
+        fn chunk(&mut self, id: &[u8], body: &[u8]) -> &mut Self {
+            self.push(id).push(&(body.len() as u32).to_le_bytes());
+            self.push(body);
+            if body.len() % 2 == 1 {
+                self.push(&[0]);
+            }
+            self
+        }
+
+        fn format(&mut self, tag: u16, channels: u16, bits: u16) -> &mut Self {
+            let mut body = [0; 16];
+            body[0..2].copy_from_slice(&tag.to_le_bytes());
+            body[2..4].copy_from_slice(&channels.to_le_bytes());
+            body[4..8].copy_from_slice(&44_100u32.to_le_bytes());
+            body[14..16].copy_from_slice(&bits.to_le_bytes());
+            self.chunk(b"fmt ", &body)
+        }
+
+        fn parse(&self) -> Result<Header, Error> {
+            parse_header(self.length as u32, |offset, buffer| {
+                let offset = (offset as usize).min(self.length);
+                let length = buffer.len().min(self.length - offset);
+                buffer[..length].copy_from_slice(&self.bytes[offset..offset + length]);
+                length
+            })
+        }
+    }
+
+    #[test]
+    fn when_parsing_basic_pcm_it_finds_format_and_data() {
+        let header = File::new()
+            .riff()
+            .format(FORMAT_PCM, 2, 16)
+            .chunk(b"data", &[0; 16])
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            header.format,
+            Format {
+                encoding: Encoding::Pcm16,
+                channels: 2,
+                sample_rate: 44_100,
+            }
+        );
+        assert_eq!(header.data_offset, 44);
+        assert_eq!(header.data_length, 16);
+        assert_eq!(header.length(), 4);
+    }
+
+    #[test]
+    fn when_parsing_file_with_other_chunks_it_skips_them() {
+        let header = File::new()
+            .riff()
+            .chunk(b"JUNK", &[1; 7])
+            .format(FORMAT_FLOAT, 1, 32)
+            .chunk(b"LIST", &[2; 10])
+            .chunk(b"data", &[0; 8])
+            .parse()
+            .unwrap();
+
+        assert_eq!(header.format.encoding, Encoding::Float32);
+        assert_eq!(header.format.channels, 1);
+        assert_eq!(header.data_offset, 12 + 16 + 24 + 18 + 8);
+        assert_eq!(header.length(), 2);
+    }
+
+    #[test]
+    fn when_parsing_extensible_format_it_reads_subformat() {
+        let mut body = [0; 40];
+        body[0..2].copy_from_slice(&FORMAT_EXTENSIBLE.to_le_bytes());
+        body[2..4].copy_from_slice(&2u16.to_le_bytes());
+        body[4..8].copy_from_slice(&48_000u32.to_le_bytes());
+        body[14..16].copy_from_slice(&24u16.to_le_bytes());
+        body[24..26].copy_from_slice(&FORMAT_PCM.to_le_bytes());
+
+        let header = File::new()
+            .riff()
+            .chunk(b"fmt ", &body)
+            .chunk(b"data", &[0; 12])
+            .parse()
+            .unwrap();
+
+        assert_eq!(header.format.encoding, Encoding::Pcm24);
+        assert_eq!(header.format.sample_rate, 48_000);
+        assert_eq!(header.length(), 2);
+    }
+
+    #[test]
+    fn when_data_ends_with_incomplete_frame_it_is_ignored() {
+        let header = File::new()
+            .riff()
+            .format(FORMAT_PCM, 2, 24)
+            .chunk(b"data", &[0; 15])
+            .parse()
+            .unwrap();
+
+        assert_eq!(header.data_length, 12);
+        assert_eq!(header.length(), 2);
+    }
+
+    #[test]
+    fn when_data_chunk_is_longer_than_file_it_is_clamped() {
+        let mut file = File::new();
+        file.riff()
+            .format(FORMAT_PCM, 2, 16)
+            .chunk(b"data", &[0; 16]);
+        file.bytes[40..44].copy_from_slice(&1_000u32.to_le_bytes());
+
+        let header = file.parse().unwrap();
+
+        assert_eq!(header.data_length, 16);
+        assert_eq!(header.length(), 4);
+    }
+
+    #[test]
+    fn when_chunk_size_overflows_offset_it_reports_error() {
+        let mut file = File::new();
+        file.riff()
+            .format(FORMAT_PCM, 2, 16)
+            .chunk(b"JUNK", &[0; 2]);
+        file.bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(file.parse(), Err(Error::InvalidChunk));
+    }
+
+    #[test]
+    fn when_parsing_invalid_files_it_reports_errors() {
+        assert_eq!(File::new().parse(), Err(Error::NotRiff));
+        assert_eq!(
+            File::new()
+                .push(b"RIFF")
+                .push(&[0; 4])
+                .push(b"AVI ")
+                .parse(),
+            Err(Error::NotWave)
+        );
+        assert_eq!(
+            File::new().riff().chunk(b"data", &[0; 4]).parse(),
+            Err(Error::MissingFormat)
+        );
+        assert_eq!(
+            File::new().riff().format(FORMAT_PCM, 2, 16).parse(),
+            Err(Error::MissingData)
+        );
+        assert_eq!(
+            File::new().riff().format(FORMAT_PCM, 2, 8).parse(),
+            Err(Error::UnsupportedFormat)
+        );
+        assert_eq!(
+            File::new().riff().format(FORMAT_PCM, 6, 16).parse(),
+            Err(Error::UnsupportedFormat)
+        );
+    }
+
+    fn format(encoding: Encoding, channels: u16) -> Format {
+        Format {
+            encoding,
+            channels,
+            sample_rate: 48_000,
+        }
+    }
+
+    #[test]
+    fn when_decoding_pcm16_it_scales_to_unit_range() {
+        let format = format(Encoding::Pcm16, 2);
+        let mut bytes = [0; 4];
+        bytes[0..2].copy_from_slice(&i16::MIN.to_le_bytes());
+        bytes[2..4].copy_from_slice(&16384i16.to_le_bytes());
+
+        assert_eq!(format.decode_frame(&bytes), (-1.0, 0.5));
+    }
+
+    #[test]
+    fn when_decoding_pcm24_it_keeps_the_sign() {
+        let format = format(Encoding::Pcm24, 2);
+        let bytes = [0x00, 0x00, 0xC0, 0x00, 0x00, 0x40];
+
+        assert_eq!(format.decode_frame(&bytes), (-0.5, 0.5));
+    }
+
+    #[test]
+    fn when_decoding_float_it_passes_values_through() {
+        let format = format(Encoding::Float32, 2);
+        let mut bytes = [0; 8];
+        bytes[0..4].copy_from_slice(&0.25f32.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(-0.75f32).to_le_bytes());
+
+        assert_eq!(format.decode_frame(&bytes), (0.25, -0.75));
+    }
+
+    #[test]
+    fn when_decoding_mono_it_plays_it_on_both_channels() {
+        let format = format(Encoding::Pcm16, 1);
+        let bytes = 16384i16.to_le_bytes();
+
+        assert_eq!(format.decode_frame(&bytes), (0.5, 0.5));
+    }
+
+    #[test]
+    fn when_decoding_in_place_it_converts_all_frames() {
+        let format = format(Encoding::Pcm16, 1);
+        let mut data = [(0.0, 0.0); 8];
+        {
+            let bytes = as_bytes_mut(&mut data);
+            for i in 0..8 {
+                let value = (i as i16 - 4) * 4096;
+                bytes[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        format.decode_in_place(&mut data, 8);
+
+        for (i, frame) in data.iter().enumerate() {
+            let expected = (i as f32 - 4.0) / 8.0;
+            assert_eq!(*frame, (expected, expected));
+        }
+    }
+}
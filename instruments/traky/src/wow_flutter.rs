@@ -0,0 +1,135 @@
+//! Fluctuation of the tape speed.
+//!
+//! Wow is a slow drift of the pitch, caused by the eccentricity of reels.
+//! Flutter is a fast and irregular wobble of the capstan. Both are modeled
+//! as sine modulators of the read speed, with their frequency and amplitude
+//! randomized on each cycle, so they never repeat exactly.
+
+use core::f32::consts::PI;
+
+#[allow(unused_imports)]
+use micromath::F32Ext as _;
+
+use proton_instruments_interface::Rand;
+
+const WOW_FREQUENCY: f32 = 0.6;
+const WOW_RANGE: f32 = 0.015;
+const FLUTTER_FREQUENCY: f32 = 9.0;
+const FLUTTER_RANGE: f32 = 0.004;
+
+// Relative deviation of the frequency randomized on each cycle.
+const FREQUENCY_SPREAD: f32 = 0.3;
+// Lowest relative amplitude randomized on each cycle.
+const MIN_AMPLITUDE: f32 = 0.5;
+
+pub struct WowFlutter {
+    wow: Modulator,
+    flutter: Modulator,
+}
+
+impl WowFlutter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            wow: Modulator::new(WOW_FREQUENCY / sample_rate as f32, WOW_RANGE),
+            flutter: Modulator::new(FLUTTER_FREQUENCY / sample_rate as f32, FLUTTER_RANGE),
+        }
+    }
+
+    /// Set the depth of wow and flutter, both between 0.0 and 1.0.
+    pub fn set(&mut self, wow: f32, flutter: f32) {
+        self.wow.depth = wow;
+        self.flutter.depth = flutter;
+    }
+
+    /// Multiplier of the tape speed for the following frame.
+    pub fn next(&mut self, randomizer: &mut impl Rand) -> f32 {
+        1.0 + self.wow.next(randomizer) + self.flutter.next(randomizer)
+    }
+}
+
+struct Modulator {
+    // Frequency relative to the sample rate.
+    frequency: f32,
+    range: f32,
+    depth: f32,
+    phase: f32,
+    step: f32,
+    amplitude: f32,
+}
+
+impl Modulator {
+    fn new(frequency: f32, range: f32) -> Self {
+        Self {
+            frequency,
+            range,
+            depth: 0.0,
+            phase: 0.0,
+            step: frequency,
+            amplitude: 1.0,
+        }
+    }
+
+    fn next(&mut self, randomizer: &mut impl Rand) -> f32 {
+        self.phase += self.step;
+        // The sine crosses zero at the start of the cycle, so neither change
+        // causes a jump of the speed.
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            let spread = FREQUENCY_SPREAD * (2.0 * random(randomizer) - 1.0);
+            self.step = self.frequency * (1.0 + spread);
+            self.amplitude = MIN_AMPLITUDE + (1.0 - MIN_AMPLITUDE) * random(randomizer);
+        }
+        (2.0 * PI * self.phase).sin() * self.amplitude * self.depth * self.range
+    }
+}
+
+// Random number between 0.0 and 1.0.
+fn random(randomizer: &mut impl Rand) -> f32 {
+    randomizer.generate() as f32 / u16::MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    struct Randomizer(u16);
+
+    impl Rand for Randomizer {
+        fn generate(&mut self) -> u16 {
+            self.0 = self.0.wrapping_mul(25_173).wrapping_add(13_849);
+            self.0
+        }
+    }
+
+    #[test]
+    fn when_disabled_it_keeps_the_speed() {
+        let mut wow_flutter = WowFlutter::new(SAMPLE_RATE);
+        let mut randomizer = Randomizer(0);
+        for _ in 0..SAMPLE_RATE {
+            assert_eq!(wow_flutter.next(&mut randomizer), 1.0);
+        }
+    }
+
+    #[test]
+    fn when_enabled_it_fluctuates_around_the_original_speed() {
+        let mut wow_flutter = WowFlutter::new(SAMPLE_RATE);
+        wow_flutter.set(1.0, 1.0);
+        let mut randomizer = Randomizer(0);
+
+        let mut sum = 0.0;
+        let mut min: f32 = 1.0;
+        let mut max: f32 = 1.0;
+        for _ in 0..10 * SAMPLE_RATE {
+            let speed = wow_flutter.next(&mut randomizer);
+            assert!((speed - 1.0).abs() <= WOW_RANGE + FLUTTER_RANGE + 1e-3);
+            sum += speed as f64;
+            min = min.min(speed);
+            max = max.max(speed);
+        }
+
+        assert!((sum / (10 * SAMPLE_RATE) as f64 - 1.0).abs() < 0.002);
+        assert!(max - min > WOW_RANGE);
+    }
+}
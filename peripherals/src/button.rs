@@ -9,6 +9,8 @@ pub struct Button<const N: usize, P> {
     debounce_filter: DebounceBuffer<N>,
     active: bool,
     clicked: bool,
+    released: bool,
+    held: u32,
 }
 
 impl<const N: usize, P: InputPin> Button<N, P> {
@@ -18,6 +20,8 @@ impl<const N: usize, P: InputPin> Button<N, P> {
             debounce_filter: DebounceBuffer::new(),
             active: false,
             clicked: false,
+            released: false,
+            held: 0,
         }
     }
 
@@ -30,6 +34,12 @@ impl<const N: usize, P: InputPin> Button<N, P> {
         self.debounce_filter.write(self.pin.is_low().ok().unwrap());
         self.active = self.debounce_filter.read();
         self.clicked = !was_active && self.active;
+        self.released = was_active && !self.active;
+        self.held = if self.active {
+            self.held.saturating_add(1)
+        } else {
+            0
+        };
     }
 
     pub fn active(&self) -> bool {
@@ -43,6 +53,15 @@ impl<const N: usize, P: InputPin> Button<N, P> {
     pub fn clicked(&self) -> bool {
         self.clicked
     }
+
+    pub fn released(&self) -> bool {
+        self.released
+    }
+
+    /// Number of samples the button has been held for, zero once released.
+    pub fn held(&self) -> u32 {
+        self.held
+    }
 }
 
 #[cfg(test)]
@@ -92,6 +111,45 @@ mod tests {
         assert!(!button.clicked());
     }
 
+    #[test]
+    fn when_let_go_it_reports_released_for_one_cycle() {
+        let a = TestPin::new();
+        let mut button = Button::<3, _>::new(a);
+
+        button.pin().high = false;
+        for _ in 0..3 {
+            button.sample();
+        }
+        assert!(!button.released());
+
+        button.pin().high = true;
+        for _ in 0..2 {
+            button.sample();
+        }
+        assert!(button.released());
+
+        button.sample();
+        assert!(!button.released());
+    }
+
+    #[test]
+    fn when_held_down_it_counts_samples_until_released() {
+        let a = TestPin::new();
+        let mut button = Button::<3, _>::new(a);
+
+        button.pin().high = false;
+        for _ in 0..5 {
+            button.sample();
+        }
+        assert_eq!(button.held(), 4);
+
+        button.pin().high = true;
+        for _ in 0..2 {
+            button.sample();
+        }
+        assert_eq!(button.held(), 0);
+    }
+
     #[test]
     fn when_held_down_it_reports_as_active() {
         let a = TestPin::new();
@@ -0,0 +1,123 @@
+//! Render an instrument offline, driven by a timeline of events.
+//!
+//! Run it with the name of the instrument, the disk image emulating the SD
+//! card, the timeline script, the output WAV file, the CSV file recording
+//! gate and CV outputs and optionally the input WAV file:
+//!
+//! ```sh
+//! cargo run --bin render -- traky sd.img timeline.txt output.wav outputs.csv [input.wav]
+//! ```
+//!
+//! See `timeline.rs` for the format of the script. The render stops at the
+//! `end` event of the timeline, or at the end of the input if there is none.
+//!
+//! Blocks are processed exactly as in the firmware, the randomizer is seeded
+//! the same on every run, so renders are reproducible and can be compared.
+//! Outputs are recorded as a row whenever any of them changes, starting with
+//! the index of the first frame of the block they were requested by.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::{env, process};
+
+use proton_control::input_snapshot::{Cv, InputSnapshot, Pot};
+use proton_control::output_request::OutputRequest;
+use proton_instruments_interface::Instrument as _;
+use proton_instruments_registry::{Instrument, NAMES};
+use proton_simulator::block_device::FileBlockDevice;
+use proton_simulator::memory::memory_manager;
+use proton_simulator::runner::Runner;
+use proton_simulator::timeline::{Event, Timeline};
+use proton_simulator::wav::{self, WavWriter};
+use proton_simulator::{BLOCK_LENGTH, SAMPLE_RATE};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 6 || args.len() > 7 || !NAMES.contains(&args[1].as_str()) {
+        eprintln!(
+            "Usage: {} <{}> <sd.img> <timeline.txt> <output.wav> <outputs.csv> [input.wav]",
+            args[0],
+            NAMES.join("|")
+        );
+        process::exit(1);
+    }
+
+    let mut sd = FileBlockDevice::open(&args[2]).unwrap_or_else(|e| fail("open SD image", e));
+    let script = fs::read_to_string(&args[3]).unwrap_or_else(|e| fail("read timeline", e));
+    let mut writer =
+        WavWriter::create(&args[4], SAMPLE_RATE).unwrap_or_else(|e| fail("create output", e));
+    let mut log = BufWriter::new(File::create(&args[5]).unwrap_or_else(|e| fail("create log", e)));
+    let input = match args.get(6) {
+        Some(path) => {
+            let wav = wav::read(path).unwrap_or_else(|e| fail("read input", e));
+            if wav.sample_rate != SAMPLE_RATE {
+                eprintln!("Input is not resampled, expected {SAMPLE_RATE} Hz");
+            }
+            wav.frames
+        }
+        None => Vec::new(),
+    };
+
+    let mut memory_manager = memory_manager();
+    let instrument = Instrument::with_name(&args[1], SAMPLE_RATE, &mut memory_manager, &mut sd);
+    let timeline =
+        Timeline::parse(&script, &instrument.state()).unwrap_or_else(|e| fail("parse timeline", e));
+    let mut runner = Runner::new(instrument, sd);
+
+    let length = match timeline.end() {
+        Some(end) => end as usize * SAMPLE_RATE as usize / 1000,
+        None if !input.is_empty() => input.len(),
+        None => fail("render", "there is no input and the timeline has no end"),
+    };
+
+    writeln!(log, "frame,gate1,gate2,gate3,cv1,cv2").unwrap();
+    let mut snapshot = InputSnapshot {
+        pot: Pot { value: 0.0 },
+        cv: [Cv { value: 0.0 }; 5],
+    };
+    let mut events = timeline.events.iter().peekable();
+    let mut last_output = None;
+
+    for start in (0..length).step_by(BLOCK_LENGTH) {
+        let time = (start as u64 * 1000 / SAMPLE_RATE as u64) as u32;
+        while let Some((_, event)) = events.next_if(|(t, _)| *t <= time) {
+            match event {
+                Event::Pot(value) => snapshot.pot.value = *value,
+                Event::Cv(input, value) => snapshot.cv[*input].value = *value,
+                Event::Reaction(reaction) => runner.execute(*reaction),
+                Event::End => (),
+            }
+        }
+        runner.update_control(snapshot);
+
+        let mut buffer = [(0.0, 0.0); BLOCK_LENGTH];
+        for (i, frame) in buffer.iter_mut().enumerate() {
+            *frame = input.get(start + i).copied().unwrap_or((0.0, 0.0));
+        }
+        runner.process(&mut buffer);
+        let frames = BLOCK_LENGTH.min(length - start);
+        writer
+            .write(&buffer[..frames])
+            .unwrap_or_else(|e| fail("write output", e));
+
+        let output = format_output(&runner.control_output());
+        if last_output.as_ref() != Some(&output) {
+            writeln!(log, "{start},{output}").unwrap_or_else(|e| fail("write log", e));
+            last_output = Some(output);
+        }
+    }
+
+    writer.finish().unwrap_or_else(|e| fail("finish output", e));
+    log.flush().unwrap_or_else(|e| fail("finish log", e));
+}
+
+fn format_output(output: &OutputRequest) -> String {
+    let [gate1, gate2, gate3] = output.gate.map(|g| u8::from(g.value));
+    let [cv1, cv2] = output.cv.map(|c| c.value);
+    format!("{gate1},{gate2},{gate3},{cv1},{cv2}")
+}
+
+fn fail(action: &str, error: impl std::fmt::Display) -> ! {
+    eprintln!("Failed to {action}: {error}");
+    process::exit(1);
+}
@@ -0,0 +1,225 @@
+//! Play an instrument in a window showing the display of the module.
+//!
+//! Run it with the name of the instrument, the disk image emulating the SD
+//! card, the WAV file recording the output and optionally a WAV file looped
+//! as the input:
+//!
+//! ```sh
+//! cargo run --bin simulator -- traky sd.img output.wav [input.wav]
+//! ```
+//!
+//! With `--verbose` given before the arguments, reactions of the UI passed
+//! to the instrument and notifications coming back from it are printed.
+//!
+//! The encoder is turned by up and down arrows and clicked by enter or left
+//! and right arrows. Page up and down turn it while held, `D` double clicks
+//! and `L` long presses it. The pot is moved by `Q` and `A`. Keys `1` to `5` select
+//! a CV input, which is then moved by `W` and `S` or held high by space, to
+//! send gates and triggers. Changes of gate outputs are printed. Close the
+//! window or press escape to quit, the output file is finished only then.
+
+use std::time::{Duration, Instant};
+use std::{env, process, thread};
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics_simulator::sdl2::Keycode;
+use embedded_graphics_simulator::{
+    OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+
+use proton_control::input_snapshot::{Cv, InputSnapshot, Pot};
+use proton_instruments_interface::Instrument as _;
+use proton_instruments_registry::{Instrument, NAMES};
+use proton_simulator::block_device::FileBlockDevice;
+use proton_simulator::memory::memory_manager;
+use proton_simulator::runner::Runner;
+use proton_simulator::wav::{self, WavWriter};
+use proton_simulator::{BLOCK_LENGTH, SAMPLE_RATE};
+use proton_ui::action::Action;
+use proton_ui::display::{draw, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use proton_ui::notification;
+use proton_ui::reducer;
+use proton_ui::view::View;
+
+// Step of the pot and CV inputs moved by keys.
+const KEY_STEP: f32 = 0.05;
+const FRAME: Duration = Duration::from_millis(10);
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let verbose = args.get(1).is_some_and(|arg| arg == "--verbose");
+    if verbose {
+        args.remove(1);
+    }
+    if args.len() < 4 || args.len() > 5 || !NAMES.contains(&args[1].as_str()) {
+        eprintln!(
+            "Usage: {} [--verbose] <{}> <sd.img> <output.wav> [input.wav]",
+            args[0],
+            NAMES.join("|")
+        );
+        process::exit(1);
+    }
+
+    let mut sd = FileBlockDevice::open(&args[2]).unwrap_or_else(|e| fail("open SD image", e));
+    let mut writer =
+        WavWriter::create(&args[3], SAMPLE_RATE).unwrap_or_else(|e| fail("create output", e));
+    let input = match args.get(4) {
+        Some(path) => {
+            wav::read(path)
+                .unwrap_or_else(|e| fail("read input", e))
+                .frames
+        }
+        None => vec![(0.0, 0.0)],
+    };
+
+    let mut memory_manager = memory_manager();
+    let instrument = Instrument::with_name(&args[1], SAMPLE_RATE, &mut memory_manager, &mut sd);
+    let mut state = instrument.state();
+    let mut runner = Runner::new(instrument, sd);
+
+    let mut display: SimulatorDisplay<BinaryColor> =
+        SimulatorDisplay::new(Size::new(DISPLAY_WIDTH, DISPLAY_HEIGHT));
+    let output_settings = OutputSettingsBuilder::new().scale(2).build();
+    let mut window = Window::new("Proton", &output_settings);
+
+    let mut controls = Controls::new();
+    let mut gates = [false; 3];
+    let mut input_position = 0;
+    let mut blocks: u64 = 0;
+    let start = Instant::now();
+
+    'running: loop {
+        for event in window.events() {
+            let action = match event {
+                SimulatorEvent::Quit => break 'running,
+                SimulatorEvent::KeyDown { keycode, .. } => {
+                    controls.key_down(keycode, start.elapsed().as_millis() as u32)
+                }
+                SimulatorEvent::KeyUp { keycode, .. } => {
+                    controls.key_up(keycode);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(action) = action {
+                if let Some(reaction) = reducer::reduce(action, &mut state) {
+                    if verbose {
+                        println!("Reaction: {:?}", reaction);
+                    }
+                    runner.execute(reaction);
+                }
+            }
+        }
+
+        runner.update_control(controls.snapshot());
+
+        // Audio is rendered in real time, catching up with the wall clock
+        let due = start.elapsed().as_secs_f64() * SAMPLE_RATE as f64 / BLOCK_LENGTH as f64;
+        while (blocks as f64) < due {
+            let mut buffer = [(0.0, 0.0); BLOCK_LENGTH];
+            for frame in buffer.iter_mut() {
+                *frame = input[input_position];
+                input_position = (input_position + 1) % input.len();
+            }
+            runner.process(&mut buffer);
+            writer
+                .write(&buffer)
+                .unwrap_or_else(|e| fail("write output", e));
+            blocks += 1;
+        }
+
+        while let Some(notification) = runner.instrument.notification() {
+            if verbose {
+                println!("Notification: {:?}", notification);
+            }
+            notification::apply(notification, &mut state);
+        }
+
+        let output = runner.control_output();
+        for (i, (gate, requested)) in gates.iter_mut().zip(output.gate).enumerate() {
+            if *gate != requested.value {
+                *gate = requested.value;
+                println!("Gate {}: {}", i + 1, if *gate { "high" } else { "low" });
+            }
+        }
+
+        let view: View = (&state).into();
+        draw(&mut display, &view).unwrap();
+        window.update(&display);
+
+        thread::sleep(FRAME);
+    }
+
+    writer.finish().unwrap_or_else(|e| fail("finish output", e));
+}
+
+fn fail(action: &str, error: impl std::fmt::Display) -> ! {
+    eprintln!("Failed to {action}: {error}");
+    process::exit(1);
+}
+
+struct Controls {
+    pot: f32,
+    cv: [f32; 5],
+    selected_cv: usize,
+    held: bool,
+}
+
+impl Controls {
+    fn new() -> Self {
+        Self {
+            pot: 0.0,
+            cv: [0.0; 5],
+            selected_cv: 0,
+            held: false,
+        }
+    }
+
+    fn key_down(&mut self, keycode: Keycode, time: u32) -> Option<Action> {
+        match keycode {
+            Keycode::Up => return Some(Action::EncoderUp(time)),
+            Keycode::Down => return Some(Action::EncoderDown(time)),
+            Keycode::PageUp => return Some(Action::PressedUp(time)),
+            Keycode::PageDown => return Some(Action::PressedDown(time)),
+            Keycode::D => return Some(Action::DoubleClick),
+            Keycode::L => return Some(Action::LongPress),
+            Keycode::Return | Keycode::Left | Keycode::Right => return Some(Action::EncoderClick),
+            Keycode::Q => self.pot = (self.pot + KEY_STEP).min(1.0),
+            Keycode::A => self.pot = (self.pot - KEY_STEP).max(0.0),
+            Keycode::Num1 => self.selected_cv = 0,
+            Keycode::Num2 => self.selected_cv = 1,
+            Keycode::Num3 => self.selected_cv = 2,
+            Keycode::Num4 => self.selected_cv = 3,
+            Keycode::Num5 => self.selected_cv = 4,
+            Keycode::W => {
+                let cv = &mut self.cv[self.selected_cv];
+                *cv = (*cv + KEY_STEP).min(1.0);
+            }
+            Keycode::S => {
+                let cv = &mut self.cv[self.selected_cv];
+                *cv = (*cv - KEY_STEP).max(0.0);
+            }
+            Keycode::Space => self.held = true,
+            _ => (),
+        }
+        None
+    }
+
+    fn key_up(&mut self, keycode: Keycode) {
+        if keycode == Keycode::Space {
+            self.held = false;
+        }
+    }
+
+    fn snapshot(&self) -> InputSnapshot {
+        let mut cv = self.cv;
+        if self.held {
+            cv[self.selected_cv] = 1.0;
+        }
+        InputSnapshot {
+            pot: Pot { value: self.pot },
+            cv: cv.map(|value| Cv { value }),
+        }
+    }
+}
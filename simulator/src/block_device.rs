@@ -0,0 +1,97 @@
+//! SD card backed by a disk image file.
+//!
+//! The image must have an MBR with a FAT partition, same as the card. It can
+//! be created with `sfdisk` and populated using `mtools`:
+//!
+//! ```sh
+//! truncate -s 64M sd.img
+//! echo 'start=2048, type=c' | sfdisk sd.img
+//! mformat -i sd.img@@1M -F ::
+//! mcopy -i sd.img@@1M sample.wav ::
+//! ```
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use embedded_sdmmc::blockdevice::{Block, BlockCount, BlockDevice, BlockIdx};
+
+pub struct FileBlockDevice {
+    // The trait takes the device by a shared reference, even for writes.
+    file: RefCell<File>,
+}
+
+impl FileBlockDevice {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self {
+            file: RefCell::new(file),
+        })
+    }
+}
+
+impl BlockDevice for FileBlockDevice {
+    type Error = io::Error;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Self::Error> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset(start_block_idx)))?;
+        for block in blocks.iter_mut() {
+            file.read_exact(&mut block.contents)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset(start_block_idx)))?;
+        for block in blocks {
+            file.write_all(&block.contents)?;
+        }
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        let length = self.file.borrow().metadata()?.len();
+        Ok(BlockCount((length / Block::LEN as u64) as u32))
+    }
+}
+
+fn offset(block_idx: BlockIdx) -> u64 {
+    block_idx.0 as u64 * Block::LEN as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_blocks_are_written_they_can_be_read_back() {
+        let path = std::env::temp_dir().join(format!("proton-block-{}.img", std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .set_len(4 * Block::LEN as u64)
+            .unwrap();
+        let device = FileBlockDevice::open(&path).unwrap();
+
+        let mut written = [Block::new(), Block::new()];
+        written[0].contents[0] = 1;
+        written[1].contents[Block::LEN - 1] = 2;
+        device.write(&written, BlockIdx(1)).unwrap();
+
+        let mut read = [Block::new(), Block::new(), Block::new()];
+        device.read(&mut read, BlockIdx(0), "test").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(device.num_blocks().unwrap(), BlockCount(4));
+        assert!(read[0].contents.iter().all(|b| *b == 0));
+        assert_eq!(read[1].contents[0], 1);
+        assert_eq!(read[2].contents[Block::LEN - 1], 2);
+    }
+}
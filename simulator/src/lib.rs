@@ -0,0 +1,19 @@
+//! Run instruments on the host, so they can be developed without the module.
+//!
+//! The pieces here stand in for the hardware of the firmware. The SD card is
+//! emulated by a disk image, the SDRAM by memory allocated on the heap and
+//! the codec by WAV files.
+
+pub mod block_device;
+mod logger;
+pub mod memory;
+pub mod runner;
+pub mod timeline;
+pub mod wav;
+
+/// Sample rate of the codec of the module.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Number of frames passed to the instrument at once, same as the DMA buffer
+/// of the module.
+pub const BLOCK_LENGTH: usize = 32;
@@ -0,0 +1,15 @@
+//! Memory of the instrument allocated on the heap instead of the SDRAM.
+
+use std::mem::MaybeUninit;
+
+use proton_instruments_interface::MemoryManager;
+
+/// Size of the SDRAM of the Daisy Seed in bytes.
+pub const SDRAM_SIZE: usize = 64 * 1024 * 1024;
+
+/// The memory is leaked, same as the SDRAM, it lives as long as the program.
+pub fn memory_manager() -> MemoryManager {
+    let words = SDRAM_SIZE / core::mem::size_of::<MaybeUninit<u32>>();
+    let slice = vec![MaybeUninit::uninit(); words].into_boxed_slice();
+    MemoryManager::from(Box::leak(slice))
+}
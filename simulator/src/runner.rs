@@ -0,0 +1,73 @@
+//! Drive the instrument the same way the firmware does.
+//!
+//! In the firmware, storage tasks run in a low-priority routine concurrently
+//! with the audio interrupt. On the host, they are run right after the block
+//! that handed them over, so the simulation stays deterministic.
+
+use core::convert::TryInto;
+
+use proton_control::input_snapshot::InputSnapshot;
+use proton_control::output_request::OutputRequest;
+use proton_instruments_interface::{Instrument, Rand, StorageTask as _};
+use proton_ui::reaction::Reaction;
+
+use crate::block_device::FileBlockDevice;
+use crate::BLOCK_LENGTH;
+
+pub struct Runner<I> {
+    pub instrument: I,
+    sd: FileBlockDevice,
+    randomizer: Randomizer,
+}
+
+impl<I: Instrument> Runner<I> {
+    pub fn new(instrument: I, sd: FileBlockDevice) -> Self {
+        Self {
+            instrument,
+            sd,
+            randomizer: Randomizer::new(),
+        }
+    }
+
+    /// Reactions the instrument does not understand are ignored.
+    pub fn execute(&mut self, reaction: Reaction) {
+        if let Ok(command) = reaction.try_into() {
+            self.instrument.execute(command);
+        }
+    }
+
+    pub fn update_control(&mut self, snapshot: InputSnapshot) {
+        self.instrument.update_control(snapshot);
+    }
+
+    /// The buffer holds the input audio and gets overwritten by the output.
+    pub fn process(&mut self, buffer: &mut [(f32, f32); BLOCK_LENGTH]) {
+        self.instrument
+            .process(&mut buffer[..], &mut self.randomizer);
+        if let Some(mut task) = self.instrument.storage_task() {
+            task.run(&mut self.sd);
+            self.instrument.finish_storage_task(task);
+        }
+    }
+
+    pub fn control_output(&self) -> OutputRequest {
+        self.instrument.control_output()
+    }
+}
+
+// Linear congruential generator with a fixed seed, so two runs with the
+// same input give the same output.
+struct Randomizer(u16);
+
+impl Randomizer {
+    fn new() -> Self {
+        Self(0)
+    }
+}
+
+impl Rand for Randomizer {
+    fn generate(&mut self) -> u16 {
+        self.0 = self.0.wrapping_mul(25_173).wrapping_add(13_849);
+        self.0
+    }
+}
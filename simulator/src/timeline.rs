@@ -0,0 +1,250 @@
+//! Script of control changes and UI reactions applied during offline render.
+//!
+//! Each line starts with the time in milliseconds followed by the event:
+//!
+//! ```text
+//! # Comments start with a hash
+//! 0 set volume 0.8
+//! 0 select mode granular
+//! 0 file sample KICK.WAV
+//! 0 set play on
+//! 0 trigger restart
+//! 100 pot 0.5
+//! 250 cv 2 1.0
+//! 260 cv 2 0.0
+//! 5000 end
+//! ```
+//!
+//! `set`, `select`, `file` and `trigger` behave as if the attribute of the
+//! given name was changed or clicked in the UI. `set` takes a number, or `on`
+//! and `off` for switches. `pot` and `cv` set the value of the control input,
+//! CV inputs are numbered from 1 as on the panel. Values stay until changed
+//! again. `end` marks the end of the render. Events do not have to be sorted,
+//! but those of the same time are applied in the order they were written.
+
+use std::fmt;
+
+use proton_ui::reaction::Reaction;
+use proton_ui::state::{Attribute, State, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Pot(f32),
+    /// Index of the CV input, starting from 0, and its value.
+    Cv(usize, f32),
+    Reaction(Reaction),
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Timeline {
+    /// Events sorted by their time in milliseconds.
+    pub events: Vec<(u32, Event)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line of the script, starting from 1.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Timeline {
+    /// Names of attributes are looked up in the state of the instrument, so
+    /// reactions carry the same names the UI would send.
+    pub fn parse(text: &str, state: &State) -> Result<Self, ParseError> {
+        let mut events = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason| ParseError {
+                line: i + 1,
+                reason,
+            };
+            let mut words = line.split_whitespace();
+            let time = words
+                .next()
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| error("invalid time"))?;
+            let words: Vec<&str> = words.collect();
+            let event = parse_event(&words, state).map_err(error)?;
+            events.push((time, event));
+        }
+        events.sort_by_key(|(time, _)| *time);
+        Ok(Self { events })
+    }
+
+    /// Time of the end of the render, if the script defines it.
+    pub fn end(&self) -> Option<u32> {
+        self.events
+            .iter()
+            .find(|(_, event)| *event == Event::End)
+            .map(|(time, _)| *time)
+    }
+}
+
+fn parse_event(words: &[&str], state: &State) -> Result<Event, &'static str> {
+    match words {
+        ["pot", value] => Ok(Event::Pot(parse_f32(value)?)),
+        ["cv", input, value] => {
+            let input: usize = input.parse().map_err(|_| "invalid CV input")?;
+            if !(1..=5).contains(&input) {
+                return Err("CV input must be between 1 and 5");
+            }
+            Ok(Event::Cv(input - 1, parse_f32(value)?))
+        }
+        ["set", name, value] => {
+            let attribute = attribute(state, name)?;
+            let name = attribute.name;
+            let reaction = match attribute.value {
+                Value::F32(_) => Reaction::SetValue(name, parse_f32(value)?),
+                Value::I32(_) => {
+                    Reaction::SetI32(name, value.parse().map_err(|_| "invalid integer")?)
+                }
+                Value::Bool(_) => match *value {
+                    "on" => Reaction::SetBool(name, true),
+                    "off" => Reaction::SetBool(name, false),
+                    _ => return Err("switch must be on or off"),
+                },
+                _ => return Err("attribute of another type"),
+            };
+            Ok(Event::Reaction(reaction))
+        }
+        ["select", name, option] => {
+            let name = find_attribute(state, name, |v| matches!(v, Value::Select(_)))?;
+            let option = state
+                .attributes
+                .iter()
+                .find_map(|a| match &a.value {
+                    Value::Select(select) if a.name == name => {
+                        select.available.iter().find(|o| *o == option).copied()
+                    }
+                    _ => None,
+                })
+                .ok_or("unknown option")?;
+            Ok(Event::Reaction(Reaction::SelectValue(name, option)))
+        }
+        ["file", name, file] => {
+            let name = find_attribute(state, name, |v| matches!(v, Value::File(_)))?;
+            let index = state
+                .browser
+                .entries
+                .iter()
+                .position(|e| !e.is_directory && e.name.as_str() == *file)
+                .ok_or("unknown file")?;
+            Ok(Event::Reaction(Reaction::SelectFile(name, index)))
+        }
+        ["trigger", name] => {
+            let name = find_attribute(state, name, |v| matches!(v, Value::Trigger))?;
+            Ok(Event::Reaction(Reaction::Trigger(name)))
+        }
+        ["end"] => Ok(Event::End),
+        _ => Err("unknown event"),
+    }
+}
+
+fn attribute<'a>(state: &'a State, name: &str) -> Result<&'a Attribute, &'static str> {
+    state
+        .attributes
+        .iter()
+        .find(|a| a.name == name)
+        .ok_or("unknown attribute")
+}
+
+fn find_attribute(
+    state: &State,
+    name: &str,
+    is_expected: impl Fn(&Value) -> bool,
+) -> Result<&'static str, &'static str> {
+    let attribute = attribute(state, name)?;
+    if !is_expected(&attribute.value) {
+        return Err("attribute of another type");
+    }
+    Ok(attribute.name)
+}
+
+fn parse_f32(value: &str) -> Result<f32, &'static str> {
+    value.parse().map_err(|_| "invalid number")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_ui::browser::{Browser, Entry};
+    use proton_ui::state::{ValueBool, ValueF32, ValueFile, ValueI32, ValueSelect};
+
+    fn state() -> State {
+        let mut browser = Browser::new();
+        browser.push(Entry::directory("DRUMS", None)).unwrap();
+        browser.push(Entry::file("KICK.WAV", None)).unwrap();
+        State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("sample").with_value_file(ValueFile::new()),
+                Attribute::new("volume").with_value_f32(ValueF32::new(0.5)),
+                Attribute::new("mode").with_value_select(ValueSelect::new(&["a", "b"]).unwrap()),
+                Attribute::new("play").with_value_bool(ValueBool::new(true)),
+                Attribute::new("steps").with_value_i32(ValueI32::new(4)),
+                Attribute::new("restart").with_value_trigger(),
+            ])
+            .unwrap()
+            .with_browser(browser)
+    }
+
+    #[test]
+    fn when_parsing_script_it_sorts_events_by_time() {
+        let text = "\
+            # Setup\n\
+            100 cv 2 1.0\n\
+            0 set volume 0.8\n\
+            \n\
+            0 select mode b\n\
+            0 file sample KICK.WAV\n\
+            50 pot 0.25\n\
+            60 set play off\n\
+            60 set steps 8\n\
+            60 trigger restart\n\
+            200 end\n";
+
+        let timeline = Timeline::parse(text, &state()).unwrap();
+
+        assert_eq!(
+            timeline.events,
+            vec![
+                (0, Event::Reaction(Reaction::SetValue("volume", 0.8))),
+                (0, Event::Reaction(Reaction::SelectValue("mode", "b"))),
+                (0, Event::Reaction(Reaction::SelectFile("sample", 1))),
+                (50, Event::Pot(0.25)),
+                (60, Event::Reaction(Reaction::SetBool("play", false))),
+                (60, Event::Reaction(Reaction::SetI32("steps", 8))),
+                (60, Event::Reaction(Reaction::Trigger("restart"))),
+                (100, Event::Cv(1, 1.0)),
+                (200, Event::End),
+            ]
+        );
+        assert_eq!(timeline.end(), Some(200));
+    }
+
+    #[test]
+    fn when_script_does_not_match_instrument_it_reports_the_line() {
+        let state = state();
+
+        let error = |text| Timeline::parse(text, &state).unwrap_err();
+
+        assert_eq!(error("0 pot 1\nx pot 1").line, 2);
+        assert_eq!(error("0 set gain 1").reason, "unknown attribute");
+        assert_eq!(error("0 set mode 1").reason, "attribute of another type");
+        assert_eq!(error("0 select mode z").reason, "unknown option");
+        assert_eq!(error("0 set play 1").reason, "switch must be on or off");
+        assert_eq!(error("0 trigger play").reason, "attribute of another type");
+        assert_eq!(error("0 file sample DRUMS").reason, "unknown file");
+        assert_eq!(error("0 cv 6 1").reason, "CV input must be between 1 and 5");
+    }
+}
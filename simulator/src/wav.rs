@@ -0,0 +1,212 @@
+//! Reading and writing of stereo WAV files standing in for the codec.
+//!
+//! Output is always written as 32 bit float. Input may be 16 or 24 bit PCM or
+//! 32 bit float, mono or stereo. Mono input is played on both channels.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// Size of the header preceding the samples in written files.
+const HEADER_SIZE: u32 = 44;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    frames: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            frames: 0,
+        };
+        writer.write_header(sample_rate)?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, frames: &[(f32, f32)]) -> io::Result<()> {
+        for (left, right) in frames {
+            self.file.write_all(&left.to_le_bytes())?;
+            self.file.write_all(&right.to_le_bytes())?;
+        }
+        self.frames += frames.len() as u32;
+        Ok(())
+    }
+
+    /// Sizes in the header are known only once all the frames are written.
+    pub fn finish(mut self) -> io::Result<()> {
+        let data_size = self.frames * 8;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> io::Result<()> {
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+        file.write_all(b"fmt ")?;
+        file.write_all(&16_u32.to_le_bytes())?;
+        file.write_all(&FORMAT_FLOAT.to_le_bytes())?;
+        file.write_all(&2_u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * 8).to_le_bytes())?;
+        file.write_all(&8_u16.to_le_bytes())?;
+        file.write_all(&32_u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0_u32.to_le_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub frames: Vec<(f32, f32)>,
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Wav> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    parse(&bytes).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unsupported WAV"))
+}
+
+fn parse(bytes: &[u8]) -> Option<Wav> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32_at(bytes, position + 4)? as usize;
+        let start = position + 8;
+        let body = bytes.get(start..(start + size).min(bytes.len()))?;
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => data = Some(body),
+            _ => (),
+        }
+        // Chunks are aligned to two bytes
+        position = start + size + size % 2;
+    }
+
+    let format = format?;
+    let mut tag = u16_at(format, 0)?;
+    if tag == FORMAT_EXTENSIBLE {
+        tag = u16_at(format, 24)?;
+    }
+    let channels = u16_at(format, 2)? as usize;
+    let sample_rate = u32_at(format, 4)?;
+    let bits = u16_at(format, 14)?;
+
+    let sample: fn(&[u8]) -> f32 = match (tag, bits) {
+        (FORMAT_PCM, 16) => pcm_16,
+        (FORMAT_PCM, 24) => pcm_24,
+        (FORMAT_FLOAT, 32) => float_32,
+        _ => return None,
+    };
+    if channels == 0 || channels > 2 {
+        return None;
+    }
+
+    let frame_size = channels * bits as usize / 8;
+    let frames = data?
+        .chunks_exact(frame_size)
+        .map(|frame| {
+            let left = sample(frame);
+            let right = if channels == 2 {
+                sample(&frame[frame_size / 2..])
+            } else {
+                left
+            };
+            (left, right)
+        })
+        .collect();
+
+    Some(Wav {
+        sample_rate,
+        frames,
+    })
+}
+
+fn pcm_16(b: &[u8]) -> f32 {
+    i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0
+}
+
+// The sample is shifted to the top of i32 to keep its sign.
+fn pcm_24(b: &[u8]) -> f32 {
+    i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0
+}
+
+fn float_32(b: &[u8]) -> f32 {
+    f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn u16_at(bytes: &[u8], position: usize) -> Option<u16> {
+    let b = bytes.get(position..position + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], position: usize) -> Option<u32> {
+    let b = bytes.get(position..position + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_written_frames_are_read_back_they_match() {
+        let path = std::env::temp_dir().join(format!("proton-wav-{}.wav", std::process::id()));
+        let frames = vec![(0.5, -0.5), (0.25, 1.0), (-1.0, 0.0)];
+
+        let mut writer = WavWriter::create(&path, 48_000).unwrap();
+        writer.write(&frames[..2]).unwrap();
+        writer.write(&frames[2..]).unwrap();
+        writer.finish().unwrap();
+        let wav = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            wav,
+            Wav {
+                sample_rate: 48_000,
+                frames
+            }
+        );
+    }
+
+    #[test]
+    fn when_reading_mono_pcm_it_plays_it_on_both_channels() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF\0\0\0\0WAVEfmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        bytes.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&44_100_u32.to_le_bytes());
+        bytes.extend_from_slice(&88_200_u32.to_le_bytes());
+        bytes.extend_from_slice(&2_u16.to_le_bytes());
+        bytes.extend_from_slice(&16_u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&4_u32.to_le_bytes());
+        bytes.extend_from_slice(&16_384_i16.to_le_bytes());
+        bytes.extend_from_slice(&(-32_768_i16).to_le_bytes());
+
+        let wav = parse(&bytes).unwrap();
+
+        assert_eq!(wav.sample_rate, 44_100);
+        assert_eq!(wav.frames, vec![(0.5, 0.5), (-1.0, -1.0)]);
+    }
+}
@@ -1,5 +1,6 @@
 use core::fmt;
-use std::{thread, time::Duration};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
 use embedded_graphics_simulator::{
@@ -7,6 +8,7 @@ use embedded_graphics_simulator::{
 };
 
 use proton_ui::action::Action;
+use proton_ui::browser::{Browser, Entry};
 use proton_ui::display::*;
 use proton_ui::reducer;
 use proton_ui::state::*;
@@ -21,8 +23,15 @@ fn main() -> Result<(), core::convert::Infallible> {
     let output_settings = OutputSettingsBuilder::new().scale(2).build();
     let mut window = Window::new("Fonts", &output_settings);
 
+    let mut browser = Browser::new();
+    let drums = browser.push(Entry::directory("DRUMS", None)).unwrap();
+    browser.push(Entry::file("PROJECT.WAV", None)).unwrap();
+    browser.push(Entry::file("KICK.WAV", Some(drums))).unwrap();
+    browser.push(Entry::file("SNARE.WAV", Some(drums))).unwrap();
+
     let mut state = State::new("Proton")
         .with_attributes(&[
+            Attribute::new("sample").with_value_file(ValueFile::new()),
             Attribute::new("scale")
                 .with_value_select(ValueSelect::new(&["major", "minor"]).unwrap()),
             Attribute::new("root").with_value_select(ValueSelect::new(&["c", "c#"]).unwrap()),
@@ -34,22 +43,27 @@ fn main() -> Result<(), core::convert::Infallible> {
                     .with_writter(speed_writter),
             ),
         ])
-        .unwrap();
+        .unwrap()
+        .with_browser(browser);
 
     let view = (&state).into();
     draw(&mut display, &view)?;
     window.update(&display);
 
+    let start = Instant::now();
     'running: loop {
         for event in window.events() {
             match event {
                 SimulatorEvent::Quit => break 'running Ok(()),
                 SimulatorEvent::KeyDown { keycode, .. } => {
+                    let time = start.elapsed().as_millis() as u32;
                     let action = match keycode {
                         Keycode::Left => Some(Action::EncoderClick),
                         Keycode::Right => Some(Action::EncoderClick),
-                        Keycode::Up => Some(Action::EncoderUp),
-                        Keycode::Down => Some(Action::EncoderDown),
+                        Keycode::Up => Some(Action::EncoderUp(time)),
+                        Keycode::Down => Some(Action::EncoderDown(time)),
+                        Keycode::D => Some(Action::DoubleClick),
+                        Keycode::L => Some(Action::LongPress),
                         _ => None,
                     };
                     if let Some(action) = action {
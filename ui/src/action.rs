@@ -1,7 +1,42 @@
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Action {
-    EncoderDown,
-    EncoderUp,
+    /// Time of the detent in milliseconds, used to speed up editing when
+    /// the encoder is turned quickly.
+    EncoderDown(u32),
+    EncoderUp(u32),
     EncoderClick,
+    DoubleClick,
+    LongPress,
+    /// Turns of the encoder while its button is held.
+    PressedDown(u32),
+    PressedUp(u32),
+}
+
+impl Action {
+    /// Plain turns and clicks of the encoder. Simple menus, such as the
+    /// instrument selection on boot, ignore the rest, so fine editing or
+    /// resetting cannot be triggered where it has no meaning.
+    pub fn is_plain(&self) -> bool {
+        matches!(
+            self,
+            Self::EncoderDown(_) | Self::EncoderUp(_) | Self::EncoderClick
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_checking_actions_only_turns_and_clicks_are_plain() {
+        assert!(Action::EncoderDown(0).is_plain());
+        assert!(Action::EncoderUp(0).is_plain());
+        assert!(Action::EncoderClick.is_plain());
+        assert!(!Action::DoubleClick.is_plain());
+        assert!(!Action::LongPress.is_plain());
+        assert!(!Action::PressedDown(0).is_plain());
+        assert!(!Action::PressedUp(0).is_plain());
+    }
 }
@@ -0,0 +1,230 @@
+use core::fmt;
+
+use heapless::Vec;
+
+pub const BROWSER_CAPACITY: usize = 64;
+pub const MAX_DEPTH: usize = 4;
+
+/// Tree of files and directories, flattened into a list.
+///
+/// Each entry points to its parent directory. Entries without a parent are
+/// in the root. The browser keeps track of the currently open directory and
+/// the selected item in its listing. The first item of every listing leads
+/// to the parent directory, or out of the browser when in the root.
+#[derive(Clone, Debug, Default)]
+pub struct Browser {
+    pub entries: Vec<Entry, BROWSER_CAPACITY>,
+    pub directory: Option<usize>,
+    pub selected: usize,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: FileName,
+    pub parent: Option<usize>,
+    pub is_directory: bool,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug)]
+pub enum BrowserError {
+    EntriesFull,
+}
+
+/// Name of a file in the 8.3 format.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FileName {
+    bytes: [u8; 12],
+    length: usize,
+}
+
+impl FileName {
+    /// Create the name from a string, cutting it off if it does not fit.
+    pub fn new(name: &str) -> Self {
+        let mut bytes = [0; 12];
+        let mut length = 0;
+        for c in name.chars() {
+            if length + c.len_utf8() > bytes.len() {
+                break;
+            }
+            c.encode_utf8(&mut bytes[length..]);
+            length += c.len_utf8();
+        }
+        Self { bytes, length }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap()
+    }
+}
+
+impl fmt::Debug for FileName {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "FileName({})", self.as_str())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for FileName {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "FileName({})", self.as_str());
+    }
+}
+
+impl Entry {
+    pub fn file(name: &str, parent: Option<usize>) -> Self {
+        Self {
+            name: FileName::new(name),
+            parent,
+            is_directory: false,
+        }
+    }
+
+    pub fn directory(name: &str, parent: Option<usize>) -> Self {
+        Self {
+            name: FileName::new(name),
+            parent,
+            is_directory: true,
+        }
+    }
+}
+
+impl Browser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry and return its index, so it can be used as a parent.
+    pub fn push(&mut self, entry: Entry) -> Result<usize, BrowserError> {
+        self.entries
+            .push(entry)
+            .map_err(|_| BrowserError::EntriesFull)?;
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Indices of entries in the currently open directory.
+    pub fn listing(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, e)| e.parent == self.directory)
+            .map(|(i, _)| i)
+    }
+
+    /// Number of items in the listing, including the one leading back.
+    pub fn listing_length(&self) -> usize {
+        self.listing().count() + 1
+    }
+
+    /// Index of the selected entry, `None` if the item leading back is
+    /// selected.
+    pub fn selected_entry(&self) -> Option<usize> {
+        if self.selected == 0 {
+            None
+        } else {
+            self.listing().nth(self.selected - 1)
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.listing_length();
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.selected == 0 {
+            self.selected = self.listing_length() - 1;
+        } else {
+            self.selected -= 1;
+        }
+    }
+
+    pub fn enter(&mut self, directory: usize) {
+        self.directory = Some(directory);
+        self.selected = 0;
+    }
+
+    /// Go to the parent directory. Returns false if already in the root.
+    pub fn leave(&mut self) -> bool {
+        match self.directory {
+            Some(directory) => {
+                self.directory = self.entries[directory].parent;
+                self.selected = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of directories leading to the entry, followed by the name of
+    /// the entry itself.
+    pub fn path(&self, index: usize) -> Vec<FileName, MAX_DEPTH> {
+        let mut path = Vec::new();
+        let mut current = Some(index);
+        while let Some(index) = current {
+            // Deeper entries are never listed
+            let _ = path.push(self.entries[index].name);
+            current = self.entries[index].parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn browser() -> Browser {
+        let mut browser = Browser::new();
+        let drums = browser.push(Entry::directory("DRUMS", None)).unwrap();
+        browser.push(Entry::file("PROJECT.WAV", None)).unwrap();
+        browser.push(Entry::file("KICK.WAV", Some(drums))).unwrap();
+        browser.push(Entry::file("SNARE.WAV", Some(drums))).unwrap();
+        browser
+    }
+
+    #[test]
+    fn when_browsing_root_it_lists_only_root_entries() {
+        let browser = browser();
+
+        assert_eq!(browser.listing_length(), 3);
+        assert_eq!(browser.selected_entry(), None);
+
+        let mut listing = browser.listing();
+        assert_eq!(listing.next(), Some(0));
+        assert_eq!(listing.next(), Some(1));
+        assert_eq!(listing.next(), None);
+    }
+
+    #[test]
+    fn when_entering_directory_it_lists_its_entries_and_can_leave_it() {
+        let mut browser = browser();
+
+        browser.select_next();
+        browser.enter(browser.selected_entry().unwrap());
+        browser.select_previous();
+        assert_eq!(browser.selected_entry(), Some(3));
+
+        assert!(browser.leave());
+        assert_eq!(browser.directory, None);
+        assert_eq!(browser.selected, 0);
+        assert!(!browser.leave());
+    }
+
+    #[test]
+    fn when_asked_for_path_it_lists_names_from_root() {
+        let browser = browser();
+
+        let path = browser.path(3);
+
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[0].as_str(), "DRUMS");
+        assert_eq!(path[1].as_str(), "SNARE.WAV");
+    }
+
+    #[test]
+    fn when_name_is_too_long_it_is_cut_off() {
+        assert_eq!(FileName::new("LONGNAME.WAVE").as_str(), "LONGNAME.WAV");
+    }
+}
@@ -6,6 +6,9 @@ use embedded_graphics::{
     text::Text,
 };
 
+use core::fmt::Write as _;
+
+use crate::presets::{PresetAction, PresetsItem, CV_INPUTS};
 use crate::view::*;
 
 pub const DISPLAY_WIDTH: u32 = 128;
@@ -16,6 +19,10 @@ const ATTRIBUTE_HEIGHT: u32 = 12;
 const ATTRIBUTE_PADDING: u32 = 5;
 const FONT_WIDTH: u32 = 6;
 const FONT_HEIGHT_ABOVE_LINE: u32 = 8;
+// Number of characters fitting into the status bar.
+const STATUS_BAR_LENGTH: usize = (DISPLAY_WIDTH / FONT_WIDTH) as usize - 1;
+// Names of CV inputs presets can be recalled by.
+const CV_NAMES: [&str; CV_INPUTS] = ["cv 1", "cv 2", "cv 3", "cv 4", "cv 5"];
 
 pub fn draw<D>(target: &mut D, view: &View) -> Result<(), D::Error>
 where
@@ -23,7 +30,16 @@ where
 {
     reset_screen(target)?;
 
-    draw_status_bar(target, view.title, view.edit)?;
+    if let Some(browser) = &view.browser {
+        return draw_browser(target, view.title, browser);
+    }
+
+    if let Some(presets) = &view.presets {
+        return draw_presets(target, presets);
+    }
+
+    let breadcrumbs = breadcrumbs(view.title, &view.path);
+    draw_status_bar(target, &breadcrumbs, view.edit, view.fine)?;
 
     for (i, attribute) in view
         .attributes
@@ -50,7 +66,8 @@ where
     )
 }
 
-fn draw_status_bar<D>(target: &mut D, text: &str, edit: bool) -> Result<(), D::Error>
+// Fine editing is marked by a shorter edit marker.
+fn draw_status_bar<D>(target: &mut D, text: &str, edit: bool, fine: bool) -> Result<(), D::Error>
 where
     D: DrawTarget<Color = BinaryColor>,
 {
@@ -66,10 +83,15 @@ where
     draw_text(target, text, Point::new(x, y), BinaryColor::On)?;
 
     if edit {
+        let height = if fine {
+            HEADER_HEIGHT / 2
+        } else {
+            HEADER_HEIGHT
+        };
         draw_rectangle(
             target,
             Point::new(DISPLAY_WIDTH as i32 - 4, 0),
-            Size::new(4, HEADER_HEIGHT),
+            Size::new(4, height),
             BinaryColor::On,
         )?;
     }
@@ -77,29 +99,165 @@ where
     Ok(())
 }
 
-fn draw_attribute<D>(
-    target: &mut D,
-    attribute: &Attribute,
-    highlighted: bool,
-    position: usize,
-) -> Result<(), D::Error>
+// Segments at the beginning of the path are replaced by ".." when it does
+// not fit, so the current page is always visible.
+fn breadcrumbs(title: &str, path: &[Option<&str>]) -> heapless::String<64> {
+    let mut segments: heapless::Vec<&str, 8> = heapless::Vec::new();
+    let _ = segments.push(title);
+    for name in path.iter().flatten() {
+        let _ = segments.push(name);
+    }
+
+    let fits = |skipped: usize| {
+        let prefix = if skipped > 0 { "../".len() } else { 0 };
+        let length: usize = segments[skipped..].iter().map(|s| s.len() + 1).sum();
+        prefix + length - 1 <= STATUS_BAR_LENGTH
+    };
+    let mut skipped = 0;
+    while skipped < segments.len() - 1 && !fits(skipped) {
+        skipped += 1;
+    }
+
+    let mut breadcrumbs = heapless::String::new();
+    if skipped > 0 {
+        let _ = breadcrumbs.push_str("..");
+    }
+    for (i, segment) in segments[skipped..].iter().enumerate() {
+        if skipped > 0 || i > 0 {
+            let _ = breadcrumbs.push('/');
+        }
+        let _ = breadcrumbs.push_str(segment);
+    }
+    breadcrumbs
+}
+
+fn draw_browser<D>(target: &mut D, title: &str, browser: &BrowserView) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let title = browser.directory.as_ref().map_or(title, |d| d.as_str());
+    draw_status_bar(target, title, true, false)?;
+
+    for (i, item) in browser.items.iter().filter_map(|i| i.as_ref()).enumerate() {
+        let highlighted = i == browser.selected;
+        let (text, background) = row_colors(highlighted);
+        let y = draw_row(target, i, background)?;
+
+        let (name, suffix) = match item {
+            BrowserItem::Back => ("..", ""),
+            BrowserItem::Directory(name) => (name.as_str(), "/"),
+            BrowserItem::File(name) => (name.as_str(), ""),
+        };
+        draw_text(
+            target,
+            name,
+            Point::new(ATTRIBUTE_PADDING as i32, y + FONT_HEIGHT_ABOVE_LINE as i32),
+            text,
+        )?;
+        let x = x_for_right_justified_text(suffix);
+        draw_text(
+            target,
+            suffix,
+            Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+            text,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn draw_presets<D>(target: &mut D, presets: &PresetsView) -> Result<(), D::Error>
 where
     D: DrawTarget<Color = BinaryColor>,
 {
-    let (text, background) = if highlighted {
+    draw_status_bar(target, "Presets", true, false)?;
+
+    for (i, item) in presets.items.iter().filter_map(|i| i.as_ref()).enumerate() {
+        let highlighted = i == presets.selected;
+        let (text, background) = row_colors(highlighted);
+        let y = draw_row(target, i, background)?;
+
+        let mut name = heapless::String::<8>::new();
+        let value = match item {
+            PresetsItem::Back => {
+                let _ = name.push_str("..");
+                ""
+            }
+            PresetsItem::Action(action) => {
+                let _ = name.push_str("action");
+                match action {
+                    PresetAction::Recall => "recall",
+                    PresetAction::Save => "save",
+                }
+            }
+            PresetsItem::Cv(input) => {
+                let _ = name.push_str("cv");
+                match input {
+                    None => "off",
+                    Some(input) => CV_NAMES[*input],
+                }
+            }
+            PresetsItem::Slot(slot, used) => {
+                let _ = write!(name, "slot {}", slot + 1);
+                if *used {
+                    "*"
+                } else {
+                    "-"
+                }
+            }
+        };
+        draw_text(
+            target,
+            &name,
+            Point::new(ATTRIBUTE_PADDING as i32, y + FONT_HEIGHT_ABOVE_LINE as i32),
+            text,
+        )?;
+        let x = x_for_right_justified_text(value);
+        draw_text(
+            target,
+            value,
+            Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+            text,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn row_colors(highlighted: bool) -> (BinaryColor, BinaryColor) {
+    if highlighted {
         (BinaryColor::Off, BinaryColor::On)
     } else {
         (BinaryColor::On, BinaryColor::Off)
-    };
+    }
+}
 
+/// Fill the background of the row and return its vertical position.
+fn draw_row<D>(target: &mut D, position: usize, background: BinaryColor) -> Result<i32, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
     let y = position as i32 * ATTRIBUTE_HEIGHT as i32 + HEADER_HEIGHT as i32;
-
     draw_rectangle(
         target,
         Point::new(0, y),
         Size::new(DISPLAY_WIDTH, ATTRIBUTE_HEIGHT),
         background,
     )?;
+    Ok(y)
+}
+
+fn draw_attribute<D>(
+    target: &mut D,
+    attribute: &Attribute,
+    highlighted: bool,
+    position: usize,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let (text, background) = row_colors(highlighted);
+    let y = draw_row(target, position, background)?;
     draw_text(
         target,
         attribute.name,
@@ -127,6 +285,69 @@ where
             let x = x_for_right_justified_text(number);
             draw_text(target, number, Point::new(x, y), text)?;
         }
+        Value::Preset(slot) => {
+            let mut string = heapless::String::<8>::new();
+            match slot {
+                Some(slot) => write!(string, "{}", slot + 1).unwrap(),
+                None => string.push('-').unwrap(),
+            }
+            let x = x_for_right_justified_text(&string);
+            draw_text(
+                target,
+                &string,
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
+        Value::Bool(value) => {
+            let value = if *value { "on" } else { "off" };
+            let x = x_for_right_justified_text(value);
+            draw_text(
+                target,
+                value,
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
+        Value::I32(value) => {
+            let mut string = heapless::String::<12>::new();
+            write!(string, "{}", value).unwrap();
+            let x = x_for_right_justified_text(&string);
+            draw_text(
+                target,
+                &string,
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
+        Value::Trigger => {
+            let x = x_for_right_justified_text("!");
+            draw_text(
+                target,
+                "!",
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
+        Value::Page => {
+            let x = x_for_right_justified_text("/");
+            draw_text(
+                target,
+                "/",
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
+        Value::File(name) => {
+            let name = name.as_ref().map_or("-", |n| n.as_str());
+            let x = x_for_right_justified_text(name);
+            draw_text(
+                target,
+                name,
+                Point::new(x, y + FONT_HEIGHT_ABOVE_LINE as i32),
+                text,
+            )?;
+        }
     }
 
     Ok(())
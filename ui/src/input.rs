@@ -5,9 +5,19 @@ use proton_peripherals::rotary::{Direction, Rotary};
 
 use crate::action::Action;
 
+// Durations in milliseconds, process is called once per millisecond.
+const LONG_PRESS: u32 = 500;
+const DOUBLE_CLICK_INTERVAL: u32 = 250;
+
 pub struct Input<A, B, C> {
     button: Button<10, C>,
     rotary: Rotary<A, B>,
+    time: u32,
+    // The current press was already used by a turn or a long press
+    press_used: bool,
+    // Time of a click waiting for a possible second one
+    pending_click: Option<u32>,
+    double_click: bool,
 }
 
 impl<A, B, C> Input<A, B, C>
@@ -17,25 +27,248 @@ where
     C: InputPin,
 {
     pub fn new(button: Button<10, C>, rotary: Rotary<A, B>) -> Self {
-        Self { button, rotary }
+        Self {
+            button,
+            rotary,
+            time: 0,
+            press_used: false,
+            pending_click: None,
+            double_click: true,
+        }
     }
 
+    /// Tell whether a double click means something else than a click on the
+    /// focused item. When it does not, clicks are reported right away.
+    pub fn set_double_click(&mut self, double_click: bool) {
+        self.double_click = double_click;
+    }
+
+    /// Clicks are reported once the button is released and the interval of
+    /// a double click passes, unless double clicks are disabled. Presses
+    /// that turned the encoder or lasted long are not reported as clicks.
     pub fn process(&mut self) -> Vec<Action, 6> {
         self.button.sample();
         self.rotary.sample().ok().unwrap();
+        self.time = self.time.wrapping_add(1);
 
         let mut actions = Vec::new();
 
-        if self.button.clicked() {
+        if let Some(released) = self.pending_click {
+            if self.time.wrapping_sub(released) > DOUBLE_CLICK_INTERVAL {
+                self.flush_click(&mut actions);
+            }
+        }
+
+        if self.button.held() == LONG_PRESS && !self.press_used {
+            self.press_used = true;
+            self.flush_click(&mut actions);
+            actions.push(Action::LongPress).unwrap();
+        }
+
+        if self.button.released() {
+            if !self.press_used {
+                if self.pending_click.take().is_some() {
+                    actions.push(Action::DoubleClick).unwrap();
+                } else if self.double_click {
+                    self.pending_click = Some(self.time);
+                } else {
+                    actions.push(Action::EncoderClick).unwrap();
+                }
+            }
+            self.press_used = false;
+        }
+
+        let direction = self.rotary.direction();
+        if direction != Direction::None {
+            // Turns must not overtake the click preceding them
+            self.flush_click(&mut actions);
+        }
+        let pressed = self.button.active();
+        if pressed && direction != Direction::None {
+            self.press_used = true;
+        }
+        match (direction, pressed) {
+            (Direction::None, _) => (),
+            (Direction::Clockwise, false) => actions.push(Action::EncoderDown(self.time)).unwrap(),
+            (Direction::CounterClockwise, false) => {
+                actions.push(Action::EncoderUp(self.time)).unwrap()
+            }
+            (Direction::Clockwise, true) => actions.push(Action::PressedDown(self.time)).unwrap(),
+            (Direction::CounterClockwise, true) => {
+                actions.push(Action::PressedUp(self.time)).unwrap()
+            }
+        }
+
+        actions
+    }
+
+    fn flush_click(&mut self, actions: &mut Vec<Action, 6>) {
+        if self.pending_click.take().is_some() {
             actions.push(Action::EncoderClick).unwrap();
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match self.rotary.direction() {
-            Direction::Clockwise => actions.push(Action::EncoderDown).unwrap(),
-            Direction::CounterClockwise => actions.push(Action::EncoderUp).unwrap(),
-            _ => (),
+    struct TestPin {
+        pub high: bool,
+    }
+
+    impl TestPin {
+        // Pins are pulled up, they are low only while the button is pressed
+        // or the encoder is between detents
+        fn new() -> Self {
+            Self { high: true }
+        }
+    }
+
+    impl InputPin for TestPin {
+        type Error = ();
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.high)
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(!self.high)
+        }
+    }
+
+    type TestInput = Input<TestPin, TestPin, TestPin>;
+    type Actions = Vec<Action, 16>;
+
+    fn input() -> TestInput {
+        Input::new(
+            Button::new(TestPin::new()),
+            Rotary::new(TestPin::new(), TestPin::new()),
+        )
+    }
+
+    fn run(input: &mut TestInput, milliseconds: u32) -> Actions {
+        let mut actions = Vec::new();
+        for _ in 0..milliseconds {
+            for action in input.process() {
+                actions.push(action).unwrap();
+            }
         }
+        actions
+    }
 
+    fn press(input: &mut TestInput, milliseconds: u32) -> Actions {
+        input.button.pin().high = false;
+        run(input, milliseconds)
+    }
+
+    fn release(input: &mut TestInput, milliseconds: u32) -> Actions {
+        input.button.pin().high = true;
+        run(input, milliseconds)
+    }
+
+    // Walk through a full cycle of the quadrature, which the encoder
+    // reports as two clockwise steps.
+    fn turn(input: &mut TestInput) -> Actions {
+        let mut actions = Vec::new();
+        for (a, b) in [(false, true), (false, false), (true, false), (true, true)] {
+            input.rotary.pin_a().high = a;
+            input.rotary.pin_b().high = b;
+            actions.extend(run(input, 1));
+        }
         actions
     }
+
+    #[test]
+    fn when_clicked_it_reports_click_after_double_click_interval() {
+        let mut input = input();
+
+        assert!(press(&mut input, 50).is_empty());
+        assert!(release(&mut input, DOUBLE_CLICK_INTERVAL - 10).is_empty());
+        assert_eq!(run(&mut input, 20).as_slice(), &[Action::EncoderClick]);
+        assert!(run(&mut input, 1000).is_empty());
+    }
+
+    #[test]
+    fn when_clicked_twice_quickly_it_reports_only_double_click() {
+        let mut input = input();
+
+        press(&mut input, 50);
+        release(&mut input, 100);
+        press(&mut input, 50);
+        let mut actions = release(&mut input, 1000);
+        actions.extend(run(&mut input, 1000));
+
+        assert_eq!(actions.as_slice(), &[Action::DoubleClick]);
+    }
+
+    #[test]
+    fn when_held_long_it_reports_long_press_without_click() {
+        let mut input = input();
+
+        assert!(press(&mut input, LONG_PRESS).is_empty());
+        let mut actions = press(&mut input, 1000);
+        actions.extend(release(&mut input, 1000));
+
+        assert_eq!(actions.as_slice(), &[Action::LongPress]);
+    }
+
+    #[test]
+    fn when_turned_while_pressed_it_reports_pressed_turns_without_click() {
+        let mut input = input();
+
+        press(&mut input, 50);
+        let mut actions = turn(&mut input);
+        actions.extend(release(&mut input, 1000));
+
+        assert_eq!(actions.len(), 2);
+        assert!(actions
+            .iter()
+            .all(|action| matches!(action, Action::PressedDown(_))));
+    }
+
+    #[test]
+    fn when_turned_after_click_it_reports_click_first() {
+        let mut input = input();
+
+        press(&mut input, 50);
+        release(&mut input, 50);
+        let actions = turn(&mut input);
+
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0], Action::EncoderClick);
+        assert!(matches!(actions[1], Action::EncoderDown(_)));
+    }
+
+    #[test]
+    fn when_time_wraps_around_it_keeps_measuring_intervals() {
+        let mut input = input();
+        input.time = u32::MAX - 30;
+
+        press(&mut input, 50);
+        assert!(release(&mut input, DOUBLE_CLICK_INTERVAL - 10).is_empty());
+        assert_eq!(run(&mut input, 20).as_slice(), &[Action::EncoderClick]);
+
+        input.time = u32::MAX - 100;
+        press(&mut input, 50);
+        release(&mut input, 100);
+        press(&mut input, 50);
+        assert_eq!(release(&mut input, 1000).as_slice(), &[Action::DoubleClick]);
+
+        input.time = u32::MAX - 200;
+        assert!(press(&mut input, LONG_PRESS).is_empty());
+        assert_eq!(press(&mut input, 10).as_slice(), &[Action::LongPress]);
+    }
+
+    #[test]
+    fn given_double_click_disabled_when_clicked_it_reports_click_right_away() {
+        let mut input = input();
+        input.set_double_click(false);
+
+        press(&mut input, 50);
+        assert_eq!(release(&mut input, 10).as_slice(), &[Action::EncoderClick]);
+        press(&mut input, 50);
+        assert_eq!(release(&mut input, 10).as_slice(), &[Action::EncoderClick]);
+        assert!(run(&mut input, 1000).is_empty());
+    }
 }
@@ -5,10 +5,14 @@
 extern crate approx;
 
 pub mod action;
+pub mod browser;
 pub mod display;
 pub mod input;
+pub mod notification;
+pub mod presets;
 pub mod reaction;
 pub mod reducer;
+pub mod snapshot;
 pub mod state;
 pub mod view;
 
@@ -0,0 +1,148 @@
+//! Changes made by the instrument itself, passed back to the UI.
+//!
+//! Reactions carry changes from the UI to the instrument. Notifications go
+//! the other way, when the instrument changes a value on its own, e.g. after
+//! it loads data from the SD card or hits an error. Applying them updates
+//! the state in place, without rebuilding it.
+
+use crate::state::{State, Value, ValueFile};
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Notification {
+    SetValue(&'static str, f32),
+    SelectValue(&'static str, &'static str),
+    SetBool(&'static str, bool),
+    SetI32(&'static str, i32),
+    /// Index of the browser entry now used by the attribute.
+    SelectFile(&'static str, usize),
+    SetTitle(&'static str),
+}
+
+/// Notifications about unknown attributes, options or entries are ignored.
+pub fn apply(notification: Notification, state: &mut State) {
+    match notification {
+        Notification::SetValue(name, value) => {
+            if let Some(Value::F32(value_f32)) = find_value(state, name) {
+                value_f32.value = value.clamp(value_f32.min, value_f32.max);
+            }
+        }
+        Notification::SelectValue(name, option) => {
+            if let Some(Value::Select(value_select)) = find_value(state, name) {
+                if let Some(index) = value_select.available.iter().position(|a| *a == option) {
+                    value_select.selected = index;
+                }
+            }
+        }
+        Notification::SetBool(name, value) => {
+            if let Some(Value::Bool(value_bool)) = find_value(state, name) {
+                value_bool.value = value;
+            }
+        }
+        Notification::SetI32(name, value) => {
+            if let Some(Value::I32(value_i32)) = find_value(state, name) {
+                value_i32.value = value.clamp(value_i32.min, value_i32.max);
+            }
+        }
+        Notification::SelectFile(name, index) => {
+            if index >= state.browser.entries.len() {
+                return;
+            }
+            let value_file = ValueFile::new().with_selected(index, &state.browser);
+            if let Some(Value::File(value)) = find_value(state, name) {
+                *value = value_file;
+            }
+        }
+        Notification::SetTitle(title) => state.title = title,
+    }
+}
+
+fn find_value<'a>(state: &'a mut State, name: &str) -> Option<&'a mut Value> {
+    state
+        .attributes
+        .iter_mut()
+        .find(|a| a.name == name)
+        .map(|a| &mut a.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::{Browser, Entry};
+    use crate::state::*;
+
+    fn state() -> State {
+        let mut browser = Browser::new();
+        browser.push(Entry::file("loop.wav", None)).unwrap();
+        browser.push(Entry::file("kick.wav", None)).unwrap();
+        State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("sample").with_value_file(ValueFile::new()),
+                Attribute::new("level").with_value_f32(ValueF32::new(0.5)),
+                Attribute::new("mode")
+                    .with_value_select(ValueSelect::new(&["a", "b", "c"]).unwrap()),
+                Attribute::new("play").with_value_bool(ValueBool::new(false)),
+                Attribute::new("steps").with_value_i32(ValueI32::new(4).with_max(16)),
+            ])
+            .unwrap()
+            .with_browser(browser)
+    }
+
+    #[test]
+    fn when_notified_it_updates_values_in_place() {
+        let mut state = state();
+        state.selected_attribute = 2;
+
+        apply(Notification::SelectFile("sample", 1), &mut state);
+        apply(Notification::SetValue("level", 0.75), &mut state);
+        apply(Notification::SelectValue("mode", "c"), &mut state);
+        apply(Notification::SetTitle("No card"), &mut state);
+        apply(Notification::SetBool("play", true), &mut state);
+        apply(Notification::SetI32("steps", 20), &mut state);
+
+        assert_eq!(state.title, "No card");
+        assert_eq!(state.selected_attribute, 2);
+        assert!(matches!(
+            state.attributes[0].value,
+            Value::File(ValueFile {
+                selected: Some(1),
+                ..
+            })
+        ));
+        assert!(matches!(
+            state.attributes[1].value,
+            Value::F32(ValueF32 { value, .. }) if value == 0.75
+        ));
+        assert!(matches!(
+            state.attributes[2].value,
+            Value::Select(ValueSelect { selected: 2, .. })
+        ));
+        assert!(matches!(
+            state.attributes[3].value,
+            Value::Bool(ValueBool { value: true, .. })
+        ));
+        assert!(matches!(
+            state.attributes[4].value,
+            Value::I32(ValueI32 { value: 16, .. })
+        ));
+    }
+
+    #[test]
+    fn when_notification_does_not_match_state_it_is_ignored() {
+        let mut state = state();
+
+        apply(Notification::SetValue("mode", 0.3), &mut state);
+        apply(Notification::SelectValue("mode", "z"), &mut state);
+        apply(Notification::SelectFile("sample", 7), &mut state);
+        apply(Notification::SetValue("unknown", 0.3), &mut state);
+
+        assert!(matches!(
+            state.attributes[0].value,
+            Value::File(ValueFile { selected: None, .. })
+        ));
+        assert!(matches!(
+            state.attributes[2].value,
+            Value::Select(ValueSelect { selected: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+//! Slots keeping complete configurations of the instrument.
+//!
+//! The page of presets lists the action taken on click, the CV input used
+//! to recall presets and the slots themselves. Clicking a slot saves the
+//! current values into it or recalls them. Storing the slots is up to the
+//! firmware, which receives reactions to these clicks.
+//!
+//! When a CV input is assigned, its range is split into as many parts as
+//! there are slots. Moving the CV into another part recalls the slot, so
+//! presets can be sequenced.
+
+pub const SLOTS: usize = 8;
+
+/// Number of CV inputs presets can be recalled by.
+pub const CV_INPUTS: usize = 5;
+
+// The back item, the action and the CV input precede slots.
+const ROWS: usize = SLOTS + 3;
+
+// Portion of the range of a slot the CV has to leave it by, so noise
+// around the boundary does not recall slots back and forth.
+const CV_HYSTERESIS: f32 = 0.1;
+
+#[derive(Clone, Debug)]
+pub struct Presets {
+    pub selected: usize,
+    pub action: PresetAction,
+    pub cv_input: Option<usize>,
+    /// Slots that have a preset stored.
+    pub used: [bool; SLOTS],
+    // Slot the CV input currently points to.
+    cv_slot: Option<usize>,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresetAction {
+    Recall,
+    Save,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresetsItem {
+    Back,
+    Action(PresetAction),
+    Cv(Option<usize>),
+    /// Index of the slot and whether it is used.
+    Slot(usize, bool),
+}
+
+impl Default for Presets {
+    fn default() -> Self {
+        Self {
+            selected: 0,
+            action: PresetAction::Recall,
+            cv_input: None,
+            used: [false; SLOTS],
+            cv_slot: None,
+        }
+    }
+}
+
+impl Presets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn item(&self, row: usize) -> Option<PresetsItem> {
+        match row {
+            0 => Some(PresetsItem::Back),
+            1 => Some(PresetsItem::Action(self.action)),
+            2 => Some(PresetsItem::Cv(self.cv_input)),
+            row if row < ROWS => Some(PresetsItem::Slot(row - 3, self.used[row - 3])),
+            _ => None,
+        }
+    }
+
+    pub fn selected_item(&self) -> PresetsItem {
+        self.item(self.selected).unwrap()
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % ROWS;
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.selected == 0 {
+            self.selected = ROWS - 1;
+        } else {
+            self.selected -= 1;
+        }
+    }
+
+    pub fn toggle_action(&mut self) {
+        self.action = match self.action {
+            PresetAction::Recall => PresetAction::Save,
+            PresetAction::Save => PresetAction::Recall,
+        };
+    }
+
+    /// Cycle through CV inputs, turned off after the last one.
+    pub fn select_next_cv_input(&mut self) {
+        self.cv_input = match self.cv_input {
+            None => Some(0),
+            Some(input) if input + 1 < CV_INPUTS => Some(input + 1),
+            Some(_) => None,
+        };
+        self.cv_slot = None;
+    }
+
+    /// Follow values of CV inputs. Returns the slot to recall when the
+    /// assigned input moves to a used one.
+    pub fn follow_cv(&mut self, cv: &[f32]) -> Option<usize> {
+        let position = cv[self.cv_input?] * SLOTS as f32;
+        if let Some(slot) = self.cv_slot {
+            let (start, end) = (slot as f32, (slot + 1) as f32);
+            if position > start - CV_HYSTERESIS && position < end + CV_HYSTERESIS {
+                return None;
+            }
+        }
+        let slot = (position.max(0.0) as usize).min(SLOTS - 1);
+        if self.cv_slot == Some(slot) {
+            return None;
+        }
+        self.cv_slot = Some(slot);
+        self.used[slot].then_some(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_listing_rows_it_puts_settings_before_slots() {
+        let mut presets = Presets::new();
+        presets.used[1] = true;
+
+        assert_eq!(presets.item(0), Some(PresetsItem::Back));
+        assert_eq!(
+            presets.item(1),
+            Some(PresetsItem::Action(PresetAction::Recall))
+        );
+        assert_eq!(presets.item(2), Some(PresetsItem::Cv(None)));
+        assert_eq!(presets.item(4), Some(PresetsItem::Slot(1, true)));
+        assert_eq!(
+            presets.item(ROWS - 1),
+            Some(PresetsItem::Slot(SLOTS - 1, false))
+        );
+        assert_eq!(presets.item(ROWS), None);
+    }
+
+    #[test]
+    fn when_cycling_cv_inputs_it_turns_off_after_the_last() {
+        let mut presets = Presets::new();
+        for input in 0..CV_INPUTS {
+            presets.select_next_cv_input();
+            assert_eq!(presets.cv_input, Some(input));
+        }
+        presets.select_next_cv_input();
+        assert_eq!(presets.cv_input, None);
+    }
+
+    #[test]
+    fn when_cv_moves_to_used_slot_it_recalls_it_once() {
+        let mut presets = Presets::new();
+        presets.used = [true; SLOTS];
+        presets.cv_input = Some(1);
+        let cv = |value| [0.0, value, 0.0, 0.0, 0.0];
+
+        assert_eq!(presets.follow_cv(&cv(0.0)), Some(0));
+        assert_eq!(presets.follow_cv(&cv(0.01)), None);
+        assert_eq!(presets.follow_cv(&cv(0.5)), Some(4));
+        assert_eq!(presets.follow_cv(&cv(0.5)), None);
+        assert_eq!(presets.follow_cv(&cv(1.5)), Some(SLOTS - 1));
+    }
+
+    #[test]
+    fn when_cv_wobbles_around_boundary_it_keeps_the_slot() {
+        let mut presets = Presets::new();
+        presets.used = [true; SLOTS];
+        presets.cv_input = Some(0);
+        let boundary = 1.0 / SLOTS as f32;
+
+        assert_eq!(presets.follow_cv(&[boundary - 0.001]), Some(0));
+        assert_eq!(presets.follow_cv(&[boundary + 0.001]), None);
+        assert_eq!(presets.follow_cv(&[boundary - 0.001]), None);
+        assert_eq!(presets.follow_cv(&[2.0 * boundary]), Some(2));
+    }
+
+    #[test]
+    fn when_cv_moves_to_empty_slot_it_recalls_nothing() {
+        let mut presets = Presets::new();
+        presets.used[0] = true;
+        presets.cv_input = Some(0);
+
+        assert_eq!(presets.follow_cv(&[0.0]), Some(0));
+        assert_eq!(presets.follow_cv(&[0.9]), None);
+        assert_eq!(presets.follow_cv(&[0.0]), Some(0));
+    }
+}
@@ -3,4 +3,14 @@
 pub enum Reaction {
     SetValue(&'static str, f32),
     SelectValue(&'static str, &'static str),
+    SetBool(&'static str, bool),
+    SetI32(&'static str, i32),
+    /// Click on an attribute with [`crate::state::Value::Trigger`].
+    Trigger(&'static str),
+    /// Index of the browser entry chosen for the attribute.
+    SelectFile(&'static str, usize),
+    /// Slot the current values should be saved to.
+    SavePreset(usize),
+    /// Slot whose values should be restored.
+    RecallPreset(usize),
 }
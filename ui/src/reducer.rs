@@ -1,79 +1,351 @@
 use super::action::Action;
+use super::presets::{PresetAction, PresetsItem};
 use super::reaction::Reaction;
-use super::state::{Menu, State, Value, ValueF32, ValueSelect};
+use super::state::{Encoder, Menu, Row, State, Value, ValueF32, ValueI32, ValueSelect};
+
+// Detents coming in quicker succession than the interval, in milliseconds,
+// multiply the step of the edited value.
+const ACCELERATION: [(u32, u32); 2] = [(20, 10), (50, 3)];
+// Fine editing divides the step of decimal values.
+const FINE_DIVIDER: f32 = 10.0;
 
 pub fn reduce(action: Action, state: &mut State) -> Option<Reaction> {
     match action {
-        Action::EncoderClick => {
-            switch_menu(state);
-            None
-        }
-        Action::EncoderUp => match state.menu {
+        Action::EncoderClick => match state.menu {
+            Menu::Browser => click_in_browser(state),
+            Menu::Presets => click_in_presets(state),
+            _ => click_in_menu(state),
+        },
+        Action::EncoderUp(time) | Action::PressedUp(time) => match state.menu {
             Menu::Main => {
                 move_to_previous_attribute(state);
                 None
             }
-            Menu::Sub => decrease_attribute_value(state),
+            Menu::Sub => {
+                let pressed = matches!(action, Action::PressedUp(_));
+                decrease_attribute_value(state, time, pressed)
+            }
+            Menu::Browser => {
+                state.browser.select_previous();
+                None
+            }
+            Menu::Presets => {
+                state.presets.select_previous();
+                None
+            }
         },
-        Action::EncoderDown => match state.menu {
+        Action::EncoderDown(time) | Action::PressedDown(time) => match state.menu {
             Menu::Main => {
                 move_to_next_attribute(state);
                 None
             }
-            Menu::Sub => increase_attribute_value(state),
+            Menu::Sub => {
+                let pressed = matches!(action, Action::PressedDown(_));
+                increase_attribute_value(state, time, pressed)
+            }
+            Menu::Browser => {
+                state.browser.select_next();
+                None
+            }
+            Menu::Presets => {
+                state.presets.select_next();
+                None
+            }
+        },
+        Action::DoubleClick => match state.menu {
+            Menu::Sub => {
+                state.encoder.fine = !state.encoder.fine;
+                None
+            }
+            _ => reduce(Action::EncoderClick, state),
         },
+        Action::LongPress => long_press(state),
     }
 }
 
-fn switch_menu(state: &mut State) {
+/// Double click toggles fine editing of numeric values. On anything else
+/// it acts as a click, so the input does not need to wait for it.
+pub fn accepts_double_click(state: &State) -> bool {
+    matches!(state.menu, Menu::Sub)
+        && state.selected_attribute_index().is_some_and(|index| {
+            matches!(state.attributes[index].value, Value::F32(_) | Value::I32(_))
+        })
+}
+
+// Long press steps back, leaving the page or closing the browser and
+// presets. While editing, it resets the attribute to its default instead.
+fn long_press(state: &mut State) -> Option<Reaction> {
+    match state.menu {
+        Menu::Main => {
+            if state.page.is_some() {
+                leave_page(state);
+            }
+            None
+        }
+        Menu::Sub => reset_attribute_value(state),
+        Menu::Browser | Menu::Presets => {
+            state.menu = Menu::Main;
+            None
+        }
+    }
+}
+
+fn reset_attribute_value(state: &mut State) -> Option<Reaction> {
+    let index = state.selected_attribute_index()?;
+    let attribute = &mut state.attributes[index];
+
+    match &mut attribute.value {
+        Value::F32(value) if value.value != value.default => {
+            value.value = value.default;
+            Some(Reaction::SetValue(attribute.name, value.value))
+        }
+        Value::I32(value) if value.value != value.default => {
+            value.value = value.default;
+            Some(Reaction::SetI32(attribute.name, value.value))
+        }
+        Value::Select(value) if value.selected != value.default => {
+            value.selected = value.default;
+            Some(Reaction::SelectValue(
+                attribute.name,
+                value.available[value.selected],
+            ))
+        }
+        Value::Bool(value) if value.value != value.default => {
+            value.value = value.default;
+            Some(Reaction::SetBool(attribute.name, value.value))
+        }
+        _ => None,
+    }
+}
+
+// Switches and triggers react right away, other attributes are edited in
+// the submenu or on their own page.
+fn click_in_menu(state: &mut State) -> Option<Reaction> {
+    let mut reaction = None;
     state.menu = match state.menu {
-        Menu::Sub => Menu::Main,
-        Menu::Main => Menu::Sub,
+        Menu::Main => match state.row(state.selected_attribute) {
+            Some(Row::Back) => {
+                leave_page(state);
+                Menu::Main
+            }
+            Some(Row::Page(name)) => {
+                state.page = Some(name);
+                state.selected_attribute = 0;
+                Menu::Main
+            }
+            Some(Row::Attribute(index)) => {
+                let attribute = &mut state.attributes[index];
+                match &mut attribute.value {
+                    Value::Bool(value_bool) => {
+                        value_bool.value = !value_bool.value;
+                        reaction = Some(Reaction::SetBool(attribute.name, value_bool.value));
+                        Menu::Main
+                    }
+                    Value::Trigger => {
+                        reaction = Some(Reaction::Trigger(attribute.name));
+                        Menu::Main
+                    }
+                    Value::File(_) => {
+                        state.browser.directory = None;
+                        state.browser.selected = 0;
+                        Menu::Browser
+                    }
+                    Value::Preset(_) => {
+                        state.presets.selected = 0;
+                        Menu::Presets
+                    }
+                    _ => Menu::Sub,
+                }
+            }
+            None => Menu::Sub,
+        },
+        Menu::Sub | Menu::Browser | Menu::Presets => Menu::Main,
+    };
+    reaction
+}
+
+// The page just left stays selected in its parent, so the user does not
+// lose track of where they were.
+fn leave_page(state: &mut State) {
+    let left = state.page;
+    state.page = state
+        .pages
+        .iter()
+        .find(|p| Some(p.name) == left)
+        .and_then(|p| p.parent);
+    let position = state
+        .rows()
+        .position(|row| matches!(row, Row::Page(name) if Some(name) == left));
+    state.selected_attribute = position.unwrap_or(0);
+}
+
+fn click_in_browser(state: &mut State) -> Option<Reaction> {
+    let selected_attribute = state.selected_attribute_index();
+    let browser = &mut state.browser;
+
+    let index = match browser.selected_entry() {
+        Some(index) => index,
+        None => {
+            if !browser.leave() {
+                state.menu = Menu::Main;
+            }
+            return None;
+        }
+    };
+
+    if browser.entries[index].is_directory {
+        browser.enter(index);
+        return None;
+    }
+
+    state.menu = Menu::Main;
+    let attribute = &mut state.attributes[selected_attribute?];
+    if let Value::File(value_file) = &mut attribute.value {
+        *value_file = value_file.with_selected(index, browser);
+    }
+    Some(Reaction::SelectFile(attribute.name, index))
+}
+
+// Clicking a slot closes the page, so the user sees the result. Empty
+// slots cannot be recalled.
+fn click_in_presets(state: &mut State) -> Option<Reaction> {
+    let presets = &mut state.presets;
+
+    let (slot, used) = match presets.selected_item() {
+        PresetsItem::Back => {
+            state.menu = Menu::Main;
+            return None;
+        }
+        PresetsItem::Action(_) => {
+            presets.toggle_action();
+            return None;
+        }
+        PresetsItem::Cv(_) => {
+            presets.select_next_cv_input();
+            return None;
+        }
+        PresetsItem::Slot(slot, used) => (slot, used),
+    };
+
+    let reaction = match presets.action {
+        PresetAction::Save => {
+            presets.used[slot] = true;
+            Reaction::SavePreset(slot)
+        }
+        PresetAction::Recall if used => Reaction::RecallPreset(slot),
+        PresetAction::Recall => return None,
+    };
+    state.menu = Menu::Main;
+    if let Some(index) = state.selected_attribute_index() {
+        if let Value::Preset(value_preset) = &mut state.attributes[index].value {
+            value_preset.slot = Some(slot);
+        }
     }
+    Some(reaction)
 }
 
 fn move_to_previous_attribute(state: &mut State) {
+    let rows = state.rows().count();
+    if rows == 0 {
+        return;
+    }
     if state.selected_attribute == 0 {
-        state.selected_attribute = state.attributes.len() - 1;
+        state.selected_attribute = rows - 1;
     } else {
         state.selected_attribute -= 1;
     }
 }
 
 fn move_to_next_attribute(state: &mut State) {
-    state.selected_attribute = (state.selected_attribute + 1) % state.attributes.len();
+    let rows = state.rows().count();
+    if rows == 0 {
+        return;
+    }
+    state.selected_attribute = (state.selected_attribute + 1) % rows;
 }
 
-fn decrease_attribute_value(state: &mut State) -> Option<Reaction> {
-    let attribute = &mut state.attributes[state.selected_attribute];
+fn decrease_attribute_value(state: &mut State, time: u32, pressed: bool) -> Option<Reaction> {
+    let index = state.selected_attribute_index()?;
+    let fine = state.encoder.fine != pressed;
+    let multiplier = detent_multiplier(&mut state.encoder, time, false, fine);
+    let attribute = &mut state.attributes[index];
 
     match &mut attribute.value {
         Value::F32(value) => {
-            decrease_f32_attribute_value(value).map(|v| Reaction::SetValue(attribute.name, v))
+            let step = if fine {
+                value.step / FINE_DIVIDER
+            } else {
+                value.step * multiplier as f32
+            };
+            decrease_f32_attribute_value(value, step).map(|v| Reaction::SetValue(attribute.name, v))
         }
         Value::Select(value) => {
             decrease_select_attribute_value(value).map(|v| Reaction::SelectValue(attribute.name, v))
         }
+        Value::I32(value) => {
+            let step = if fine {
+                1
+            } else {
+                value.step * multiplier as i32
+            };
+            decrease_i32_attribute_value(value, step).map(|v| Reaction::SetI32(attribute.name, v))
+        }
+        Value::File(_) | Value::Preset(_) | Value::Bool(_) | Value::Trigger => None,
     }
 }
 
-fn increase_attribute_value(state: &mut State) -> Option<Reaction> {
-    let attribute = &mut state.attributes[state.selected_attribute];
+fn increase_attribute_value(state: &mut State, time: u32, pressed: bool) -> Option<Reaction> {
+    let index = state.selected_attribute_index()?;
+    let fine = state.encoder.fine != pressed;
+    let multiplier = detent_multiplier(&mut state.encoder, time, true, fine);
+    let attribute = &mut state.attributes[index];
 
     match &mut attribute.value {
         Value::F32(value) => {
-            increase_f32_attribute_value(value).map(|v| Reaction::SetValue(attribute.name, v))
+            let step = if fine {
+                value.step / FINE_DIVIDER
+            } else {
+                value.step * multiplier as f32
+            };
+            increase_f32_attribute_value(value, step).map(|v| Reaction::SetValue(attribute.name, v))
         }
         Value::Select(value) => {
             increase_select_attribute_value(value).map(|v| Reaction::SelectValue(attribute.name, v))
         }
+        Value::I32(value) => {
+            let step = if fine {
+                1
+            } else {
+                value.step * multiplier as i32
+            };
+            increase_i32_attribute_value(value, step).map(|v| Reaction::SetI32(attribute.name, v))
+        }
+        Value::File(_) | Value::Preset(_) | Value::Bool(_) | Value::Trigger => None,
+    }
+}
+
+// Only turns in the same direction speed up, so the user can slow down by
+// turning back. Turning while the button is held edits by the resolution
+// opposite to the toggled one.
+fn detent_multiplier(encoder: &mut Encoder, time: u32, up: bool, fine: bool) -> u32 {
+    let last_detent = encoder.last_detent.replace((time, up));
+    if fine {
+        return 1;
     }
+    let interval = match last_detent {
+        Some((last_time, last_up)) if last_up == up => time.wrapping_sub(last_time),
+        _ => return 1,
+    };
+    ACCELERATION
+        .iter()
+        .find(|(max_interval, _)| interval <= *max_interval)
+        .map_or(1, |(_, multiplier)| *multiplier)
 }
 
-fn decrease_f32_attribute_value(value_f32: &mut ValueF32) -> Option<f32> {
+fn decrease_f32_attribute_value(value_f32: &mut ValueF32, step: f32) -> Option<f32> {
     let old_value = value_f32.value;
 
-    value_f32.value = (value_f32.value - value_f32.step).max(value_f32.min);
+    value_f32.value = (value_f32.value - step).max(value_f32.min);
 
     let epsilon = (value_f32.max - value_f32.min) * 0.0001;
     let new_value = value_f32.value;
@@ -84,10 +356,10 @@ fn decrease_f32_attribute_value(value_f32: &mut ValueF32) -> Option<f32> {
     }
 }
 
-fn increase_f32_attribute_value(value_f32: &mut ValueF32) -> Option<f32> {
+fn increase_f32_attribute_value(value_f32: &mut ValueF32, step: f32) -> Option<f32> {
     let old_value = value_f32.value;
 
-    value_f32.value = (value_f32.value + value_f32.step).min(value_f32.max);
+    value_f32.value = (value_f32.value + step).min(value_f32.max);
 
     let epsilon = (value_f32.max - value_f32.min) * 0.0001;
     let new_value = value_f32.value;
@@ -98,6 +370,18 @@ fn increase_f32_attribute_value(value_f32: &mut ValueF32) -> Option<f32> {
     }
 }
 
+fn decrease_i32_attribute_value(value_i32: &mut ValueI32, step: i32) -> Option<i32> {
+    let old_value = value_i32.value;
+    value_i32.value = value_i32.value.saturating_sub(step).max(value_i32.min);
+    (value_i32.value != old_value).then_some(value_i32.value)
+}
+
+fn increase_i32_attribute_value(value_i32: &mut ValueI32, step: i32) -> Option<i32> {
+    let old_value = value_i32.value;
+    value_i32.value = value_i32.value.saturating_add(step).min(value_i32.max);
+    (value_i32.value != old_value).then_some(value_i32.value)
+}
+
 fn decrease_select_attribute_value(value_select: &mut ValueSelect) -> Option<&'static str> {
     let old_value = value_select.available[value_select.selected];
 
@@ -134,22 +418,22 @@ mod tests {
 
     #[test]
     fn when_turns_up_on_middle_attribute_it_scrolls_to_previous_attribute() {
-        assert_attribute_transition(1, Action::EncoderUp, 0);
+        assert_attribute_transition(1, Action::EncoderUp(0), 0);
     }
 
     #[test]
     fn when_turns_up_on_first_attribute_it_scrolls_to_last_attribute() {
-        assert_attribute_transition(0, Action::EncoderUp, 2);
+        assert_attribute_transition(0, Action::EncoderUp(0), 2);
     }
 
     #[test]
     fn when_turns_down_on_middle_attribute_it_scrolls_to_next_attribute() {
-        assert_attribute_transition(1, Action::EncoderDown, 2);
+        assert_attribute_transition(1, Action::EncoderDown(0), 2);
     }
 
     #[test]
     fn when_turns_down_on_last_attribute_it_scrolls_to_first_attribute() {
-        assert_attribute_transition(2, Action::EncoderDown, 0);
+        assert_attribute_transition(2, Action::EncoderDown(0), 0);
     }
 
     fn assert_attribute_transition(old: usize, action: Action, new: usize) {
@@ -192,7 +476,7 @@ mod tests {
                 .with_min(0.0)
                 .with_max(10.0)
                 .with_step(1.0),
-            Action::EncoderUp,
+            Action::EncoderUp(0),
             1.0,
             true,
         );
@@ -205,7 +489,7 @@ mod tests {
                 .with_min(-10.0)
                 .with_max(0.0)
                 .with_step(1.0),
-            Action::EncoderUp,
+            Action::EncoderUp(0),
             -10.0,
             true,
         );
@@ -218,7 +502,7 @@ mod tests {
                 .with_min(-10.0)
                 .with_max(0.0)
                 .with_step(1.0),
-            Action::EncoderUp,
+            Action::EncoderUp(0),
             -10.0,
             false,
         );
@@ -232,7 +516,7 @@ mod tests {
                 .with_min(0.0)
                 .with_max(10.0)
                 .with_step(1.0),
-            Action::EncoderDown,
+            Action::EncoderDown(0),
             2.0,
             true,
         );
@@ -245,7 +529,7 @@ mod tests {
                 .with_min(0.0)
                 .with_max(10.0)
                 .with_step(1.0),
-            Action::EncoderDown,
+            Action::EncoderDown(0),
             10.0,
             true,
         );
@@ -258,7 +542,7 @@ mod tests {
                 .with_min(0.0)
                 .with_max(10.0)
                 .with_step(1.0),
-            Action::EncoderDown,
+            Action::EncoderDown(0),
             10.0,
             false,
         );
@@ -298,22 +582,22 @@ mod tests {
 
     #[test]
     fn given_select_attribute_on_middle_when_turns_up_in_submenu_it_scrolls_to_previous_value() {
-        assert_value_select_transition_in_submenu(1, Action::EncoderUp, 0);
+        assert_value_select_transition_in_submenu(1, Action::EncoderUp(0), 0);
     }
 
     #[test]
     fn given_select_attribute_on_first_when_turns_up_in_submenu_it_scrolls_to_last_value() {
-        assert_value_select_transition_in_submenu(0, Action::EncoderUp, 2);
+        assert_value_select_transition_in_submenu(0, Action::EncoderUp(0), 2);
     }
 
     #[test]
     fn given_select_attribute_on_middle_when_turns_down_in_submenu_it_scrolls_to_previous_value() {
-        assert_value_select_transition_in_submenu(1, Action::EncoderDown, 2);
+        assert_value_select_transition_in_submenu(1, Action::EncoderDown(0), 2);
     }
 
     #[test]
     fn given_select_attribute_on_first_when_turns_down_in_submenu_it_scrolls_to_last_value() {
-        assert_value_select_transition_in_submenu(2, Action::EncoderDown, 0);
+        assert_value_select_transition_in_submenu(2, Action::EncoderDown(0), 0);
     }
 
     fn assert_value_select_transition_in_submenu(old: usize, action: Action, new: usize) {
@@ -342,4 +626,427 @@ mod tests {
             unreachable!();
         };
     }
+
+    #[test]
+    fn given_bool_attribute_when_clicks_on_it_it_toggles_without_entering_submenu() {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_bool(ValueBool::new(false))])
+            .unwrap();
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+        assert_eq!(reaction, Some(Reaction::SetBool("a1", true)));
+        assert!(matches!(state.menu, Menu::Main));
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+        assert_eq!(reaction, Some(Reaction::SetBool("a1", false)));
+        assert!(matches!(
+            state.attributes[0].value,
+            Value::Bool(ValueBool { value: false, .. })
+        ));
+    }
+
+    #[test]
+    fn given_trigger_attribute_when_clicks_on_it_it_fires_without_entering_submenu() {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_trigger()])
+            .unwrap();
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::Trigger("a1")));
+        assert!(matches!(state.menu, Menu::Main));
+    }
+
+    #[test]
+    fn given_i32_attribute_when_turns_in_submenu_it_moves_by_step_within_range() {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[Attribute::new("a1")
+                .with_value_i32(ValueI32::new(8).with_min(-10).with_max(10).with_step(4))])
+            .unwrap();
+        reduce(Action::EncoderClick, &mut state);
+        assert!(matches!(state.menu, Menu::Sub));
+
+        assert_eq!(
+            reduce(Action::EncoderDown(0), &mut state),
+            Some(Reaction::SetI32("a1", 10))
+        );
+        assert_eq!(reduce(Action::EncoderDown(100), &mut state), None);
+        assert_eq!(
+            reduce(Action::EncoderUp(200), &mut state),
+            Some(Reaction::SetI32("a1", 6))
+        );
+    }
+
+    fn state_with_pages() -> State {
+        use crate::state::*;
+
+        State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("a1"),
+                Attribute::new("a2").with_page("p1"),
+                Attribute::new("a3").with_page("p2"),
+            ])
+            .unwrap()
+            .with_pages(&[Page::new("p1"), Page::new("p2").with_parent("p1")])
+            .unwrap()
+    }
+
+    #[test]
+    fn given_pages_when_clicks_on_page_it_enters_it() {
+        let mut state = state_with_pages();
+        reduce(Action::EncoderDown(0), &mut state);
+        assert_eq!(state.row(state.selected_attribute), Some(Row::Page("p1")));
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert!(reaction.is_none());
+        assert!(matches!(state.menu, Menu::Main));
+        assert_eq!(state.page, Some("p1"));
+        assert_eq!(state.selected_attribute, 0);
+        assert_eq!(
+            state.rows().collect::<heapless::Vec<_, 4>>(),
+            [Row::Back, Row::Attribute(1), Row::Page("p2")]
+        );
+    }
+
+    #[test]
+    fn given_nested_page_when_clicks_back_it_returns_to_parent_with_page_selected() {
+        let mut state = state_with_pages();
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderUp(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        assert_eq!(state.path(), ["p1", "p2"]);
+
+        reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(state.page, Some("p1"));
+        assert_eq!(state.row(state.selected_attribute), Some(Row::Page("p2")));
+
+        reduce(Action::EncoderUp(0), &mut state);
+        reduce(Action::EncoderUp(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(state.page, None);
+        assert_eq!(state.row(state.selected_attribute), Some(Row::Page("p1")));
+    }
+
+    #[test]
+    fn given_nested_page_when_long_presses_it_returns_to_parent() {
+        let mut state = state_with_pages();
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderUp(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+
+        reduce(Action::LongPress, &mut state);
+        assert_eq!(state.page, Some("p1"));
+
+        reduce(Action::LongPress, &mut state);
+        reduce(Action::LongPress, &mut state);
+        assert_eq!(state.page, None);
+        assert_eq!(state.row(state.selected_attribute), Some(Row::Page("p1")));
+    }
+
+    #[test]
+    fn given_attribute_on_page_when_turns_in_submenu_it_changes_it() {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("a1"),
+                Attribute::new("a2")
+                    .with_value_f32(ValueF32::new(0.0).with_step(0.5))
+                    .with_page("p1"),
+            ])
+            .unwrap()
+            .with_pages(&[Page::new("p1")])
+            .unwrap();
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+
+        let reaction = reduce(Action::EncoderDown(0), &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SetValue("a2", 0.5)));
+    }
+
+    fn state_editing_f32() -> State {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_f32(
+                ValueF32::new(50.0)
+                    .with_min(0.0)
+                    .with_max(100.0)
+                    .with_step(1.0),
+            )])
+            .unwrap();
+        reduce(Action::EncoderClick, &mut state);
+        state
+    }
+
+    fn value_f32(state: &State) -> f32 {
+        match state.attributes[0].value {
+            Value::F32(value_f32) => value_f32.value,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn when_turns_slowly_in_submenu_it_moves_by_step() {
+        let mut state = state_editing_f32();
+
+        for time in [0, 100, 200] {
+            reduce(Action::EncoderDown(time), &mut state);
+        }
+
+        assert_relative_eq!(value_f32(&state), 53.0);
+    }
+
+    #[test]
+    fn when_turns_quickly_in_submenu_it_multiplies_step_by_speed() {
+        let mut state = state_editing_f32();
+
+        for time in [0, 10, 20, 60] {
+            reduce(Action::EncoderDown(time), &mut state);
+        }
+
+        assert_relative_eq!(value_f32(&state), 50.0 + 1.0 + 10.0 + 10.0 + 3.0);
+    }
+
+    #[test]
+    fn when_turns_back_quickly_in_submenu_it_does_not_accelerate() {
+        let mut state = state_editing_f32();
+
+        reduce(Action::EncoderDown(0), &mut state);
+        let reaction = reduce(Action::EncoderUp(10), &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SetValue("a1", 50.0)));
+    }
+
+    #[test]
+    fn given_fine_editing_when_turns_quickly_in_submenu_it_moves_by_fraction_of_step() {
+        let mut state = state_editing_f32();
+
+        reduce(Action::DoubleClick, &mut state);
+        assert!(matches!(state.menu, Menu::Sub));
+        for time in [0, 10, 20] {
+            reduce(Action::EncoderUp(time), &mut state);
+        }
+        assert_relative_eq!(value_f32(&state), 49.7, epsilon = 0.0001);
+
+        reduce(Action::DoubleClick, &mut state);
+        reduce(Action::EncoderUp(30), &mut state);
+        assert_relative_eq!(value_f32(&state), 39.7, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn when_turns_while_pressed_in_submenu_it_edits_by_opposite_resolution() {
+        let mut state = state_editing_f32();
+
+        for time in [0, 10] {
+            reduce(Action::PressedDown(time), &mut state);
+        }
+        assert_relative_eq!(value_f32(&state), 50.2, epsilon = 0.0001);
+
+        reduce(Action::DoubleClick, &mut state);
+        for time in [100, 110] {
+            reduce(Action::PressedDown(time), &mut state);
+        }
+        assert_relative_eq!(value_f32(&state), 61.2, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn given_edited_attribute_when_long_presses_in_submenu_it_resets_it_to_default() {
+        let mut state = state_editing_f32();
+        reduce(Action::EncoderDown(0), &mut state);
+
+        let reaction = reduce(Action::LongPress, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SetValue("a1", 50.0)));
+        assert!(matches!(state.menu, Menu::Sub));
+        assert_eq!(reduce(Action::LongPress, &mut state), None);
+    }
+
+    #[test]
+    fn given_edited_select_when_long_presses_in_submenu_it_resets_it_to_default() {
+        use crate::state::*;
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_select(
+                ValueSelect::new(&["v1", "v2", "v3"])
+                    .unwrap()
+                    .with_selected(1),
+            )])
+            .unwrap();
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+
+        let reaction = reduce(Action::LongPress, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SelectValue("a1", "v2")));
+    }
+
+    #[test]
+    fn given_main_menu_when_double_clicks_it_acts_as_click() {
+        let mut state = state_editing_f32();
+        reduce(Action::EncoderClick, &mut state);
+
+        reduce(Action::DoubleClick, &mut state);
+
+        assert!(matches!(state.menu, Menu::Sub));
+    }
+
+    #[test]
+    fn when_editing_numeric_value_it_accepts_double_click() {
+        use crate::state::*;
+
+        let mut state = state_editing_f32();
+        assert!(accepts_double_click(&state));
+
+        reduce(Action::EncoderClick, &mut state);
+        assert!(!accepts_double_click(&state));
+
+        let mut state = State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("a1").with_value_select(ValueSelect::new(&["v1", "v2"]).unwrap())
+            ])
+            .unwrap();
+        reduce(Action::EncoderClick, &mut state);
+        assert!(matches!(state.menu, Menu::Sub));
+        assert!(!accepts_double_click(&state));
+    }
+
+    fn state_with_browser() -> State {
+        use crate::browser::*;
+        use crate::state::*;
+
+        let mut browser = Browser::new();
+        let drums = browser.push(Entry::directory("DRUMS", None)).unwrap();
+        browser.push(Entry::file("KICK.WAV", Some(drums))).unwrap();
+
+        State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_file(ValueFile::new())])
+            .unwrap()
+            .with_browser(browser)
+    }
+
+    #[test]
+    fn given_file_attribute_when_clicks_on_it_it_opens_browser() {
+        let mut state = state_with_browser();
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert!(reaction.is_none());
+        assert!(matches!(state.menu, Menu::Browser));
+    }
+
+    #[test]
+    fn given_browser_when_long_presses_it_closes_browser() {
+        let mut state = state_with_browser();
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+
+        let reaction = reduce(Action::LongPress, &mut state);
+
+        assert!(reaction.is_none());
+        assert!(matches!(state.menu, Menu::Main));
+    }
+
+    #[test]
+    fn given_browser_when_clicks_on_first_item_in_root_it_closes_browser() {
+        let mut state = state_with_browser();
+        reduce(Action::EncoderClick, &mut state);
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert!(reaction.is_none());
+        assert!(matches!(state.menu, Menu::Main));
+    }
+
+    #[test]
+    fn given_browser_when_clicks_on_file_in_directory_it_selects_the_file() {
+        let mut state = state_with_browser();
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        assert_eq!(state.browser.directory, Some(0));
+        reduce(Action::EncoderDown(0), &mut state);
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SelectFile("a1", 1)));
+        assert!(matches!(state.menu, Menu::Main));
+        if let Value::File(value_file) = &state.attributes[0].value {
+            assert_eq!(value_file.selected, Some(1));
+            assert_eq!(value_file.name.unwrap().as_str(), "KICK.WAV");
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn state_with_presets() -> State {
+        use crate::state::*;
+
+        State::new("Proton")
+            .with_attributes(&[Attribute::new("a1").with_value_preset(ValuePreset::new())])
+            .unwrap()
+    }
+
+    #[test]
+    fn given_presets_when_clicks_on_empty_slot_to_recall_it_stays_open() {
+        let mut state = state_with_presets();
+        reduce(Action::EncoderClick, &mut state);
+        assert!(matches!(state.menu, Menu::Presets));
+        for _ in 0..3 {
+            reduce(Action::EncoderDown(0), &mut state);
+        }
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert!(reaction.is_none());
+        assert!(matches!(state.menu, Menu::Presets));
+    }
+
+    #[test]
+    fn given_presets_when_saves_and_recalls_slot_it_reacts_and_closes_the_page() {
+        let mut state = state_with_presets();
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        for _ in 0..3 {
+            reduce(Action::EncoderDown(0), &mut state);
+        }
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::SavePreset(1)));
+        assert!(matches!(state.menu, Menu::Main));
+        assert!(state.presets.used[1]);
+        if let Value::Preset(value_preset) = &state.attributes[0].value {
+            assert_eq!(value_preset.slot, Some(1));
+        } else {
+            unreachable!();
+        }
+
+        reduce(Action::EncoderClick, &mut state);
+        reduce(Action::EncoderDown(0), &mut state);
+        reduce(Action::EncoderClick, &mut state);
+        for _ in 0..3 {
+            reduce(Action::EncoderDown(0), &mut state);
+        }
+
+        let reaction = reduce(Action::EncoderClick, &mut state);
+
+        assert_eq!(reaction, Some(Reaction::RecallPreset(1)));
+    }
 }
@@ -0,0 +1,400 @@
+//! Values of attributes saved as text, so they survive a power cycle.
+//!
+//! Each persistent attribute is stored on its own line as its name and
+//! value separated by `=`. Numbers are written in full precision, selects
+//! by the name of the option and files by their index in the browser
+//! followed by their name, e.g. `sample=3:LOOP.WAV`. Values are matched to
+//! attributes by name when restored, so attributes may be added, removed or
+//! reordered between versions of the firmware. Unknown names and options
+//! are skipped.
+//!
+//! Saving is debounced by [`Autosave`], so turning the encoder does not
+//! write to the SD card with every detent.
+
+use core::fmt::Write as _;
+
+use heapless::{String, Vec};
+
+use crate::browser::Browser;
+use crate::reaction::Reaction;
+use crate::state::{State, Value, ValueBool, ValueF32, ValueFile, ValueI32, ValueSelect};
+
+/// Maximum size of the snapshot in bytes.
+pub const SNAPSHOT_SIZE: usize = 2048;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    text: String<SNAPSHOT_SIZE>,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    Full,
+    Invalid,
+}
+
+impl Snapshot {
+    pub fn capture(state: &State) -> Result<Self, SnapshotError> {
+        let mut text = String::new();
+        for attribute in state.attributes.iter().filter(|a| a.persistent) {
+            let name = attribute.name;
+            let result = match &attribute.value {
+                Value::F32(value) => writeln!(text, "{}={}", name, value.value),
+                Value::Select(value) => {
+                    writeln!(text, "{}={}", name, value.available[value.selected])
+                }
+                Value::File(ValueFile {
+                    selected: Some(index),
+                    name: Some(file_name),
+                }) => writeln!(text, "{}={}:{}", name, index, file_name.as_str()),
+                Value::Bool(value) => writeln!(text, "{}={}", name, bool_to_str(value.value)),
+                Value::I32(value) => writeln!(text, "{}={}", name, value.value),
+                Value::File(_) | Value::Preset(_) | Value::Trigger => Ok(()),
+            };
+            result.map_err(|_| SnapshotError::Full)?;
+        }
+        Ok(Self { text })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let text = core::str::from_utf8(bytes).map_err(|_| SnapshotError::Invalid)?;
+        let mut snapshot = Self {
+            text: String::new(),
+        };
+        snapshot
+            .text
+            .push_str(text)
+            .map_err(|_| SnapshotError::Full)?;
+        Ok(snapshot)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.text.as_bytes()
+    }
+
+    /// Set values of attributes from the snapshot. Reactions to values that
+    /// changed are returned, so they can be passed to the instrument as if
+    /// the user set them.
+    pub fn restore(&self, state: &mut State) -> Vec<Reaction, 64> {
+        let mut reactions = Vec::new();
+        for line in self.text.lines() {
+            let (name, value) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let attribute = match state
+                .attributes
+                .iter_mut()
+                .find(|a| a.persistent && a.name == name)
+            {
+                Some(attribute) => attribute,
+                None => continue,
+            };
+            let name = attribute.name;
+            let reaction = match &mut attribute.value {
+                Value::F32(value_f32) => {
+                    restore_f32(value_f32, value).map(|v| Reaction::SetValue(name, v))
+                }
+                Value::Select(value_select) => {
+                    restore_select(value_select, value).map(|v| Reaction::SelectValue(name, v))
+                }
+                Value::File(value_file) => restore_file(value_file, value, &state.browser)
+                    .map(|i| Reaction::SelectFile(name, i)),
+                Value::Bool(value_bool) => {
+                    restore_bool(value_bool, value).map(|v| Reaction::SetBool(name, v))
+                }
+                Value::I32(value_i32) => {
+                    restore_i32(value_i32, value).map(|v| Reaction::SetI32(name, v))
+                }
+                Value::Preset(_) | Value::Trigger => None,
+            };
+            if let Some(reaction) = reaction {
+                let _ = reactions.push(reaction);
+            }
+        }
+        reactions
+    }
+}
+
+fn restore_f32(value_f32: &mut ValueF32, value: &str) -> Option<f32> {
+    let value = value
+        .parse::<f32>()
+        .ok()?
+        .clamp(value_f32.min, value_f32.max);
+    if value == value_f32.value {
+        return None;
+    }
+    value_f32.value = value;
+    Some(value)
+}
+
+// Switches are stored the same way as selects of "on" and "off", so an
+// attribute can turn from one into the other without losing its value.
+fn bool_to_str(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn restore_bool(value_bool: &mut ValueBool, value: &str) -> Option<bool> {
+    let value = match value {
+        "on" => true,
+        "off" => false,
+        _ => return None,
+    };
+    if value == value_bool.value {
+        return None;
+    }
+    value_bool.value = value;
+    Some(value)
+}
+
+fn restore_i32(value_i32: &mut ValueI32, value: &str) -> Option<i32> {
+    let value = value
+        .parse::<i32>()
+        .ok()?
+        .clamp(value_i32.min, value_i32.max);
+    if value == value_i32.value {
+        return None;
+    }
+    value_i32.value = value;
+    Some(value)
+}
+
+fn restore_select(value_select: &mut ValueSelect, value: &str) -> Option<&'static str> {
+    let index = value_select.available.iter().position(|a| *a == value)?;
+    if index == value_select.selected {
+        return None;
+    }
+    value_select.selected = index;
+    Some(value_select.available[index])
+}
+
+// The card may have changed since the snapshot was saved, so the file is
+// restored only if it is still found under the same index.
+fn restore_file(value_file: &mut ValueFile, value: &str, browser: &Browser) -> Option<usize> {
+    let (index, name) = value.split_once(':')?;
+    let index = index.parse::<usize>().ok()?;
+    let entry = browser.entries.get(index)?;
+    if entry.is_directory || entry.name.as_str() != name || value_file.selected == Some(index) {
+        return None;
+    }
+    *value_file = value_file.with_selected(index, browser);
+    Some(index)
+}
+
+/// Countdown postponed by every change, elapsing once the user stops
+/// tweaking values for a while.
+pub struct Autosave {
+    delay: u32,
+    countdown: Option<u32>,
+}
+
+impl Autosave {
+    /// The delay is given in number of ticks.
+    pub fn new(delay: u32) -> Self {
+        Self {
+            delay,
+            countdown: None,
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.countdown = Some(self.delay);
+    }
+
+    /// Touch only if the reaction changed a value stored in the snapshot.
+    /// Triggers and presets do not call for saving.
+    pub fn react(&mut self, reaction: &Reaction, state: &State) {
+        let name = match reaction {
+            Reaction::SetValue(name, _)
+            | Reaction::SelectValue(name, _)
+            | Reaction::SetBool(name, _)
+            | Reaction::SetI32(name, _)
+            | Reaction::SelectFile(name, _) => name,
+            Reaction::Trigger(_) | Reaction::SavePreset(_) | Reaction::RecallPreset(_) => return,
+        };
+        if state
+            .attributes
+            .iter()
+            .any(|a| a.persistent && a.name == *name)
+        {
+            self.touch();
+        }
+    }
+
+    /// Returns true once the delay since the last change has passed.
+    pub fn tick(&mut self) -> bool {
+        match self.countdown {
+            Some(0) => {
+                self.countdown = None;
+                true
+            }
+            Some(countdown) => {
+                self.countdown = Some(countdown - 1);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::Entry;
+    use crate::state::*;
+
+    fn state() -> State {
+        let mut browser = Browser::new();
+        browser.push(Entry::directory("drums", None)).unwrap();
+        browser.push(Entry::file("loop.wav", None)).unwrap();
+        browser.push(Entry::file("kick.wav", Some(0))).unwrap();
+        State::new("Proton")
+            .with_attributes(&[
+                Attribute::new("sample").with_value_file(ValueFile::new()),
+                Attribute::new("level").with_value_f32(ValueF32::new(0.5)),
+                Attribute::new("mode")
+                    .with_value_select(ValueSelect::new(&["a", "b", "c"]).unwrap()),
+                Attribute::new("marker")
+                    .with_value_f32(ValueF32::new(0.0))
+                    .with_persistent(false),
+            ])
+            .unwrap()
+            .with_browser(browser)
+    }
+
+    #[test]
+    fn when_restored_it_sets_values_and_reacts_to_changes() {
+        let mut original = state();
+        original.attributes[0].value =
+            Value::File(ValueFile::new().with_selected(2, &original.browser));
+        original.attributes[1].value = Value::F32(ValueF32::new(0.123_456));
+        original.attributes[2].value =
+            Value::Select(ValueSelect::new(&["a", "b", "c"]).unwrap().with_selected(2));
+        let snapshot = Snapshot::capture(&original).unwrap();
+
+        let mut restored = state();
+        let reactions = Snapshot::from_bytes(snapshot.as_bytes())
+            .unwrap()
+            .restore(&mut restored);
+
+        assert_eq!(
+            reactions.as_slice(),
+            &[
+                Reaction::SelectFile("sample", 2),
+                Reaction::SetValue("level", 0.123_456),
+                Reaction::SelectValue("mode", "c"),
+            ]
+        );
+        assert!(matches!(
+            restored.attributes[2].value,
+            Value::Select(ValueSelect { selected: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn when_restored_switches_and_integers_it_reacts_to_changes() {
+        let attributes = [
+            Attribute::new("play").with_value_bool(ValueBool::new(true)),
+            Attribute::new("steps").with_value_i32(ValueI32::new(4).with_max(16)),
+            Attribute::new("reset").with_value_trigger(),
+        ];
+        let mut original = State::new("Proton").with_attributes(&attributes).unwrap();
+        original.attributes[0].value = Value::Bool(ValueBool::new(false));
+        original.attributes[1].value = Value::I32(ValueI32::new(12));
+        let snapshot = Snapshot::capture(&original).unwrap();
+        assert_eq!(snapshot.as_bytes(), b"play=off\nsteps=12\n");
+
+        let mut restored = State::new("Proton").with_attributes(&attributes).unwrap();
+        let reactions = Snapshot::from_bytes(b"play=off\nsteps=99\n")
+            .unwrap()
+            .restore(&mut restored);
+
+        assert_eq!(
+            reactions.as_slice(),
+            &[
+                Reaction::SetBool("play", false),
+                Reaction::SetI32("steps", 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn when_attribute_is_not_persistent_it_is_left_out() {
+        let mut state = state();
+        state.attributes[3].value = Value::F32(ValueF32::new(0.7));
+
+        let snapshot = Snapshot::capture(&state).unwrap();
+
+        let text = core::str::from_utf8(snapshot.as_bytes()).unwrap();
+        assert!(!text.contains("marker"));
+        assert!(text.contains("level=0.5\n"));
+        assert!(!text.contains("sample"));
+    }
+
+    #[test]
+    fn when_snapshot_does_not_match_attributes_it_skips_them() {
+        let mut state = state();
+        let snapshot =
+            Snapshot::from_bytes(b"unknown=1\nmode=z\nlevel=7\nmarker=0.3\nsample=0:drums\nbroken")
+                .unwrap();
+
+        let reactions = snapshot.restore(&mut state);
+
+        // The level is clamped to its maximum
+        assert_eq!(reactions.as_slice(), &[Reaction::SetValue("level", 1.0)]);
+        assert!(matches!(
+            state.attributes[3].value,
+            Value::F32(ValueF32 { value, .. }) if value == 0.0
+        ));
+    }
+
+    #[test]
+    fn when_file_moved_in_browser_it_is_not_restored() {
+        let mut state = state();
+        let snapshot = Snapshot::from_bytes(b"sample=1:kick.wav\n").unwrap();
+
+        let reactions = snapshot.restore(&mut state);
+
+        assert!(reactions.is_empty());
+    }
+
+    #[test]
+    fn when_changes_keep_coming_autosave_waits_for_them_to_stop() {
+        let mut autosave = Autosave::new(3);
+        assert!(!autosave.tick());
+
+        autosave.touch();
+        assert!(!autosave.tick());
+        assert!(!autosave.tick());
+        autosave.touch();
+        assert!(!autosave.tick());
+        assert!(!autosave.tick());
+        assert!(!autosave.tick());
+        assert!(autosave.tick());
+        assert!(!autosave.tick());
+    }
+
+    #[test]
+    fn when_reaction_does_not_change_snapshot_autosave_is_not_touched() {
+        let state = state();
+        let mut autosave = Autosave::new(0);
+
+        autosave.react(&Reaction::SetValue("marker", 0.5), &state);
+        autosave.react(&Reaction::Trigger("restart"), &state);
+        autosave.react(&Reaction::RecallPreset(1), &state);
+        autosave.react(&Reaction::SavePreset(1), &state);
+        assert!(!autosave.tick());
+
+        autosave.react(&Reaction::SetValue("level", 0.5), &state);
+        assert!(autosave.tick());
+        autosave.react(&Reaction::SelectValue("mode", "b"), &state);
+        assert!(autosave.tick());
+        autosave.react(&Reaction::SelectFile("sample", 2), &state);
+        assert!(autosave.tick());
+    }
+}
@@ -2,12 +2,25 @@ use core::fmt;
 
 use heapless::Vec;
 
+use crate::browser::{Browser, FileName};
+use crate::presets::Presets;
+
+/// Maximum number of pages nested in each other.
+pub const MAX_PAGE_DEPTH: usize = 4;
+
 #[derive(Clone, Debug)]
 pub struct State {
     pub title: &'static str,
     pub attributes: Vec<Attribute, 64>,
+    pub pages: Vec<Page, 16>,
+    /// Page currently open, `None` for the root.
+    pub page: Option<&'static str>,
+    /// Index of the selected row of the current page, see [`State::row`].
     pub selected_attribute: usize,
     pub menu: Menu,
+    pub browser: Browser,
+    pub presets: Presets,
+    pub encoder: Encoder,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -15,12 +28,25 @@ pub struct State {
 pub enum Menu {
     Main,
     Sub,
+    Browser,
+    Presets,
+}
+
+/// Turns of the encoder edit values faster when they come in quick
+/// succession, unless the fine editing is toggled on.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Encoder {
+    /// Time of the last detent and whether it was turned up.
+    pub last_detent: Option<(u32, bool)>,
+    pub fine: bool,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug)]
 pub enum StateError {
     AttributesFull,
+    PagesFull,
 }
 
 #[cfg(feature = "defmt")]
@@ -39,8 +65,13 @@ impl State {
         Self {
             title,
             attributes: Vec::new(),
+            pages: Vec::new(),
+            page: None,
             selected_attribute: 0,
             menu: Menu::Main,
+            browser: Browser::new(),
+            presets: Presets::new(),
+            encoder: Encoder::default(),
         }
     }
 
@@ -51,10 +82,103 @@ impl State {
         Ok(self)
     }
 
+    /// Attributes are assigned to pages by [`Attribute::with_page`].
+    pub fn with_pages(mut self, pages: &[Page]) -> Result<Self, StateError> {
+        self.pages
+            .extend_from_slice(pages)
+            .map_err(|_| StateError::PagesFull)?;
+        Ok(self)
+    }
+
     pub fn with_selected_attribute(mut self, selected_attribute: usize) -> Self {
         self.selected_attribute = selected_attribute;
         self
     }
+
+    /// Files available to attributes with [`Value::File`]. The browser is
+    /// shared by all of them.
+    pub fn with_browser(mut self, browser: Browser) -> Self {
+        self.browser = browser;
+        self
+    }
+}
+
+impl State {
+    /// Rows listed on the current page. The way back to the parent page
+    /// comes first, followed by attributes and nested pages.
+    pub fn rows(&self) -> impl Iterator<Item = Row> + '_ {
+        let back = self.page.map(|_| Row::Back);
+        let attributes = self
+            .attributes
+            .iter()
+            .enumerate()
+            .filter(move |(_, a)| a.page == self.page)
+            .map(|(i, _)| Row::Attribute(i));
+        let pages = self
+            .pages
+            .iter()
+            .filter(move |p| p.parent == self.page)
+            .map(|p| Row::Page(p.name));
+        back.into_iter().chain(attributes).chain(pages)
+    }
+
+    pub fn row(&self, index: usize) -> Option<Row> {
+        self.rows().nth(index)
+    }
+
+    /// Index of the attribute in the selected row, if there is one.
+    pub fn selected_attribute_index(&self) -> Option<usize> {
+        match self.row(self.selected_attribute) {
+            Some(Row::Attribute(index)) => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Names of pages leading from the root to the current one.
+    pub fn path(&self) -> Vec<&'static str, MAX_PAGE_DEPTH> {
+        let mut path = Vec::new();
+        let mut page = self.page;
+        while let Some(name) = page {
+            if path.push(name).is_err() {
+                break;
+            }
+            page = self
+                .pages
+                .iter()
+                .find(|p| p.name == name)
+                .and_then(|p| p.parent);
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Group of attributes listed together, possibly nested in another page.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Page {
+    pub name: &'static str,
+    pub parent: Option<&'static str>,
+}
+
+impl Page {
+    pub fn new(name: &'static str) -> Self {
+        Self { name, parent: None }
+    }
+
+    pub fn with_parent(mut self, parent: &'static str) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Row {
+    Back,
+    /// Index of the attribute in the list of all attributes.
+    Attribute(usize),
+    Page(&'static str),
 }
 
 #[derive(Clone, Debug)]
@@ -62,6 +186,9 @@ impl State {
 pub struct Attribute {
     pub name: &'static str,
     pub value: Value,
+    pub persistent: bool,
+    /// Page the attribute is listed on, `None` for the root.
+    pub page: Option<&'static str>,
 }
 
 impl Attribute {
@@ -69,9 +196,23 @@ impl Attribute {
         Attribute {
             name,
             value: Value::F32(ValueF32::new(0.0)),
+            persistent: true,
+            page: None,
         }
     }
 
+    pub fn with_page(mut self, page: &'static str) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    /// Keep the value out of saved snapshots, e.g. when the instrument
+    /// stores it on its own.
+    pub fn with_persistent(mut self, persistent: bool) -> Self {
+        self.persistent = persistent;
+        self
+    }
+
     pub fn with_value_f32(mut self, value_f32: ValueF32) -> Self {
         self.value = Value::F32(value_f32);
         self
@@ -81,6 +222,33 @@ impl Attribute {
         self.value = Value::Select(value_select);
         self
     }
+
+    pub fn with_value_file(mut self, value_file: ValueFile) -> Self {
+        self.value = Value::File(value_file);
+        self
+    }
+
+    pub fn with_value_preset(mut self, value_preset: ValuePreset) -> Self {
+        self.value = Value::Preset(value_preset);
+        self
+    }
+
+    pub fn with_value_bool(mut self, value_bool: ValueBool) -> Self {
+        self.value = Value::Bool(value_bool);
+        self
+    }
+
+    pub fn with_value_i32(mut self, value_i32: ValueI32) -> Self {
+        self.value = Value::I32(value_i32);
+        self
+    }
+
+    /// Triggers are not persistent, they carry no value to restore.
+    pub fn with_value_trigger(mut self) -> Self {
+        self.value = Value::Trigger;
+        self.persistent = false;
+        self
+    }
 }
 
 #[allow(clippy::large_enum_variant)] // TODO: Use Box instead
@@ -89,12 +257,19 @@ impl Attribute {
 pub enum Value {
     Select(ValueSelect),
     F32(ValueF32),
+    File(ValueFile),
+    Preset(ValuePreset),
+    Bool(ValueBool),
+    I32(ValueI32),
+    /// Action fired by a click, without entering the edit mode.
+    Trigger,
 }
 
 #[derive(Clone, Debug)]
 pub struct ValueSelect {
-    pub available: Vec<&'static str, 12>,
+    pub available: Vec<&'static str, 16>,
     pub selected: usize,
+    pub default: usize,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -115,6 +290,7 @@ impl ValueSelect {
         let mut value_select = Self {
             available: Vec::new(),
             selected: 0,
+            default: 0,
         };
         value_select
             .available
@@ -123,8 +299,97 @@ impl ValueSelect {
         Ok(value_select)
     }
 
+    /// The initially selected option is also the default.
     pub fn with_selected(mut self, selected: usize) -> Self {
         self.selected = selected;
+        self.default = selected;
+        self
+    }
+}
+
+/// File chosen in the browser, pointing to its entry.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ValueFile {
+    pub selected: Option<usize>,
+    pub name: Option<FileName>,
+}
+
+impl ValueFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_selected(mut self, selected: usize, browser: &Browser) -> Self {
+        self.selected = Some(selected);
+        self.name = Some(browser.entries[selected].name);
+        self
+    }
+}
+
+/// Entry to the page of presets, showing the slot used last.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ValuePreset {
+    pub slot: Option<usize>,
+}
+
+impl ValuePreset {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Switch toggled by a click, without entering the edit mode.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ValueBool {
+    pub value: bool,
+    pub default: bool,
+}
+
+impl ValueBool {
+    pub fn new(value: bool) -> Self {
+        Self {
+            value,
+            default: value,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug)]
+pub struct ValueI32 {
+    pub value: i32,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+impl ValueI32 {
+    pub fn new(value: i32) -> Self {
+        Self {
+            value,
+            default: value,
+            min: 0,
+            max: 100,
+            step: 1,
+        }
+    }
+
+    pub fn with_min(mut self, min: i32) -> Self {
+        self.min = min;
+        self
+    }
+
+    pub fn with_max(mut self, max: i32) -> Self {
+        self.max = max;
+        self
+    }
+
+    pub fn with_step(mut self, step: i32) -> Self {
+        self.step = step;
         self
     }
 }
@@ -132,6 +397,7 @@ impl ValueSelect {
 #[derive(Copy, Clone)]
 pub struct ValueF32 {
     pub value: f32,
+    pub default: f32,
     pub min: f32,
     pub max: f32,
     pub step: f32,
@@ -171,6 +437,7 @@ impl ValueF32 {
     pub fn new(value: f32) -> Self {
         Self {
             value,
+            default: value,
             min: 0.0,
             max: 1.0,
             step: 0.01,
@@ -180,8 +447,10 @@ impl ValueF32 {
 }
 
 impl ValueF32 {
+    /// The initial value is also the default.
     pub fn with_value(mut self, value: f32) -> Self {
         self.value = value;
+        self.default = value;
         self
     }
 
@@ -3,7 +3,9 @@ use micromath::F32Ext;
 
 use core::fmt;
 
-use super::state;
+use super::browser::{Browser, FileName};
+use super::presets::{Presets, PresetsItem};
+use super::state::{self, Row, MAX_PAGE_DEPTH};
 
 const ATTRIBUTES_CAPACITY: usize = 4;
 
@@ -11,25 +13,59 @@ const ATTRIBUTES_CAPACITY: usize = 4;
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct View {
     pub title: &'static str,
+    /// Pages leading from the root to the current one.
+    pub path: [Option<&'static str>; MAX_PAGE_DEPTH],
     pub attributes: [Option<Attribute>; ATTRIBUTES_CAPACITY],
     pub selected_attribute: usize,
     pub edit: bool,
+    pub fine: bool,
+    pub browser: Option<BrowserView>,
+    pub presets: Option<PresetsView>,
 }
 
 impl From<&state::State> for View {
     fn from(other: &state::State) -> Self {
         let attribute_page = selected_attribute_to_page(other.selected_attribute);
         let first_index = attribute_page * ATTRIBUTES_CAPACITY;
+        let attribute = |i: usize| {
+            other.row(i).map(|row| match row {
+                Row::Back => Attribute {
+                    name: "..",
+                    value: Value::Str(""),
+                },
+                Row::Attribute(index) => (&other.attributes[index]).into(),
+                Row::Page(name) => Attribute {
+                    name,
+                    value: Value::Page,
+                },
+            })
+        };
+        let mut path = [None; MAX_PAGE_DEPTH];
+        for (slot, name) in path.iter_mut().zip(other.path()) {
+            *slot = Some(name);
+        }
         Self {
             title: other.title,
+            path,
             attributes: [
-                other.attributes.get(first_index).map(|a| a.into()),
-                other.attributes.get(first_index + 1).map(|a| a.into()),
-                other.attributes.get(first_index + 2).map(|a| a.into()),
-                other.attributes.get(first_index + 3).map(|a| a.into()),
+                attribute(first_index),
+                attribute(first_index + 1),
+                attribute(first_index + 2),
+                attribute(first_index + 3),
             ],
             selected_attribute: other.selected_attribute % 4,
             edit: matches!(other.menu, state::Menu::Sub),
+            fine: other.encoder.fine,
+            browser: if matches!(other.menu, state::Menu::Browser) {
+                Some((&other.browser).into())
+            } else {
+                None
+            },
+            presets: if matches!(other.menu, state::Menu::Presets) {
+                Some((&other.presets).into())
+            } else {
+                None
+            },
         }
     }
 }
@@ -38,6 +74,75 @@ fn selected_attribute_to_page(selected_attribute: usize) -> usize {
     (selected_attribute as f32 / ATTRIBUTES_CAPACITY as f32).floor() as usize
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BrowserView {
+    pub directory: Option<FileName>,
+    pub items: [Option<BrowserItem>; ATTRIBUTES_CAPACITY],
+    pub selected: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BrowserItem {
+    Back,
+    Directory(FileName),
+    File(FileName),
+}
+
+impl From<&Browser> for BrowserView {
+    fn from(other: &Browser) -> Self {
+        let page = selected_attribute_to_page(other.selected);
+        let first_index = page * ATTRIBUTES_CAPACITY;
+        let item = |i: usize| {
+            if i == 0 {
+                return Some(BrowserItem::Back);
+            }
+            other.listing().nth(i - 1).map(|index| {
+                let entry = &other.entries[index];
+                if entry.is_directory {
+                    BrowserItem::Directory(entry.name)
+                } else {
+                    BrowserItem::File(entry.name)
+                }
+            })
+        };
+        Self {
+            directory: other.directory.map(|index| other.entries[index].name),
+            items: [
+                item(first_index),
+                item(first_index + 1),
+                item(first_index + 2),
+                item(first_index + 3),
+            ],
+            selected: other.selected % ATTRIBUTES_CAPACITY,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PresetsView {
+    pub items: [Option<PresetsItem>; ATTRIBUTES_CAPACITY],
+    pub selected: usize,
+}
+
+impl From<&Presets> for PresetsView {
+    fn from(other: &Presets) -> Self {
+        let page = selected_attribute_to_page(other.selected);
+        let first_index = page * ATTRIBUTES_CAPACITY;
+        Self {
+            items: [
+                other.item(first_index),
+                other.item(first_index + 1),
+                other.item(first_index + 2),
+                other.item(first_index + 3),
+            ],
+            selected: other.selected % ATTRIBUTES_CAPACITY,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Attribute {
@@ -59,6 +164,13 @@ impl From<&state::Attribute> for Attribute {
 pub enum Value {
     Str(&'static str),
     F32(fn(&mut dyn fmt::Write, f32), f32),
+    File(Option<FileName>),
+    Preset(Option<usize>),
+    Bool(bool),
+    I32(i32),
+    Trigger,
+    /// Nested page, entered by a click.
+    Page,
 }
 
 impl From<&state::Value> for Value {
@@ -68,6 +180,11 @@ impl From<&state::Value> for Value {
                 Value::Str(value_select.available[value_select.selected])
             }
             state::Value::F32(value_f32) => Value::F32(value_f32.writter, value_f32.value),
+            state::Value::File(value_file) => Value::File(value_file.name),
+            state::Value::Preset(value_preset) => Value::Preset(value_preset.slot),
+            state::Value::Bool(value_bool) => Value::Bool(value_bool.value),
+            state::Value::I32(value_i32) => Value::I32(value_i32.value),
+            state::Value::Trigger => Value::Trigger,
         }
     }
 }
@@ -77,6 +194,12 @@ impl fmt::Debug for Value {
         match self {
             Self::Str(value) => write!(fmt, "Value::Str({})", value),
             Self::F32(_, value) => write!(fmt, "Value::F32({})", value),
+            Self::File(value) => write!(fmt, "Value::File({:?})", value),
+            Self::Preset(value) => write!(fmt, "Value::Preset({:?})", value),
+            Self::Bool(value) => write!(fmt, "Value::Bool({})", value),
+            Self::I32(value) => write!(fmt, "Value::I32({})", value),
+            Self::Trigger => write!(fmt, "Value::Trigger"),
+            Self::Page => write!(fmt, "Value::Page"),
         }
     }
 }
@@ -87,6 +210,12 @@ impl defmt::Format for Value {
         match self {
             Self::Str(value) => defmt::write!(fmt, "Value::Str({})", value),
             Self::F32(_, value) => defmt::write!(fmt, "Value::F32({})", value),
+            Self::File(value) => defmt::write!(fmt, "Value::File({:?})", value),
+            Self::Preset(value) => defmt::write!(fmt, "Value::Preset({:?})", value),
+            Self::Bool(value) => defmt::write!(fmt, "Value::Bool({})", value),
+            Self::I32(value) => defmt::write!(fmt, "Value::I32({})", value),
+            Self::Trigger => defmt::write!(fmt, "Value::Trigger"),
+            Self::Page => defmt::write!(fmt, "Value::Page"),
         }
     }
 }
@@ -96,6 +225,11 @@ impl PartialEq for Value {
         match (self, other) {
             (Self::Str(a), Self::Str(b)) => a == b,
             (Self::F32(_, a), Self::F32(_, b)) => a == b,
+            (Self::File(a), Self::File(b)) => a == b,
+            (Self::Preset(a), Self::Preset(b)) => a == b,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::I32(a), Self::I32(b)) => a == b,
+            (Self::Trigger, Self::Trigger) | (Self::Page, Self::Page) => true,
             _ => false,
         }
     }
@@ -132,6 +266,7 @@ mod tests {
 
         let expected_view = View {
             title: "Title",
+            path: [None; MAX_PAGE_DEPTH],
             attributes: [
                 Some(Attribute {
                     name: "a5",
@@ -146,9 +281,138 @@ mod tests {
             ],
             selected_attribute: 1,
             edit: false,
+            fine: false,
+            browser: None,
+            presets: None,
         };
 
         let actual_view: View = (&source_state).into();
         assert_eq!(actual_view, expected_view);
     }
+
+    #[test]
+    fn given_switch_integer_and_trigger_when_converted_into_view_it_passes_their_values() {
+        use crate::state;
+
+        let source_state = state::State::new("Title")
+            .with_attributes(&[
+                state::Attribute::new("a1").with_value_bool(state::ValueBool::new(true)),
+                state::Attribute::new("a2").with_value_i32(state::ValueI32::new(-3).with_min(-5)),
+                state::Attribute::new("a3").with_value_trigger(),
+            ])
+            .unwrap();
+
+        let actual_view: View = (&source_state).into();
+
+        assert_eq!(
+            actual_view.attributes.map(|a| a.map(|a| a.value)),
+            [
+                Some(Value::Bool(true)),
+                Some(Value::I32(-3)),
+                Some(Value::Trigger),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn given_open_page_when_converted_into_view_it_lists_its_rows_and_path() {
+        use crate::state;
+
+        let mut source_state = state::State::new("Title")
+            .with_attributes(&[
+                state::Attribute::new("a1"),
+                state::Attribute::new("a2")
+                    .with_value_select(state::ValueSelect::new(&["v1"]).unwrap())
+                    .with_page("p2"),
+            ])
+            .unwrap()
+            .with_pages(&[
+                state::Page::new("p1"),
+                state::Page::new("p2").with_parent("p1"),
+                state::Page::new("p3").with_parent("p2"),
+            ])
+            .unwrap();
+        source_state.page = Some("p2");
+
+        let actual_view: View = (&source_state).into();
+
+        assert_eq!(actual_view.path, [Some("p1"), Some("p2"), None, None]);
+        assert_eq!(
+            actual_view.attributes,
+            [
+                Some(Attribute {
+                    name: "..",
+                    value: Value::Str(""),
+                }),
+                Some(Attribute {
+                    name: "a2",
+                    value: Value::Str("v1"),
+                }),
+                Some(Attribute {
+                    name: "p3",
+                    value: Value::Page,
+                }),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn given_open_browser_when_converted_into_view_it_lists_current_directory() {
+        use crate::browser::*;
+        use crate::state;
+
+        let mut browser = Browser::new();
+        let drums = browser.push(Entry::directory("DRUMS", None)).unwrap();
+        browser.push(Entry::file("KICK.WAV", Some(drums))).unwrap();
+        browser.push(Entry::file("PROJECT.WAV", None)).unwrap();
+        browser.enter(drums);
+        browser.select_next();
+        let mut source_state = state::State::new("Title").with_browser(browser);
+        source_state.menu = state::Menu::Browser;
+
+        let actual_view: View = (&source_state).into();
+
+        assert_eq!(
+            actual_view.browser,
+            Some(BrowserView {
+                directory: Some(FileName::new("DRUMS")),
+                items: [
+                    Some(BrowserItem::Back),
+                    Some(BrowserItem::File(FileName::new("KICK.WAV"))),
+                    None,
+                    None,
+                ],
+                selected: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn given_open_presets_when_converted_into_view_it_lists_page_of_selected_row() {
+        use crate::state;
+
+        let mut source_state = state::State::new("Title");
+        source_state.menu = state::Menu::Presets;
+        source_state.presets.used[2] = true;
+        for _ in 0..5 {
+            source_state.presets.select_next();
+        }
+
+        let actual_view: View = (&source_state).into();
+
+        assert_eq!(
+            actual_view.presets,
+            Some(PresetsView {
+                items: [
+                    Some(PresetsItem::Slot(1, false)),
+                    Some(PresetsItem::Slot(2, true)),
+                    Some(PresetsItem::Slot(3, false)),
+                    Some(PresetsItem::Slot(4, false)),
+                ],
+                selected: 1,
+            })
+        );
+    }
 }